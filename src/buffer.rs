@@ -0,0 +1,266 @@
+// Buffer.rs - Rope backed storage for the text of a document
+use crate::Row;
+use ropey::{Rope, RopeSlice};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+use tree_sitter::{InputEdit, Point};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Buffer struct to hold the text of a document
+#[derive(Debug)]
+pub struct Buffer {
+    rope: Rope,                         // For holding the lines, joined by newlines
+    snapshot: RefCell<Option<Rc<str>>>, // For caching the joined text between edits
+    edited: Option<usize>,              // The first line edited since it was last checked
+    edits: Option<Vec<InputEdit>>,      // The changes since they were last taken, None if all new
+    pub tab_width: usize,               // The distance between tab stops when lining up text
+}
+
+// Add methods to the Buffer struct / class
+impl Buffer {
+    pub fn new(text: &str, tab_width: usize) -> Self {
+        // Initialise a buffer from a string
+        Self {
+            rope: Rope::from_str(text),
+            snapshot: RefCell::new(None),
+            edited: Some(0),
+            edits: None,
+            tab_width,
+        }
+    }
+    pub fn len_lines(&self) -> usize {
+        // Get the amount of lines in the buffer
+        self.rope.len_lines()
+    }
+    pub fn line(&self, y: usize) -> Row<'_> {
+        // Get a view of a line in the buffer
+        Row::new(self.slice(y), self.tab_width)
+    }
+    pub fn get(&self, y: usize) -> Option<Row<'_>> {
+        // Get a view of a line in the buffer if it exists
+        if y < self.len_lines() {
+            Some(self.line(y))
+        } else {
+            None
+        }
+    }
+    pub fn lines(&self) -> impl Iterator<Item = Row<'_>> {
+        // Iterate through every line in the buffer
+        (0..self.len_lines()).map(move |y| self.line(y))
+    }
+    pub fn text(&self) -> Rc<str> {
+        // Get the entire text, only joining the rope after it has been edited
+        let mut snapshot = self.snapshot.borrow_mut();
+        if let Some(text) = &*snapshot {
+            return Rc::clone(text);
+        }
+        let text: Rc<str> = Rc::from(String::from(&self.rope));
+        *snapshot = Some(Rc::clone(&text));
+        text
+    }
+    pub fn line_of_byte(&self, byte: usize) -> usize {
+        // Find which line a byte index into the text lies on
+        self.rope.byte_to_line(byte)
+    }
+    pub fn line_to_byte(&self, y: usize) -> usize {
+        // Find the byte index of the start of a line
+        self.rope.line_to_byte(y)
+    }
+    pub fn line_end(&self, y: usize) -> usize {
+        // Find the byte index of the end of a line, before its newline
+        let y = y.min(self.len_lines().saturating_sub(1));
+        self.rope.line_to_byte(y) + self.slice(y).len_bytes()
+    }
+    pub fn len_bytes(&self) -> usize {
+        // Get the length of the text in bytes
+        self.rope.len_bytes()
+    }
+    pub fn slice_bytes(&self, start: usize, end: usize) -> Cow<'_, str> {
+        // Get the text between two bytes, only copying it if it is fragmented
+        self.rope.byte_slice(start..end).into()
+    }
+    pub fn chunks(&self, start: usize, end: usize) -> impl Iterator<Item = &str> {
+        // Go through the text between two bytes, in the pieces it is stored in
+        self.rope.byte_slice(start..end).chunks()
+    }
+    pub fn chunk_at(&self, byte: usize) -> &str {
+        // Get the text from a byte to the end of the piece it is stored in
+        if byte >= self.rope.len_bytes() {
+            return "";
+        }
+        let (chunk, start, _, _) = self.rope.chunk_at_byte(byte);
+        &chunk[byte - start..]
+    }
+    pub fn char_at_byte(&self, byte: usize) -> Option<char> {
+        // Get the character that starts at a byte
+        if byte < self.rope.len_bytes() {
+            Some(self.rope.char(self.rope.byte_to_char(byte)))
+        } else {
+            None
+        }
+    }
+    pub fn insert(&mut self, ch: char, x: usize, y: usize) {
+        // Insert a character
+        let index = self.char_index(x, y);
+        self.insert_at(index, ch.encode_utf8(&mut [0; 4]));
+        self.invalidate(y);
+    }
+    pub fn insert_str(&mut self, text: &str, x: usize, y: usize) {
+        // Insert a string, which may span several lines
+        let index = self.char_index(x, y);
+        self.insert_at(index, text);
+        self.invalidate(y);
+    }
+    pub fn delete(&mut self, x: usize, y: usize) -> Option<char> {
+        // Remove a character
+        let index = self.char_index(x, y);
+        let (result, length) = match self.line(y).chars().get(x) {
+            Some(c) => (c.parse().ok(), c.chars().count()),
+            None => (None, 0),
+        };
+        self.remove_at(index..index + length);
+        self.invalidate(y);
+        result
+    }
+    pub fn range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        // Get the text between two grapheme positions
+        let start = self.char_index(start.0, start.1);
+        let end = self.char_index(end.0, end.1);
+        self.rope.slice(start..end).to_string()
+    }
+    pub fn remove_range(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        // Remove the text between two grapheme positions and return it
+        let y = start.1;
+        let start = self.char_index(start.0, start.1);
+        let end = self.char_index(end.0, end.1);
+        let removed = self.rope.slice(start..end).to_string();
+        self.remove_at(start..end);
+        self.invalidate(y);
+        removed
+    }
+    pub fn insert_line(&mut self, y: usize, text: &str) {
+        // Insert a new line before the line at y
+        if y >= self.len_lines() {
+            let end = self.rope.len_chars();
+            self.insert_at(end, &format!("\n{text}"));
+        } else {
+            let start = self.rope.line_to_char(y);
+            self.insert_at(start, &format!("{text}\n"));
+        }
+        self.invalidate(y);
+    }
+    pub fn remove_line(&mut self, y: usize) {
+        // Remove an entire line, along with its newline
+        if y + 1 < self.len_lines() {
+            let start = self.rope.line_to_char(y);
+            let end = self.rope.line_to_char(y + 1);
+            self.remove_at(start..end);
+        } else if y > 0 {
+            let start = self.rope.line_to_char(y) - 1;
+            self.remove_at(start..self.rope.len_chars());
+        } else {
+            self.remove_at(0..self.rope.len_chars());
+        }
+        self.invalidate(y.saturating_sub(1));
+    }
+    pub fn set_line(&mut self, y: usize, text: &str) {
+        // Replace the contents of a line
+        let start = self.rope.line_to_char(y);
+        let end = start + self.slice(y).len_chars();
+        self.remove_at(start..end);
+        self.insert_at(start, text);
+        self.invalidate(y);
+    }
+    pub fn split_line(&mut self, x: usize, y: usize) {
+        // Break a line in two at a grapheme index
+        self.insert('\n', x, y);
+    }
+    pub fn join_line(&mut self, y: usize) {
+        // Join a line with the line below it
+        if y + 1 < self.len_lines() {
+            let newline = self.rope.line_to_char(y + 1) - 1;
+            self.remove_at(newline..newline + 1);
+            self.invalidate(y);
+        }
+    }
+    fn slice(&self, y: usize) -> RopeSlice<'_> {
+        // Get a line without its newline character
+        let line = self.rope.line(y);
+        let length = line.len_chars();
+        if length > 0 && line.char(length - 1) == '\n' {
+            line.slice(..length - 1)
+        } else {
+            line
+        }
+    }
+    fn char_index(&self, x: usize, y: usize) -> usize {
+        // Convert a grapheme index on a line to a character index in the rope
+        let line: Cow<str> = self.slice(y).into();
+        let chars: usize = line
+            .graphemes(true)
+            .take(x)
+            .map(|g| g.chars().count())
+            .sum();
+        self.rope.line_to_char(y) + chars
+    }
+    pub fn take_edited(&mut self) -> Option<usize> {
+        // Find the first line edited since this was last called
+        self.edited.take()
+    }
+    pub fn take_edits(&mut self) -> Option<Vec<InputEdit>> {
+        // Get the changes made since this was last called, or None if the whole text is new
+        self.edits.replace(vec![])
+    }
+    fn insert_at(&mut self, index: usize, text: &str) {
+        // Insert text at a character index, noting down the change
+        let start = self.rope.char_to_byte(index);
+        let position = self.point(start);
+        self.rope.insert(index, text);
+        let end = start + text.len();
+        self.record(InputEdit {
+            start_byte: start,
+            old_end_byte: start,
+            new_end_byte: end,
+            start_position: position,
+            old_end_position: position,
+            new_end_position: self.point(end),
+        });
+    }
+    fn remove_at(&mut self, range: Range<usize>) {
+        // Remove the text between two character indices, noting down the change
+        let start = self.rope.char_to_byte(range.start);
+        let end = self.rope.char_to_byte(range.end);
+        let position = self.point(start);
+        let old_end_position = self.point(end);
+        self.rope.remove(range);
+        self.record(InputEdit {
+            start_byte: start,
+            old_end_byte: end,
+            new_end_byte: start,
+            start_position: position,
+            old_end_position,
+            new_end_position: position,
+        });
+    }
+    fn record(&mut self, edit: InputEdit) {
+        // Note down a change for anything that keeps track of the text, such as a parser
+        if let Some(edits) = &mut self.edits {
+            edits.push(edit);
+        }
+    }
+    fn point(&self, byte: usize) -> Point {
+        // Find the line and the byte within the line of a byte
+        let row = self.rope.byte_to_line(byte);
+        Point {
+            row,
+            column: byte - self.rope.line_to_byte(row),
+        }
+    }
+    fn invalidate(&mut self, y: usize) {
+        // Throw away the cached text after an edit and note where the edit happened
+        *self.snapshot.get_mut() = None;
+        self.edited = Some(self.edited.map_or(y, |edited| edited.min(y)));
+    }
+}
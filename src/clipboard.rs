@@ -0,0 +1,67 @@
+// Clipboard.rs - For holding cut and copied text and syncing with the host
+use std::io::{Error, Write};
+use std::process::{Command, Stdio};
+
+// The amount of entries to remember in the kill ring
+const RING_SIZE: usize = 32;
+
+// Kill ring struct to hold recently cut and copied text
+#[derive(Debug, Default)]
+pub struct KillRing {
+    entries: Vec<String>, // For holding the entries, newest last
+}
+
+// Add methods to the kill ring struct / class
+impl KillRing {
+    pub fn push(&mut self, text: String) {
+        // Add some text to the kill ring, dropping the oldest entry when full
+        if self.top() != Some(&text) {
+            self.entries.push(text);
+        }
+        if self.entries.len() > RING_SIZE {
+            self.entries.remove(0);
+        }
+    }
+    pub fn top(&self) -> Option<&String> {
+        // Get the most recent entry in the kill ring
+        self.entries.last()
+    }
+    pub fn rotate(&mut self) {
+        // Bring the previous entry to the top of the kill ring
+        if let Some(entry) = self.entries.pop() {
+            self.entries.insert(0, entry);
+        }
+    }
+}
+
+pub fn copy_command(command: &str, text: &str) -> Result<(), Error> {
+    // Pipe text into an external clipboard command, such as xclip or wl-copy
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(Error::other("clipboard command failed"))
+    }
+}
+
+pub fn paste_command(command: &str) -> Result<String, Error> {
+    // Read text from an external clipboard command, such as xclip -o or wl-paste
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n"))
+    } else {
+        Err(Error::other("clipboard command failed"))
+    }
+}
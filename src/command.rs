@@ -0,0 +1,598 @@
+// Command.rs - For parsing and completing commands typed into the command line
+use crate::format::{Charset, LineEnding, CHARSETS, LINE_ENDINGS};
+use crate::pane::Split;
+use std::fs;
+use std::path::Path;
+use termion::event::Key;
+
+// Kinds of argument that a command can take
+#[derive(Debug, Clone, Copy)]
+pub enum Arg {
+    Number,                          // A whole number
+    Text,                            // Any text, quoted to include spaces
+    Path,                            // A path to a file
+    Choice(&'static [&'static str]), // One word out of a fixed set
+}
+
+// Describes a command that can be typed into the command line
+pub struct Spec {
+    pub name: &'static str,                   // The name to invoke it with
+    pub args: &'static [(&'static str, Arg)], // The name and kind of each argument
+    pub required: usize,                      // Amount of arguments needed
+}
+
+// The settings that can be changed with the set command
+pub const SETTINGS: &[&str] = &[
+    "tab_width",
+    "undo_period",
+    "line_number_padding_left",
+    "line_number_padding_right",
+];
+
+// The ways that the cursor can be moved with the move command
+pub const MOVEMENTS: &[&str] = &[
+    "up",
+    "down",
+    "left",
+    "right",
+    "home",
+    "end",
+    "page_up",
+    "page_down",
+];
+
+// Every command that the editor understands
+pub const COMMANDS: &[Spec] = &[
+    Spec {
+        name: "save",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "save_as",
+        args: &[("path", Arg::Path)],
+        required: 0,
+    },
+    Spec {
+        name: "save_all",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "new",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "open",
+        args: &[("path", Arg::Path)],
+        required: 0,
+    },
+    Spec {
+        name: "quit",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "search",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "replace",
+        args: &[("target", Arg::Text), ("with", Arg::Text)],
+        required: 0,
+    },
+    Spec {
+        name: "replace_all",
+        args: &[("target", Arg::Text), ("with", Arg::Text)],
+        required: 0,
+    },
+    Spec {
+        name: "undo",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "redo",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "copy",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "cut",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "paste",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "cycle_clipboard",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "tab",
+        args: &[("direction", Arg::Choice(&["next", "prev"]))],
+        required: 1,
+    },
+    Spec {
+        name: "split",
+        args: &[("direction", Arg::Choice(&["horizontal", "vertical"]))],
+        required: 1,
+    },
+    Spec {
+        name: "pane",
+        args: &[("direction", Arg::Choice(&["next", "prev"]))],
+        required: 1,
+    },
+    Spec {
+        name: "resize",
+        args: &[("change", Arg::Choice(&["grow", "shrink"]))],
+        required: 1,
+    },
+    Spec {
+        name: "close_pane",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "goto",
+        args: &[("line", Arg::Number)],
+        required: 1,
+    },
+    Spec {
+        name: "move",
+        args: &[("direction", Arg::Choice(MOVEMENTS))],
+        required: 1,
+    },
+    Spec {
+        name: "command",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "record",
+        args: &[("register", Arg::Text)],
+        required: 0,
+    },
+    Spec {
+        name: "play",
+        args: &[("register", Arg::Text), ("count", Arg::Number)],
+        required: 0,
+    },
+    Spec {
+        name: "export",
+        args: &[("register", Arg::Text)],
+        required: 0,
+    },
+    Spec {
+        name: "set",
+        args: &[("setting", Arg::Choice(SETTINGS)), ("value", Arg::Number)],
+        required: 2,
+    },
+    Spec {
+        name: "reload",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "edit_config",
+        args: &[],
+        required: 0,
+    },
+    Spec {
+        name: "indent_with",
+        args: &[("style", Arg::Choice(&["tabs", "spaces"]))],
+        required: 1,
+    },
+    Spec {
+        name: "line_ending",
+        args: &[("ending", Arg::Choice(LINE_ENDINGS))],
+        required: 1,
+    },
+    Spec {
+        name: "encoding",
+        args: &[("charset", Arg::Choice(CHARSETS))],
+        required: 1,
+    },
+];
+
+// Settings that can be changed while the editor is running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Setting {
+    TabWidth,
+    UndoPeriod,
+    LineNumberPaddingLeft,
+    LineNumberPaddingRight,
+}
+
+// Commands that have been parsed and are ready to run
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Save,
+    SaveAs(Option<String>),
+    SaveAll,
+    New,
+    Open(Option<String>),
+    Quit,
+    Search,
+    Replace(Option<(String, String)>),
+    ReplaceAll(Option<(String, String)>),
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    CycleClipboard,
+    NextTab,
+    PrevTab,
+    Split(Split),
+    NextPane,
+    PrevPane,
+    GrowPane,
+    ShrinkPane,
+    ClosePane,
+    Goto(usize),
+    Move(Key),
+    Prompt,
+    Record(Option<String>),
+    Play(Option<String>, Option<usize>),
+    Export(Option<String>),
+    Set(Setting, usize),
+    Reload,
+    EditConfig,
+    IndentWith(bool),
+    LineEnding(LineEnding),
+    Encoding(Charset),
+}
+
+impl Spec {
+    pub fn find(name: &str) -> Option<&'static Spec> {
+        // Look up a command by its name
+        COMMANDS.iter().find(|spec| spec.name == name)
+    }
+    pub fn usage(&self) -> String {
+        // Describe how the command should be written
+        let args: String = self
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                if i < self.required {
+                    format!(" <{name}>")
+                } else {
+                    format!(" [{name}]")
+                }
+            })
+            .collect();
+        format!("{}{args}", self.name)
+    }
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        // Turn the text of the command line into a command
+        let mut words = split(input);
+        if words.is_empty() {
+            return Err("No command given".to_string());
+        }
+        let name = words.remove(0);
+        let spec = Spec::find(&name).ok_or(format!("Unknown command: {name}"))?;
+        if words.len() < spec.required || words.len() > spec.args.len() {
+            return Err(format!("Usage: {}", spec.usage()));
+        }
+        // Check each argument against the kind the command expects
+        let mut numbers = vec![];
+        for (word, (arg, kind)) in words.iter().zip(spec.args) {
+            match kind {
+                Arg::Number => numbers.push(
+                    word.parse::<usize>()
+                        .map_err(|_| format!("Expected a number for {arg}, found {word}"))?,
+                ),
+                Arg::Choice(choices) if !choices.contains(&word.as_str()) => {
+                    return Err(format!("Expected one of {} for {arg}", choices.join(", ")));
+                }
+                _ => (),
+            }
+        }
+        let pair = |mut words: Vec<String>| match words.len() {
+            0 => Ok(None),
+            2 => {
+                let with = words.pop().unwrap_or_default();
+                Ok(Some((words.pop().unwrap_or_default(), with)))
+            }
+            _ => Err(format!("Usage: {}", spec.usage())),
+        };
+        Ok(match spec.name {
+            "save" => Self::Save,
+            "save_as" => Self::SaveAs(words.into_iter().next()),
+            "save_all" => Self::SaveAll,
+            "new" => Self::New,
+            "open" => Self::Open(words.into_iter().next()),
+            "quit" => Self::Quit,
+            "search" => Self::Search,
+            "replace" => Self::Replace(pair(words)?),
+            "replace_all" => Self::ReplaceAll(pair(words)?),
+            "undo" => Self::Undo,
+            "redo" => Self::Redo,
+            "copy" => Self::Copy,
+            "cut" => Self::Cut,
+            "paste" => Self::Paste,
+            "cycle_clipboard" => Self::CycleClipboard,
+            "tab" if words[0] == "next" => Self::NextTab,
+            "tab" => Self::PrevTab,
+            "split" if words[0] == "horizontal" => Self::Split(Split::Horizontal),
+            "split" => Self::Split(Split::Vertical),
+            "pane" if words[0] == "next" => Self::NextPane,
+            "pane" => Self::PrevPane,
+            "resize" if words[0] == "grow" => Self::GrowPane,
+            "resize" => Self::ShrinkPane,
+            "close_pane" => Self::ClosePane,
+            "goto" => Self::Goto(numbers[0]),
+            "move" => Self::Move(match words[0].as_str() {
+                "up" => Key::Up,
+                "down" => Key::Down,
+                "left" => Key::Left,
+                "right" => Key::Right,
+                "home" => Key::Home,
+                "end" => Key::End,
+                "page_up" => Key::PageUp,
+                _ => Key::PageDown,
+            }),
+            "command" => Self::Prompt,
+            "record" => Self::Record(words.into_iter().next()),
+            "play" => Self::Play(words.into_iter().next(), numbers.first().copied()),
+            "export" => Self::Export(words.into_iter().next()),
+            "set" => Self::Set(
+                match words[0].as_str() {
+                    "tab_width" => Setting::TabWidth,
+                    "undo_period" => Setting::UndoPeriod,
+                    "line_number_padding_left" => Setting::LineNumberPaddingLeft,
+                    _ => Setting::LineNumberPaddingRight,
+                },
+                numbers[0],
+            ),
+            "reload" => Self::Reload,
+            "edit_config" => Self::EditConfig,
+            "indent_with" => Self::IndentWith(words[0] == "tabs"),
+            "line_ending" => {
+                Self::LineEnding(LineEnding::parse(&words[0]).unwrap_or(LineEnding::Lf))
+            }
+            "encoding" => Self::Encoding(Charset::parse(&words[0]).unwrap_or(Charset::Utf8)),
+            _ => unreachable!(),
+        })
+    }
+}
+
+pub fn complete(input: &str) -> Vec<String> {
+    // Find the ways the last word of the command line could be finished
+    let mut tokens = tokens(input);
+    // The last word is only being typed if nothing has come after it yet
+    let (start, partial) = match tokens.last() {
+        Some(&(start, end, _)) if end == input.len() => (
+            start,
+            tokens.pop().map(|(_, _, word)| word).unwrap_or_default(),
+        ),
+        _ => (input.len(), String::new()),
+    };
+    let prefix = &input[..start];
+    let words: Vec<String> = tokens.into_iter().map(|(_, _, word)| word).collect();
+    let candidates: Vec<String> = if words.is_empty() {
+        // Complete the name of the command, ready for any arguments
+        COMMANDS
+            .iter()
+            .map(|spec| match spec.args {
+                [] => spec.name.to_string(),
+                _ => format!("{} ", spec.name),
+            })
+            .collect()
+    } else {
+        // Complete an argument of the command, ready for any that follow
+        let spec = Spec::find(&words[0]);
+        let more = spec.is_some_and(|spec| words.len() < spec.args.len());
+        match spec.and_then(|spec| spec.args.get(words.len() - 1)) {
+            Some((_, Arg::Choice(choices))) => choices
+                .iter()
+                .map(|choice| {
+                    if more {
+                        format!("{choice} ")
+                    } else {
+                        choice.to_string()
+                    }
+                })
+                .collect(),
+            Some((_, Arg::Path)) => paths(&partial),
+            _ => vec![],
+        }
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(&partial))
+        .map(|candidate| {
+            // Keep words with spaces in them together
+            if candidate.trim_end().contains(char::is_whitespace) {
+                let (word, rest) = candidate.split_at(candidate.trim_end().len());
+                format!("{prefix}\"{word}\"{rest}")
+            } else {
+                format!("{prefix}{candidate}")
+            }
+        })
+        .collect()
+}
+
+fn paths(partial: &str) -> Vec<String> {
+    // List the files that a partially typed path could refer to
+    let (dir, shown) = match partial.rfind('/') {
+        Some(i) => (&partial[..=i], &partial[..=i]),
+        None => (".", ""),
+    };
+    let mut result: Vec<String> = fs::read_dir(Path::new(dir))
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let slash = if entry.path().is_dir() { "/" } else { "" };
+                    format!("{shown}{name}{slash}")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    result.sort();
+    result
+}
+
+fn split(input: &str) -> Vec<String> {
+    // Split a command into words, keeping quoted text together
+    tokens(input).into_iter().map(|(_, _, word)| word).collect()
+}
+
+fn tokens(input: &str) -> Vec<(usize, usize, String)> {
+    // Split a command into words, along with the byte offsets where each starts and ends
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut start = None;
+    for (i, c) in input.char_indices() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                start = start.or(Some(i));
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    words.push((start, i, std::mem::take(&mut word)));
+                }
+            }
+            c => {
+                word.push(c);
+                start = start.or(Some(i));
+            }
+        }
+    }
+    if let Some(start) = start {
+        words.push((start, input.len(), word));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn words() {
+        assert_eq!(split("goto  120 "), vec!["goto", "120"]);
+        assert_eq!(
+            split("replace \"a b\" c\"d e\""),
+            vec!["replace", "a b", "cd e"]
+        );
+        assert_eq!(split("replace \"\" x"), vec!["replace", "", "x"]);
+        assert_eq!(
+            split("open \"unfinished quote"),
+            vec!["open", "unfinished quote"]
+        );
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn offsets() {
+        // Words are found by the bytes they take up in the text that was typed
+        assert_eq!(
+            tokens("open é\"x y\""),
+            vec![(0, 4, "open".to_string()), (5, 12, "éx y".to_string())]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Command::parse("goto 12"), Ok(Command::Goto(12)));
+        assert_eq!(
+            Command::parse("replace \"a b\" c"),
+            Ok(Command::Replace(Some(("a b".to_string(), "c".to_string()))))
+        );
+        assert_eq!(
+            Command::parse("set tab_width 2"),
+            Ok(Command::Set(Setting::TabWidth, 2))
+        );
+        assert_eq!(Command::parse("tab prev"), Ok(Command::PrevTab));
+        assert_eq!(
+            Command::parse("goto"),
+            Err("Usage: goto <line>".to_string())
+        );
+        assert_eq!(
+            Command::parse("goto x"),
+            Err("Expected a number for line, found x".to_string())
+        );
+        assert_eq!(
+            Command::parse("tab up"),
+            Err("Expected one of next, prev for direction".to_string())
+        );
+        assert_eq!(
+            Command::parse("fly"),
+            Err("Unknown command: fly".to_string())
+        );
+        assert_eq!(Command::parse(""), Err("No command given".to_string()));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(complete("save_"), vec!["save_as ", "save_all"]);
+        assert_eq!(complete("  undo"), vec!["  undo"]);
+        assert_eq!(complete("fly"), Vec::<String>::new());
+        assert_eq!(complete("").len(), COMMANDS.len());
+    }
+
+    #[test]
+    fn arguments() {
+        // Arguments that are followed by more are finished with a space
+        assert_eq!(complete("tab n"), vec!["tab next"]);
+        assert_eq!(complete("set tab"), vec!["set tab_width "]);
+        assert_eq!(complete("set tab_width "), Vec::<String>::new());
+        assert_eq!(
+            complete("line_ending "),
+            vec!["line_ending lf", "line_ending crlf", "line_ending cr"]
+        );
+        assert_eq!(complete("fly a"), Vec::<String>::new());
+        assert_eq!(complete("goto 1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn quotes() {
+        // The prefix is kept as typed, even when quotes are taken out of the last word
+        assert_eq!(complete("open é\"x"), Vec::<String>::new());
+        assert_eq!(complete("tab \"n"), vec!["tab next"]);
+        assert_eq!(complete("\"tab\" ne"), vec!["\"tab\" next"]);
+    }
+
+    #[test]
+    fn paths() {
+        let folder = env::temp_dir().join(format!("ox-command-{}", process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("sub")).unwrap();
+        fs::write(folder.join("some file.txt"), "").unwrap();
+        fs::write(folder.join("other.txt"), "").unwrap();
+        let dir = folder.to_string_lossy();
+        assert_eq!(
+            complete(&format!("open {dir}/s")),
+            vec![
+                format!("open \"{dir}/some file.txt\""),
+                format!("open {dir}/sub/")
+            ]
+        );
+        assert_eq!(
+            complete(&format!("open \"{dir}/some")),
+            vec![format!("open \"{dir}/some file.txt\"")]
+        );
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
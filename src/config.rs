@@ -1,10 +1,27 @@
 // Config.rs - In charge of storing configuration information
+use crate::command::Command;
+use crate::editor::RESET_FG;
+use crate::keys::{keymap, macros};
+use crate::terminal::{Input, Terminal};
+use crate::textmate::load;
+use directories::BaseDirs;
 use regex::Regex;
 use ron::de::from_str;
-use serde::Deserialize;
+use ron::value::Map;
+use ron::Value;
+use serde::de::{DeserializeSeed, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
 use std::fs;
-use termion::color;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use syntect::parsing::SyntaxSet;
+use termion::event::Key;
+use termion::{color, style};
 
 // Enum for determining what type of token it is
 #[derive(Clone)]
@@ -26,37 +43,163 @@ pub enum Status {
 pub struct Reader {
     pub general: General,
     pub theme: Theme,
-    pub highlights: HashMap<String, (u8, u8, u8)>,
+    pub highlights: HashMap<String, Style>,
     pub languages: Vec<Language>,
+    #[serde(default)]
+    pub clipboard: Clipboard,
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+    #[serde(skip)]
+    pub keymap: HashMap<Key, Command>,
+    #[serde(default)]
+    pub macros: HashMap<String, Vec<String>>,
+    #[serde(skip)]
+    pub registers: HashMap<String, Vec<Input>>,
+    #[serde(skip)]
+    pub syntaxes: Option<Rc<SyntaxSet>>,
 }
 
 impl Reader {
-    pub fn read(config: &str) -> (Self, Status) {
-        // Read the config file, if it fails, use a hard-coded configuration
-        // Expand the path to get rid of any filepath issues
-        let config = if let Ok(config) = shellexpand::full(config) {
-            (*config).to_string()
-        } else {
-            config.to_string()
+    pub fn layers(user: &str, file: Option<&str>) -> Vec<String> {
+        // Find the config files that apply, from the least to the most specific
+        let user =
+            shellexpand::full(user).map_or_else(|_| user.to_string(), |path| path.to_string());
+        let mut result = vec![SYSTEM_CONFIG.to_string(), user];
+        // Look for a project config in the folder of the file being edited and the ones above it
+        let cwd = env::current_dir().unwrap_or_default();
+        let start = file.map_or(cwd.clone(), |file| {
+            let file = cwd.join(file);
+            file.parent().map_or(file.clone(), Path::to_path_buf)
+        });
+        if let Some(project) = start
+            .ancestors()
+            .map(|folder| folder.join(PROJECT_CONFIG))
+            .find(|path| path.is_file())
+        {
+            result.push(project.to_string_lossy().to_string());
+        }
+        result
+    }
+    pub fn read(layers: &[String]) -> (Self, Status) {
+        // Read the config files, if they fail, use a hard-coded configuration
+        let (result, status) = match Self::parse(layers) {
+            Ok((result, true)) => (result, Status::Success),
+            Ok((result, false)) => (result, Status::File),
+            Err(error) => (from_str(DEFAULT).unwrap(), Status::Parse(error)),
         };
-        // Attempt to read and parse the configuration file
-        if let Ok(file) = fs::read_to_string(config) {
-            let result: (Self, Status) = if let Ok(contents) = from_str(&file) {
-                (contents, Status::Success)
-            } else {
-                // There is a syntax issue with the config file
-                let result: Result<Self, ron::Error> = from_str(&file);
-                // Provide the syntax issue with the config file for debugging
-                (
-                    from_str(DEFAULT).unwrap(),
-                    Status::Parse(format!("{:?}", result)),
-                )
-            };
-            result
+        result.prepare(status)
+    }
+    pub fn reload(layers: &[String]) -> Result<(Self, Status), String> {
+        // Read the config files again, leaving it to the caller to keep the old config on error
+        Ok(Self::parse(layers)?.0.prepare(Status::Success))
+    }
+    fn parse(layers: &[String]) -> Result<(Self, bool), String> {
+        // Lay each config file that exists over the defaults, returning whether any were found
+        let Layer {
+            mut config,
+            mut colours,
+        } = from_str(DEFAULT).unwrap();
+        let mut found = false;
+        for path in layers {
+            if let Ok(file) = fs::read_to_string(path) {
+                // Provide any syntax issue with the config file for debugging
+                let Layer {
+                    config: mut layer,
+                    colours: chosen,
+                } = from_str(&file).map_err(|error: ron::Error| format!("{error:?}"))?;
+                colours = chosen.or(colours);
+                if let (true, Value::Map(map)) = (path.ends_with(PROJECT_CONFIG), &mut layer) {
+                    // Projects can't choose the commands that are run for the clipboard or to save
+                    map.remove(&Value::String("clipboard".to_string()));
+                }
+                if let (true, Some(Value::Map(general))) = (
+                    path.ends_with(PROJECT_CONFIG),
+                    field_mut(&mut layer, "general"),
+                ) {
+                    general.remove(&Value::String("privilege_helper".to_string()));
+                }
+                merge(&mut config, layer);
+                found = true;
+            }
+        }
+        // Give each language its own copy of the general settings, with its changes made
+        let general = field(&config, "general").cloned().unwrap_or(Value::Unit);
+        if let Some(Value::Seq(languages)) = field_mut(&mut config, "languages") {
+            for language in languages {
+                if let Some(changes) = field_mut(language, "general") {
+                    let mut settings = general.clone();
+                    merge(&mut settings, mem::replace(changes, Value::Unit));
+                    *changes = Value::Option(Some(Box::new(settings)));
+                }
+            }
+        }
+        let mut config: Self = config
+            .into_rust()
+            .map_err(|error: ron::Error| format!("{error:?}"))?;
+        let colours = colours.unwrap_or_default();
+        config.general.colours = colours;
+        for general in config
+            .languages
+            .iter_mut()
+            .filter_map(|l| l.general.as_mut())
+        {
+            general.colours = colours;
+        }
+        Ok((config, found))
+    }
+    fn prepare(mut self, mut status: Status) -> (Self, Status) {
+        // Get everything that the config refers to ready
+        // Bind the keys and read the macros, reporting anything that doesn't make sense
+        let (keys, key_error) = keymap(&self.keys);
+        let (registers, macro_error) = macros(&self.macros);
+        self.keymap = keys;
+        self.registers = registers;
+        // Load the TextMate and Sublime Text grammars
+        let grammars = shellexpand::full(&self.general.grammars)
+            .map_or_else(|_| self.general.grammars.clone(), |path| path.to_string());
+        let (syntaxes, grammar_error) = load(&grammars);
+        self.syntaxes = syntaxes.map(Rc::new);
+        // Swap in the colours from a theme file if one is named
+        let theme_error = if self.general.theme.is_empty() {
+            None
         } else {
-            // File wasn't able to be found
-            (from_str(DEFAULT).unwrap(), Status::File)
+            match Self::load_theme(&self.general.theme) {
+                Ok((theme, highlights)) => {
+                    // Anything the theme leaves out keeps its colour from the config
+                    self.theme = theme;
+                    self.highlights.extend(highlights);
+                    None
+                }
+                Err(error) => Some(error),
+            }
+        };
+        let error = key_error.or(macro_error).or(grammar_error).or(theme_error);
+        if let (Some(error), Status::Success) = (error, &status) {
+            status = Status::Parse(error);
         }
+        (self, status)
+    }
+    pub fn load_theme(name: &str) -> Result<(Theme, HashMap<String, Style>), String> {
+        // Read a theme file, either from a path or by its name in the themes folder
+        let file = Path::new(name).extension().is_some_and(|ext| ext == "ron");
+        let path = if name.contains('/') || file {
+            PathBuf::from(shellexpand::full(name).map_or(name.to_string(), |path| path.to_string()))
+        } else {
+            let dirs = BaseDirs::new().ok_or_else(|| format!("Theme not found: {name}"))?;
+            dirs.config_dir().join(format!("ox/themes/{name}.ron"))
+        };
+        let file = fs::read_to_string(path).map_err(|_| format!("Theme not found: {name}"))?;
+        let theme: ThemeFile =
+            from_str(&file).map_err(|e| format!("Theme {name} is invalid: {e}"))?;
+        Ok((theme.theme, theme.highlights))
+    }
+    pub fn general(&self, extension: &str) -> General {
+        // Get the general settings for a file extension, with any changes its language makes
+        self.languages
+            .iter()
+            .find(|lang| lang.extensions.iter().any(|ext| ext == extension))
+            .and_then(|lang| lang.general.clone())
+            .unwrap_or_else(|| self.general.clone())
     }
     pub fn get_syntax_regex(config: &Self, extension: &str) -> Vec<TokenType> {
         // Compile the regular expressions from their string format
@@ -100,33 +243,342 @@ impl Reader {
         }
         result
     }
-    pub fn rgb_fg(colour: (u8, u8, u8)) -> color::Fg<color::Rgb> {
-        // Get the text ANSI code from an RGB value
-        color::Fg(color::Rgb(colour.0, colour.1, colour.2))
+    pub fn rgb_fg(colour: Colour) -> color::Fg<Colour> {
+        // Get the text ANSI code from a colour
+        color::Fg(colour)
     }
-    pub fn rgb_bg(colour: (u8, u8, u8)) -> color::Bg<color::Rgb> {
-        // Get the background ANSI code from an RGB value
-        color::Bg(color::Rgb(colour.0, colour.1, colour.2))
+    pub fn rgb_bg(colour: Colour) -> color::Bg<Colour> {
+        // Get the background ANSI code from a colour
+        color::Bg(colour)
     }
 }
 
 // Struct for storing the general configuration
 #[derive(Debug, Deserialize, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct General {
     pub line_number_padding_right: usize,
     pub line_number_padding_left: usize,
     pub tab_width: usize,
+    #[serde(default)]
+    pub hard_tabs: bool,
     pub undo_period: u64,
+    #[serde(default)]
+    pub modal: bool,
+    #[serde(default)]
+    pub tree_sitter: bool,
+    #[serde(default)]
+    pub grammars: String,
+    #[serde(default)]
+    pub theme: String,
+    #[serde(default)]
+    pub colours: Colours,
+    #[serde(default)]
+    pub watch_config: bool,
+    #[serde(default)]
+    pub backup: bool,
+    #[serde(default)]
+    pub privilege_helper: String,
+}
+
+// How many colours the terminal can show
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum Colours {
+    #[default]
+    Auto, // Work it out from the environment
+    TrueColour, // Any RGB value
+    Ansi256,    // The 256 colour palette
+    Ansi16,     // The 16 standard colours
+}
+
+// A config file, read so that how many colours to use survives merging it with other files
+struct Layer {
+    config: Value,            // The settings, ready to be merged
+    colours: Option<Colours>, // How many colours to use, a name that would be lost as a Value
+}
+
+impl<'de> Deserialize<'de> for Layer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Read the sections of the file, picking how many colours to use out of the general ones
+        Sections { general: false }.deserialize(deserializer)
+    }
+}
+
+// For reading the sections of a config file, or the settings in its general section
+struct Sections {
+    general: bool, // True when reading the general section
+}
+
+impl<'de> DeserializeSeed<'de> for Sections {
+    type Value = Layer;
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Layer, D::Error> {
+        let name = if self.general { "General" } else { "" };
+        deserializer.deserialize_struct(name, &[], self)
+    }
+}
+
+impl<'de> Visitor<'de> for Sections {
+    type Value = Layer;
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a config")
+    }
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Layer, A::Error> {
+        let mut config = Map::new();
+        let mut colours = None;
+        while let Some(key) = access.next_key::<Value>()? {
+            let name = match &key {
+                Value::String(name) => name.as_str(),
+                _ => "",
+            };
+            if self.general && name == "colours" {
+                colours = Some(access.next_value()?);
+            } else if !self.general && name == "general" {
+                let general = access.next_value_seed(Sections { general: true })?;
+                colours = general.colours;
+                config.insert(key, general.config);
+            } else {
+                config.insert(key, access.next_value()?);
+            }
+        }
+        Ok(Layer {
+            config: Value::Map(config),
+            colours,
+        })
+    }
+}
+
+// A colour from the config, either an RGB value or whatever colour the terminal uses
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(try_from = "Written")]
+pub enum Colour {
+    Rgb((u8, u8, u8)), // Shown as near as the terminal can manage
+    Default,           // Left to the terminal, so that its own colours show through
+}
+
+// The ways of writing a colour, either as an RGB value or as "default"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Written {
+    Rgb((u8, u8, u8)),
+    Name(String),
+}
+
+impl TryFrom<Written> for Colour {
+    type Error = String;
+    fn try_from(written: Written) -> Result<Self, Self::Error> {
+        // Read a colour, only allowing the names that are known
+        match written {
+            Written::Rgb(rgb) => Ok(Self::Rgb(rgb)),
+            Written::Name(name) if name == "default" => Ok(Self::Default),
+            Written::Name(name) => Err(format!("Unknown colour: {name}")),
+        }
+    }
+}
+
+impl color::Color for Colour {
+    fn write_fg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Write the code for a text colour
+        let Self::Rgb(rgb) = *self else {
+            return color::Reset.write_fg(f);
+        };
+        match Terminal::colours() {
+            Colours::Ansi256 => color::AnsiValue(ansi256(rgb)).write_fg(f),
+            Colours::Ansi16 => match ansi16(rgb) {
+                index @ 0..=7 => write!(f, "\x1b[{}m", 30 + index),
+                index => write!(f, "\x1b[{}m", 82 + index),
+            },
+            _ => color::Rgb(rgb.0, rgb.1, rgb.2).write_fg(f),
+        }
+    }
+    fn write_bg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Write the code for a background colour
+        let Self::Rgb(rgb) = *self else {
+            return color::Reset.write_bg(f);
+        };
+        match Terminal::colours() {
+            Colours::Ansi256 => color::AnsiValue(ansi256(rgb)).write_bg(f),
+            Colours::Ansi16 => match ansi16(rgb) {
+                index @ 0..=7 => write!(f, "\x1b[{}m", 40 + index),
+                index => write!(f, "\x1b[{}m", 92 + index),
+            },
+            _ => color::Rgb(rgb.0, rgb.1, rgb.2).write_bg(f),
+        }
+    }
+}
+
+// The RGB values that terminals commonly use for the 16 standard colours
+const ANSI16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+// The levels of each channel in the colour cube of the 256 colour palette
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    // Work out how far apart two colours are
+    let channel = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+fn ansi16(colour: (u8, u8, u8)) -> u8 {
+    // Find the nearest of the 16 standard colours
+    (0..16)
+        .min_by_key(|&index| distance(colour, ANSI16[index]))
+        .unwrap_or(0) as u8
+}
+
+fn ansi256(colour: (u8, u8, u8)) -> u8 {
+    // Find the nearest colour in the cube or on the grey ramp of the 256 colour palette
+    let level = |channel: u8| {
+        (0..6)
+            .min_by_key(|&index| (i32::from(CUBE[index]) - i32::from(channel)).abs())
+            .unwrap_or(0)
+    };
+    let (r, g, b) = (level(colour.0), level(colour.1), level(colour.2));
+    let cube = (CUBE[r], CUBE[g], CUBE[b]);
+    let average = (u32::from(colour.0) + u32::from(colour.1) + u32::from(colour.2)) / 3;
+    let step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey = 8 + step * 10;
+    if distance(colour, (grey, grey, grey)) < distance(colour, cube) {
+        232 + step
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
 }
 
 // Struct for storing theme information
 #[derive(Debug, Deserialize, Clone)]
 pub struct Theme {
-    pub editor_bg: (u8, u8, u8),
-    pub editor_fg: (u8, u8, u8),
-    pub status_bg: (u8, u8, u8),
-    pub status_fg: (u8, u8, u8),
-    pub line_number_fg: (u8, u8, u8),
+    pub editor_bg: Colour,
+    pub editor_fg: Colour,
+    pub status_bg: Colour,
+    pub status_fg: Colour,
+    pub line_number_fg: Colour,
+}
+
+// How a kind of token is drawn
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(from = "Highlight")]
+pub struct Style {
+    pub fg: Option<Colour>, // The colour of the text
+    pub bg: Option<Colour>, // The colour behind the text
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+// The ways of writing a highlight, either as just a colour or with the full style
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Highlight {
+    Colour(Colour),
+    Style {
+        #[serde(default)]
+        fg: Option<Colour>,
+        #[serde(default)]
+        bg: Option<Colour>,
+        #[serde(default)]
+        bold: bool,
+        #[serde(default)]
+        italic: bool,
+        #[serde(default)]
+        underline: bool,
+    },
+}
+
+// Struct for storing a theme file
+#[derive(Deserialize)]
+struct ThemeFile {
+    theme: Theme,
+    highlights: HashMap<String, Style>,
+}
+
+impl From<Highlight> for Style {
+    fn from(highlight: Highlight) -> Self {
+        // Fill in the parts of the style that were left out
+        match highlight {
+            Highlight::Colour(fg) => Self {
+                fg: Some(fg),
+                ..Self::default()
+            },
+            Highlight::Style {
+                fg,
+                bg,
+                bold,
+                italic,
+                underline,
+            } => Self {
+                fg,
+                bg,
+                bold,
+                italic,
+                underline,
+            },
+        }
+    }
+}
+
+impl Style {
+    pub fn start(&self) -> String {
+        // Get the ANSI codes that begin drawing text in this style
+        let mut result = String::new();
+        if let Some(fg) = self.fg {
+            result.push_str(&Reader::rgb_fg(fg).to_string());
+        }
+        if let Some(bg) = self.bg {
+            result.push_str(&Reader::rgb_bg(bg).to_string());
+        }
+        if self.bold {
+            result.push_str(style::Bold.as_ref());
+        }
+        if self.italic {
+            result.push_str(style::Italic.as_ref());
+        }
+        if self.underline {
+            result.push_str(style::Underline.as_ref());
+        }
+        result
+    }
+    pub fn end(&self, theme: &Theme) -> String {
+        // Get the ANSI codes that go back to drawing plain text
+        let mut result = RESET_FG.to_string();
+        if self.bg.is_some() {
+            result.push_str(&Reader::rgb_bg(theme.editor_bg).to_string());
+        }
+        if self.bold {
+            // Termion's NoBold is read as a double underline by some terminals
+            result.push_str("\x1b[22m");
+        }
+        if self.italic {
+            result.push_str(style::NoItalic.as_ref());
+        }
+        if self.underline {
+            result.push_str(style::NoUnderline.as_ref());
+        }
+        result
+    }
+}
+
+// Struct for storing clipboard settings
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Clipboard {
+    pub osc52: bool,           // Send copied text to the terminal's clipboard
+    pub copy_command: String,  // Command that copied text is piped into
+    pub paste_command: String, // Command that prints the text to paste
 }
 
 // Struct for storing language information
@@ -137,12 +589,189 @@ pub struct Language {
     pub extensions: Vec<String>,
     pub keywords: Vec<String>,
     pub definitions: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub general: Option<General>,
+}
+
+fn merge(base: &mut Value, layer: Value) {
+    // Lay one config over another, keeping whatever the new one leaves out
+    match (base, layer) {
+        (Value::Map(base), Value::Map(layer)) => {
+            for (key, value) in layer.iter() {
+                let merged = match base.remove(key) {
+                    Some(Value::Seq(mut languages))
+                        if *key == Value::String("languages".to_string()) =>
+                    {
+                        if let Value::Seq(layer) = value {
+                            merge_languages(&mut languages, layer.clone());
+                            Value::Seq(languages)
+                        } else {
+                            value.clone()
+                        }
+                    }
+                    Some(mut old) => {
+                        merge(&mut old, value.clone());
+                        old
+                    }
+                    None => value.clone(),
+                };
+                base.insert(key.clone(), merged);
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+fn merge_languages(base: &mut Vec<Value>, layer: Vec<Value>) {
+    // Merge languages that share a name and add the new ones
+    for language in layer {
+        let name = field(&language, "name").cloned();
+        match base
+            .iter_mut()
+            .find(|old| name.is_some() && field(old, "name") == name.as_ref())
+        {
+            Some(old) => merge(old, language),
+            None => base.push(language),
+        }
+    }
 }
 
+fn field<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    // Get a field of a struct or an entry of a map in a config
+    match value {
+        Value::Map(map) => map
+            .iter()
+            .find(|(key, _)| **key == Value::String(name.to_string()))
+            .map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+fn field_mut<'a>(value: &'a mut Value, name: &str) -> Option<&'a mut Value> {
+    // Get a field of a struct or an entry of a map in a config, ready to change it
+    match value {
+        Value::Map(map) => map
+            .iter_mut()
+            .find(|(key, _)| **key == Value::String(name.to_string()))
+            .map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+// Where the config that applies to everyone on the system is kept
+const SYSTEM_CONFIG: &str = "/etc/ox/ox.ron";
+// The name of the config file that can be kept in a project
+const PROJECT_CONFIG: &str = ".ox.ron";
+
 // Default configuration format
 // Minify using:
 // (| )//[a-zA-Z0-9 ]+ on https://www.regextester.com/
 // https://codebeautify.org/text-minifier
 const DEFAULT: &str = r#"
-(general:General(line_number_padding_right:2,line_number_padding_left:1,tab_width:4,undo_period:5,),theme:Theme(editor_bg:(41,41,61),editor_fg:(255,255,255),status_bg:(59,59,84),status_fg:(35,240,144),line_number_fg:(65,65,98),),highlights:{"comments":(113,113,169),"keywords":(134,76,232),"references":(134,76,232),"strings":(39,222,145),"characters":(40,198,232),"digits":(40,198,232),"booleans":(86,217,178),"functions":(47,141,252),"structs":(47,141,252),"macros":(223,52,249),"attributes":(40,198,232),"headers":(47,141,252),"symbols":(47,141,252),"global":(86,217,178),},languages:[Language(name:"Rust",icon:"\u{e7a8}",extensions:["rs"],keywords:["as","break","const","continue","crate","else","enum","extern","fn","for","if","impl","in","let","loop","match","mod","move","mut","pub","ref","return","self","static","struct","super","trait","type","unsafe","use","where","while","async","await","dyn","abstract","become","box","do","final","macro","override","priv","typeof","unsized","virtual","yield","try","'static","u8","u16","u32","u64","u128","usize","i8","i16","i32","i64","i128","isize","f32","f64","String","Vec","str","Some","bool","None","Box","Result","Option","Ok","Err",],definitions:{"comments":["(?m)(//.*)$","(?ms)(/\\*.*?\\*/)",],"strings":["(\".*?\")",],"characters":["('.')","('\\\\.')",],"digits":["\\b(\\d+.\\d+|\\d+)","\\b(\\d+.\\d+(?:f32|f64))",],"booleans":["\\b(true)\\b","\\b(false)\\b",],"functions":["fn\\s+([a-z_][A-Za-z0-9_]*)\\s*\\(",],"structs":["(?:trait|enum|struct|impl)\\s+([A-Z][A-Za-z0-9_]*)\\s*","impl(?:<.*?>|)\\s+([A-Z][A-Za-z0-9_]*)","([A-Z][A-Za-z0-9_]*)::","impl.*for\\s+([A-Z][A-Za-z0-9_]*)",],"macros":["\\b([a-z_][a-zA-Z0-9_]*!)",],"attributes":["^\\s*(#(?:!|)\\[.*?\\])",],"references":["&str","&mut","&self","&i8","&i16","&i32","&i64","&i128","&isize","&u8","&u16","&u32","&u64","&u128","&usize","&f32","&f64",]}),Language(name:"Ruby",icon:"\u{e739}",extensions:["rb"],keywords:["__ENCODING__","__LINE__","__FILE__","BEGIN","END","alias","and","begin","break","case","class","def","defined?","do","else","elsif","end","ensure","print","for","if","in","module","next","nil","not","or","puts","redo","rescue","retry","return","self","super","then","undef","unless","until","when","while","yield","raise","include","extend",],definitions:{"comments":["(?m)(#.*)$","(?ms)(=begin.*=end)",],"strings":["((?:f|r|)\".*?\")","(\'.*?\')",],"digits":[r"\b(\d+.\d+|\d+)",],"booleans":[r"\b(true)\b",r"\b(false)\b",],"structs":[r"class(\s+[A-Za-z0-9_]*)",],"functions":[r"def\s+([a-z_][A-Za-z0-9_]*)",],"symbols":[r"(:[^,\)\.\s=]+)",],"global":[r"(\$[a-z_][A-Za-z0-9_]*)\s",]}),Language(name:"Crystal",icon:"\u{e7a3}",extensions:["cr"],keywords:["__ENCODING__","__LINE__","__FILE__","BEGIN","END","alias","and","begin","break","case","class","def","defined?","do","else","elsif","end","ensure","print","for","if","in","module","next","nil","not","or","puts","redo","rescue","retry","return","self","super","then","undef","unless","until","when","while","yield","raise","include","extend","Int32","String","getter","setter","property",],definitions:{"comments":["(?m)(#.*)$","(?ms)(=begin.*=end)",],"strings":["(?ms)(\".*?\")","((?:f|r|)\".*?\")","(\'.*?\')",],"digits":[r"\b(\d+.\d+|\d+)",],"booleans":[r"\b(true)\b",r"\b(false)\b",],"structs":[r"class(\s+[A-Za-z0-9_]*)",],"functions":[r"def\s+([a-z_][A-Za-z0-9_]*)",],"symbols":[r"(:[^,\}\)\.\s=]+)",],"global":[r"(\$[a-z_][A-Za-z0-9_]*)\s",]}),Language(name:"Python",icon:"\u{e73c}",extensions:["py","pyw"],keywords:["and","as","assert","break","class","continue","def","del","elif","else","except","exec","finally","for","from","global","if","import","in","is","lambda","not","or","pass","print","raise","return","try","while","with","yield","str","bool","int","tuple","list","dict","tuple","len","None","input","type","set","range","enumerate","open","iter","min","max","dir","self","isinstance","help","next","super",],definitions:{"comments":["(?m)(#.*)$",],"strings":["(?ms)(\"\"\".*?\"\"\")","(?ms)(\'\'\'.*?\'\'\')","((?:f|r|)\".*?\")","(\'.*?\')",],"digits":["\\b(\\d+.\\d+|\\d+)",],"booleans":["\\b(True)\\b","\\b(False)\\b",],"structs":["class\\s+([A-Za-z0-9_]*)",],"functions":["def\\s+([a-z_][A-Za-z0-9_]*)",],"attributes":["@.*$",]}),Language(name:"Javascript",icon:"\u{e74e}",extensions:["js"],keywords:["abstract","arguments","await","boolean","break","byte","case","catch","char","class","const","continue","debugger","default","delete","do","double","else","enum","eval","export","extends","final","finally","float","for","of","function","goto","if","implements","import","in","instanceof","int","interface","let","long","native","new","null","package","private","protected","public","return","short","static","super","switch","synchronized","this","throw","throws","transient","try","typeof","var","void","volatile","console","while","with","yield","undefined","NaN","-Infinity","Infinity",],definitions:{"comments":["(?m)(//.*)$","(?ms)(/\\*.*\\*/)$",],"strings":["(?ms)(\"\"\".*?\"\"\")","(?ms)(\'\'\'.*?\'\'\')","((?:f|r|)\".*?\")","(\'.*?\')",],"digits":["\\b(\\d+.\\d+|\\d+)",],"booleans":["\\b(true)\\b","\\b(false)\\b",],"structs":["class\\s+([A-Za-z0-9_]*)",],"functions":["function\\s+([a-z_][A-Za-z0-9_]*)","\\b([a-z_][A-Za-z0-9_]*)\\s*\\("],}),Language(name:"C",icon:"\u{e61e}",extensions:["c","h"],keywords:["auto","break","case","char","const","continue","default","do","double","else","enum","extern","float","for","goto","if","int","long","register","return","short","signed","sizeof","static","struct","switch","typedef","union","unsigned","void","volatile","while","printf","fscanf","scanf","fputsf","exit","stderr","malloc","calloc","bool","realloc","free","strlen","size_t",],definitions:{"comments":["(?m)(//.*)$","(?ms)(/\\*.*?\\*/)",],"strings":["(\".*?\")",],"characters":["('.')","('\\\\.')",],"digits":["\\b(\\d+.\\d+|\\d+)","\\b(\\d+.\\d+(?:f|))",],"booleans":["\\b(true)\\b","\\b(false)\\b",],"functions":["(int|bool|void|char|double|long|short|size_t)\\s+([a-z_][A-Za-z0-9_]*)\\s*\\(",],"structs":["struct\\s+([A-Za-z0-9_]*)\\s*",],"attributes":["^\\s*(#.*?)\\s",],"headers":["(<.*?>)",],}),],)
+(general:General(line_number_padding_right:2,line_number_padding_left:1,tab_width:4,hard_tabs:false,undo_period:5,modal:false,tree_sitter:false,grammars:"~/.config/ox/grammars",theme:"",colours:Auto,watch_config:false,backup:false,privilege_helper:"sudo",),theme:Theme(editor_bg:(41,41,61),editor_fg:(255,255,255),status_bg:(59,59,84),status_fg:(35,240,144),line_number_fg:(65,65,98),),highlights:{"comments":(113,113,169),"keywords":(134,76,232),"references":(134,76,232),"strings":(39,222,145),"characters":(40,198,232),"digits":(40,198,232),"booleans":(86,217,178),"functions":(47,141,252),"structs":(47,141,252),"macros":(223,52,249),"attributes":(40,198,232),"headers":(47,141,252),"symbols":(47,141,252),"global":(86,217,178),},languages:[Language(name:"Rust",icon:"\u{e7a8}",extensions:["rs"],keywords:["as","break","const","continue","crate","else","enum","extern","fn","for","if","impl","in","let","loop","match","mod","move","mut","pub","ref","return","self","static","struct","super","trait","type","unsafe","use","where","while","async","await","dyn","abstract","become","box","do","final","macro","override","priv","typeof","unsized","virtual","yield","try","'static","u8","u16","u32","u64","u128","usize","i8","i16","i32","i64","i128","isize","f32","f64","String","Vec","str","Some","bool","None","Box","Result","Option","Ok","Err",],definitions:{"comments":["(?m)(//.*)$","(?ms)(/\\*.*?\\*/)",],"strings":["(\".*?\")",],"characters":["('.')","('\\\\.')",],"digits":["\\b(\\d+.\\d+|\\d+)","\\b(\\d+.\\d+(?:f32|f64))",],"booleans":["\\b(true)\\b","\\b(false)\\b",],"functions":["fn\\s+([a-z_][A-Za-z0-9_]*)\\s*\\(",],"structs":["(?:trait|enum|struct|impl)\\s+([A-Z][A-Za-z0-9_]*)\\s*","impl(?:<.*?>|)\\s+([A-Z][A-Za-z0-9_]*)","([A-Z][A-Za-z0-9_]*)::","impl.*for\\s+([A-Z][A-Za-z0-9_]*)",],"macros":["\\b([a-z_][a-zA-Z0-9_]*!)",],"attributes":["^\\s*(#(?:!|)\\[.*?\\])",],"references":["&str","&mut","&self","&i8","&i16","&i32","&i64","&i128","&isize","&u8","&u16","&u32","&u64","&u128","&usize","&f32","&f64",]}),Language(name:"Ruby",icon:"\u{e739}",extensions:["rb"],keywords:["__ENCODING__","__LINE__","__FILE__","BEGIN","END","alias","and","begin","break","case","class","def","defined?","do","else","elsif","end","ensure","print","for","if","in","module","next","nil","not","or","puts","redo","rescue","retry","return","self","super","then","undef","unless","until","when","while","yield","raise","include","extend",],definitions:{"comments":["(?m)(#.*)$","(?ms)(=begin.*=end)",],"strings":["((?:f|r|)\".*?\")","(\'.*?\')",],"digits":[r"\b(\d+.\d+|\d+)",],"booleans":[r"\b(true)\b",r"\b(false)\b",],"structs":[r"class(\s+[A-Za-z0-9_]*)",],"functions":[r"def\s+([a-z_][A-Za-z0-9_]*)",],"symbols":[r"(:[^,\)\.\s=]+)",],"global":[r"(\$[a-z_][A-Za-z0-9_]*)\s",]}),Language(name:"Crystal",icon:"\u{e7a3}",extensions:["cr"],keywords:["__ENCODING__","__LINE__","__FILE__","BEGIN","END","alias","and","begin","break","case","class","def","defined?","do","else","elsif","end","ensure","print","for","if","in","module","next","nil","not","or","puts","redo","rescue","retry","return","self","super","then","undef","unless","until","when","while","yield","raise","include","extend","Int32","String","getter","setter","property",],definitions:{"comments":["(?m)(#.*)$","(?ms)(=begin.*=end)",],"strings":["(?ms)(\".*?\")","((?:f|r|)\".*?\")","(\'.*?\')",],"digits":[r"\b(\d+.\d+|\d+)",],"booleans":[r"\b(true)\b",r"\b(false)\b",],"structs":[r"class(\s+[A-Za-z0-9_]*)",],"functions":[r"def\s+([a-z_][A-Za-z0-9_]*)",],"symbols":[r"(:[^,\}\)\.\s=]+)",],"global":[r"(\$[a-z_][A-Za-z0-9_]*)\s",]}),Language(name:"Python",icon:"\u{e73c}",extensions:["py","pyw"],keywords:["and","as","assert","break","class","continue","def","del","elif","else","except","exec","finally","for","from","global","if","import","in","is","lambda","not","or","pass","print","raise","return","try","while","with","yield","str","bool","int","tuple","list","dict","tuple","len","None","input","type","set","range","enumerate","open","iter","min","max","dir","self","isinstance","help","next","super",],definitions:{"comments":["(?m)(#.*)$",],"strings":["(?ms)(\"\"\".*?\"\"\")","(?ms)(\'\'\'.*?\'\'\')","((?:f|r|)\".*?\")","(\'.*?\')",],"digits":["\\b(\\d+.\\d+|\\d+)",],"booleans":["\\b(True)\\b","\\b(False)\\b",],"structs":["class\\s+([A-Za-z0-9_]*)",],"functions":["def\\s+([a-z_][A-Za-z0-9_]*)",],"attributes":["@.*$",]}),Language(name:"Javascript",icon:"\u{e74e}",extensions:["js"],keywords:["abstract","arguments","await","boolean","break","byte","case","catch","char","class","const","continue","debugger","default","delete","do","double","else","enum","eval","export","extends","final","finally","float","for","of","function","goto","if","implements","import","in","instanceof","int","interface","let","long","native","new","null","package","private","protected","public","return","short","static","super","switch","synchronized","this","throw","throws","transient","try","typeof","var","void","volatile","console","while","with","yield","undefined","NaN","-Infinity","Infinity",],definitions:{"comments":["(?m)(//.*)$","(?ms)(/\\*.*\\*/)$",],"strings":["(?ms)(\"\"\".*?\"\"\")","(?ms)(\'\'\'.*?\'\'\')","((?:f|r|)\".*?\")","(\'.*?\')",],"digits":["\\b(\\d+.\\d+|\\d+)",],"booleans":["\\b(true)\\b","\\b(false)\\b",],"structs":["class\\s+([A-Za-z0-9_]*)",],"functions":["function\\s+([a-z_][A-Za-z0-9_]*)","\\b([a-z_][A-Za-z0-9_]*)\\s*\\("],}),Language(name:"C",icon:"\u{e61e}",extensions:["c","h"],keywords:["auto","break","case","char","const","continue","default","do","double","else","enum","extern","float","for","goto","if","int","long","register","return","short","signed","sizeof","static","struct","switch","typedef","union","unsigned","void","volatile","while","printf","fscanf","scanf","fputsf","exit","stderr","malloc","calloc","bool","realloc","free","strlen","size_t",],definitions:{"comments":["(?m)(//.*)$","(?ms)(/\\*.*?\\*/)",],"strings":["(\".*?\")",],"characters":["('.')","('\\\\.')",],"digits":["\\b(\\d+.\\d+|\\d+)","\\b(\\d+.\\d+(?:f|))",],"booleans":["\\b(true)\\b","\\b(false)\\b",],"functions":["(int|bool|void|char|double|long|short|size_t)\\s+([a-z_][A-Za-z0-9_]*)\\s*\\(",],"structs":["struct\\s+([A-Za-z0-9_]*)\\s*",],"attributes":["^\\s*(#.*?)\\s",],"headers":["(<.*?>)",],}),],clipboard:Clipboard(osc52:false,copy_command:"",paste_command:"",),)
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn folder(name: &str) -> PathBuf {
+        // Make an empty folder to lay out config files in
+        let folder = env::temp_dir().join(format!("ox-config-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn partial_theme() {
+        // Highlights that a theme leaves out keep the colours from the config
+        let folder = folder("partial_theme");
+        let theme = folder.join("partial.ron");
+        fs::write(
+            &theme,
+            "(theme:Theme(editor_bg:(0,0,0),editor_fg:(1,1,1),status_bg:(2,2,2),\
+             status_fg:(3,3,3),line_number_fg:(4,4,4)),highlights:{\"comments\":(9,9,9)})",
+        )
+        .unwrap();
+        let config = folder.join("ox.ron");
+        fs::write(
+            &config,
+            format!("(general:General(theme:\"{}\"))", theme.display()),
+        )
+        .unwrap();
+        let (config, status) = Reader::read(&[config.to_string_lossy().to_string()]);
+        assert!(matches!(status, Status::Success), "{:?}", status);
+        assert_eq!(config.theme.editor_bg, Colour::Rgb((0, 0, 0)));
+        assert_eq!(
+            config.highlights["comments"].fg,
+            Some(Colour::Rgb((9, 9, 9)))
+        );
+        assert_eq!(
+            config.highlights["headers"].fg,
+            Some(Colour::Rgb((47, 141, 252)))
+        );
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn layers() {
+        // Later files win, but a project can't choose the commands that are run for it
+        let folder = folder("layers");
+        let system = folder.join("system.ron");
+        fs::write(
+            &system,
+            "(general:General(tab_width:2,colours:Ansi256,privilege_helper:\"doas\"),\
+             clipboard:Clipboard(osc52:false,copy_command:\"copy\",paste_command:\"paste\"),\
+             highlights:{\"comments\":(1,2,3)})",
+        )
+        .unwrap();
+        let user = folder.join("user.ron");
+        fs::write(&user, "(general:General(tab_width:3,modal:true))").unwrap();
+        let project = folder.join(PROJECT_CONFIG);
+        fs::write(
+            &project,
+            "(general:General(hard_tabs:true,privilege_helper:\"evil\"),\
+             clipboard:Clipboard(osc52:true,copy_command:\"evil\",paste_command:\"evil\"),\
+             languages:[Language(name:\"Rust\",general:General(tab_width:8))])",
+        )
+        .unwrap();
+        let layers: Vec<String> = [system, user, project]
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        let (config, status) = Reader::read(&layers);
+        assert!(matches!(status, Status::Success), "{:?}", status);
+        assert_eq!(config.general.tab_width, 3);
+        assert!(config.general.modal);
+        assert!(config.general.hard_tabs);
+        assert_eq!(config.general.colours, Colours::Ansi256);
+        assert_eq!(config.general.privilege_helper, "doas");
+        assert!(!config.clipboard.osc52);
+        assert_eq!(config.clipboard.copy_command, "copy");
+        assert_eq!(
+            config.highlights["comments"].fg,
+            Some(Colour::Rgb((1, 2, 3)))
+        );
+        // Languages keep what they change and take everything else from the general settings
+        let rust = config.general("rs");
+        assert_eq!(rust.tab_width, 8);
+        assert!(rust.hard_tabs);
+        assert_eq!(rust.colours, Colours::Ansi256);
+        assert_eq!(config.general("py").tab_width, 3);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn default_colours() {
+        // Leaving out how many colours to use works them out from the terminal
+        let folder = folder("default_colours");
+        let user = folder.join("user.ron");
+        fs::write(&user, "(general:General(tab_width:3))").unwrap();
+        let (config, status) = Reader::read(&[user.to_string_lossy().to_string()]);
+        assert!(matches!(status, Status::Success), "{:?}", status);
+        assert_eq!(config.general.colours, Colours::Auto);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
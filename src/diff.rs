@@ -0,0 +1,219 @@
+// Diff.rs - For showing the differences between two versions of a file
+use std::fmt::Write;
+
+// How many unchanged lines to show around each change
+const CONTEXT: usize = 3;
+
+// The most pairs of lines to compare before treating everything in between as changed
+const LIMIT: usize = 4_000_000;
+
+// What happened to a line between the old and the new version
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Same,   // The line is in both versions
+    Remove, // The line is only in the old version
+    Add,    // The line is only in the new version
+}
+
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    // Write out the differences between two texts in the unified diff format
+    let edits = edits(old, new);
+    let mut result = format!("--- {old_name}\n+++ {new_name}\n");
+    // Find the line numbers that each edit comes after
+    let mut numbers = vec![(0, 0)];
+    for (edit, _) in &edits {
+        let (old, new) = numbers[numbers.len() - 1];
+        numbers.push(match edit {
+            Edit::Same => (old + 1, new + 1),
+            Edit::Remove => (old + 1, new),
+            Edit::Add => (old, new + 1),
+        });
+    }
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&i| edits[i].0 != Edit::Same)
+        .collect();
+    let mut i = 0;
+    while i < changes.len() {
+        // Group together changes that are close enough for their context to overlap
+        let mut last = i;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= CONTEXT * 2 + 1 {
+            last += 1;
+        }
+        let start = changes[i].saturating_sub(CONTEXT);
+        let end = (changes[last] + CONTEXT + 1).min(edits.len());
+        let (old_start, new_start) = numbers[start];
+        let (old_end, new_end) = numbers[end];
+        let _ = writeln!(
+            result,
+            "@@ -{} +{} @@",
+            range(old_start, old_end),
+            range(new_start, new_end)
+        );
+        for (edit, line) in &edits[start..end] {
+            result.push(match edit {
+                Edit::Same => ' ',
+                Edit::Remove => '-',
+                Edit::Add => '+',
+            });
+            result.push_str(line);
+            result.push('\n');
+        }
+        i = last + 1;
+    }
+    result
+}
+
+fn range(start: usize, end: usize) -> String {
+    // Write which lines a hunk covers, leaving out the length of a single line as diff does
+    match end - start {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        length => format!("{},{length}", start + 1),
+    }
+}
+
+fn lines(text: &str) -> Vec<&str> {
+    // Split a text into its lines, where an empty text has none at all
+    if text.is_empty() {
+        vec![]
+    } else {
+        text.split('\n').collect()
+    }
+}
+
+fn edits<'a>(old: &'a str, new: &'a str) -> Vec<(Edit, &'a str)> {
+    // Work out the fewest lines to remove and add to turn one text into another
+    let old = lines(old);
+    let new = lines(new);
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut result: Vec<(Edit, &str)> = old[..prefix].iter().map(|l| (Edit::Same, *l)).collect();
+    if a.len().saturating_mul(b.len()) > LIMIT {
+        // Too big to compare line by line, so replace the whole middle
+        result.extend(a.iter().map(|l| (Edit::Remove, *l)));
+        result.extend(b.iter().map(|l| (Edit::Add, *l)));
+    } else {
+        // Find the longest run of lines the middles have in common, from the end backwards
+        let width = b.len() + 1;
+        let mut common = vec![0_usize; (a.len() + 1) * width];
+        for x in (0..a.len()).rev() {
+            for y in (0..b.len()).rev() {
+                common[x * width + y] = if a[x] == b[y] {
+                    common[(x + 1) * width + y + 1] + 1
+                } else {
+                    common[(x + 1) * width + y].max(common[x * width + y + 1])
+                };
+            }
+        }
+        let (mut x, mut y) = (0, 0);
+        while x < a.len() || y < b.len() {
+            if x < a.len() && y < b.len() && a[x] == b[y] {
+                result.push((Edit::Same, a[x]));
+                x += 1;
+                y += 1;
+            } else if y < b.len()
+                && (x == a.len() || common[x * width + y + 1] > common[(x + 1) * width + y])
+            {
+                result.push((Edit::Add, b[y]));
+                y += 1;
+            } else {
+                result.push((Edit::Remove, a[x]));
+                x += 1;
+            }
+        }
+    }
+    result.extend(old[old.len() - suffix..].iter().map(|l| (Edit::Same, *l)));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> String {
+        // Compare two texts, named as diff -u --label old --label new would name them
+        unified(old, new, "old", "new")
+    }
+
+    #[test]
+    fn insert() {
+        assert_eq!(
+            diff("a\nb\nc\nd\ne\nf\ng\nh", "a\nb\nc\nd\nX\ne\nf\ng\nh"),
+            "--- old\n+++ new\n@@ -2,6 +2,7 @@\n b\n c\n d\n+X\n e\n f\n g\n"
+        );
+        assert_eq!(
+            diff("a\nb\nc", "X\na\nb\nc"),
+            "--- old\n+++ new\n@@ -1,3 +1,4 @@\n+X\n a\n b\n c\n"
+        );
+        assert_eq!(
+            diff("a\nb\nc", "a\nb\nc\nX"),
+            "--- old\n+++ new\n@@ -1,3 +1,4 @@\n a\n b\n c\n+X\n"
+        );
+    }
+
+    #[test]
+    fn delete() {
+        assert_eq!(
+            diff("a\nb\nc\nd\ne\nf\ng\nh", "a\nb\nc\ne\nf\ng\nh"),
+            "--- old\n+++ new\n@@ -1,7 +1,6 @@\n a\n b\n c\n-d\n e\n f\n g\n"
+        );
+    }
+
+    #[test]
+    fn single_lines() {
+        assert_eq!(diff("a", "b"), "--- old\n+++ new\n@@ -1 +1 @@\n-a\n+b\n");
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            diff("", "a\nb"),
+            "--- old\n+++ new\n@@ -0,0 +1,2 @@\n+a\n+b\n"
+        );
+        assert_eq!(
+            diff("a\nb", ""),
+            "--- old\n+++ new\n@@ -1,2 +0,0 @@\n-a\n-b\n"
+        );
+        assert_eq!(diff("same", "same"), "--- old\n+++ new\n");
+    }
+
+    #[test]
+    fn adjacent_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14";
+        // Six unchanged lines between changes are shared as the context of one hunk
+        assert_eq!(
+            diff(old, "1\nX\n3\n4\n5\n6\n7\n8\nY\n10\n11\n12\n13\n14"),
+            "--- old\n+++ new\n@@ -1,12 +1,12 @@\n 1\n-2\n+X\n 3\n 4\n 5\n 6\n 7\n 8\n-9\n+Y\n 10\n 11\n 12\n"
+        );
+        // Any more and the changes get hunks of their own
+        assert_eq!(
+            diff(old, "1\nX\n3\n4\n5\n6\n7\n8\n9\nY\n11\n12\n13\n14"),
+            "--- old\n+++ new\n@@ -1,5 +1,5 @@\n 1\n-2\n+X\n 3\n 4\n 5\n@@ -7,7 +7,7 @@\n 7\n 8\n 9\n-10\n+Y\n 11\n 12\n 13\n"
+        );
+    }
+
+    #[test]
+    fn too_big_to_compare() {
+        // Past the limit, everything between the common start and end is replaced
+        let old: Vec<String> = (0..2100).map(|i| format!("old {i}")).collect();
+        let new: Vec<String> = (0..2100).map(|i| format!("new {i}")).collect();
+        let old = format!("start\n{}\nend", old.join("\n"));
+        let new = format!("start\n{}\nend", new.join("\n"));
+        let result = diff(&old, &new);
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines[2], "@@ -1,2102 +1,2102 @@");
+        assert_eq!(lines[3], " start");
+        assert_eq!(lines[4], "-old 0");
+        assert_eq!(lines[2104], "+new 0");
+        assert_eq!(lines.last(), Some(&" end"));
+    }
+}
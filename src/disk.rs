@@ -0,0 +1,243 @@
+// Disk.rs - For safely writing documents to disk and noticing when others change them
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::{chown, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::time::SystemTime;
+
+// What a file was like when it was last read or written by ox
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamp {
+    pub modified: Option<SystemTime>, // When the file was last written to
+    pub hash: u64,                    // A hash of the contents of the file
+}
+
+impl Stamp {
+    pub fn new(bytes: &[u8], metadata: &Metadata) -> Self {
+        // Note down the contents of a file and when it was written
+        Self {
+            modified: metadata.modified().ok(),
+            hash: hash(bytes),
+        }
+    }
+    pub fn read(path: &str) -> Option<Self> {
+        // Note down what a file is like now
+        let metadata = fs::metadata(path).ok()?;
+        let bytes = fs::read(path).ok()?;
+        Some(Self::new(&bytes, &metadata))
+    }
+}
+
+pub fn changed(path: &str, stamp: Option<Stamp>) -> bool {
+    // Check if a file was changed by something else since ox last read or wrote it
+    match (stamp, fs::metadata(path)) {
+        (Some(stamp), Ok(metadata)) => {
+            // Only compare the contents when the time suggests something happened
+            metadata.modified().ok() != stamp.modified
+                && Stamp::read(path).is_some_and(|now| now.hash != stamp.hash)
+        }
+        // A file has appeared where there wasn't one before
+        (None, Ok(_)) => true,
+        _ => false,
+    }
+}
+
+pub fn write<F>(path: &str, backup: bool, contents: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    // Write a file without leaving it half written if something goes wrong part way through
+    let target = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let metadata = fs::metadata(&target).ok();
+    if backup && metadata.is_some() {
+        fs::copy(&target, format!("{}~", target.display()))?;
+    }
+    // Files with other names linked to them are written in place to keep the links, and files
+    // that can't be written to are too, so that renaming doesn't get around their permissions
+    let linked = metadata.as_ref().is_some_and(|m| m.nlink() > 1);
+    let protected = read_only(&target.to_string_lossy());
+    let created = if linked || protected {
+        None
+    } else {
+        // New files get the usual permissions, and others are kept private until they're copied
+        temporary(&target, if metadata.is_some() { 0o600 } else { 0o666 })
+    };
+    let Some((temp, file)) = created else {
+        // Fall back to writing in place, such as when the folder can't be written to
+        let mut writer = BufWriter::new(File::create(&target)?);
+        contents(&mut writer)?;
+        return writer.flush();
+    };
+    let result = (|| {
+        let mut writer = BufWriter::new(file);
+        contents(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        if let Some(metadata) = &metadata {
+            // Keep the permissions and, where allowed, the owner of the original file
+            fs::set_permissions(&temp, metadata.permissions())?;
+            let _ = chown(&temp, Some(metadata.uid()), Some(metadata.gid()));
+        }
+        fs::rename(&temp, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+pub fn read_only(path: &str) -> bool {
+    // Check if a file exists but can't be written to, without changing it
+    Path::new(path).exists() && OpenOptions::new().append(true).open(path).is_err()
+}
+
+pub fn needs_password(helper: &str) -> io::Result<bool> {
+    // Check if a program such as sudo will ask for a password before running a command
+    let status = command(helper)?
+        .args(["-n", "true"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    Ok(!status.success())
+}
+
+pub fn takes_password(helper: &str) -> bool {
+    // Check if a program can be given a password through its input, which only sudo can
+    helper
+        .split_whitespace()
+        .next()
+        .and_then(|program| Path::new(program).file_name())
+        .is_some_and(|name| name == "sudo")
+}
+
+pub fn write_elevated<F>(
+    path: &str,
+    helper: &str,
+    password: Option<&str>,
+    contents: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    // Write a file through a program such as sudo, copying it over from a file only we can read
+    let temp = env::temp_dir().join(format!("ox-{}.tmp", process::id()));
+    let _ = fs::remove_file(&temp);
+    let result = (|| {
+        // Only make a new file, so a link left there by someone else is never followed
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temp)?;
+        let mut writer = BufWriter::new(file);
+        contents(&mut writer)?;
+        writer.flush()?;
+        // The input only holds the password, so nothing else can be mistaken for another try
+        let mut command = command(helper)?;
+        if password.is_some() {
+            command.args(["-S", "-p", ""]);
+        }
+        let mut child = command
+            .arg("dd")
+            .arg(format!("if={}", temp.display()))
+            .arg(format!("of={path}"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let (Some(mut input), Some(password)) = (child.stdin.take(), password) {
+            let _ = writeln!(input, "{password}");
+        }
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            let error = String::from_utf8_lossy(&output.stderr);
+            let error = error.lines().map(str::trim).rev().find(|l| !l.is_empty());
+            Err(io::Error::other(
+                error.unwrap_or("the privilege helper failed").to_string(),
+            ))
+        }
+    })();
+    let _ = fs::remove_file(&temp);
+    result
+}
+
+fn command(helper: &str) -> io::Result<Command> {
+    // Get ready to run a program such as sudo, along with any arguments it was given
+    let mut words = helper.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no privilege helper is set"))?;
+    let mut command = Command::new(program);
+    command.args(words);
+    Ok(command)
+}
+
+fn temporary(target: &Path, mode: u32) -> Option<(PathBuf, File)> {
+    // Make a hidden file next to a file to write it to first, never opening one already there
+    let name = target
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+    for attempt in 0..100 {
+        let temp = target.with_file_name(format!(".{}.{}.{attempt}.tmp", name, process::id()));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temp)
+        {
+            Ok(file) => return Some((temp, file)),
+            // Something else has the name, such as a link left there by someone else
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    // Hash the contents of a file
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn planted_link() {
+        // A link where the temporary file would go is left alone and never written through
+        let folder = env::temp_dir().join(format!("ox-disk-{}", process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("secret.txt");
+        let elsewhere = folder.join("elsewhere.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        fs::write(&elsewhere, "untouched").unwrap();
+        let planted = folder.join(format!(".secret.txt.{}.0.tmp", process::id()));
+        symlink(&elsewhere, &planted).unwrap();
+        write(&path.to_string_lossy(), false, |file| {
+            file.write_all(b"new")
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&elsewhere).unwrap(), "untouched");
+        assert!(fs::symlink_metadata(&planted)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -1,12 +1,22 @@
 // Document.rs - For managing external files
-use crate::config::{Reader, Status, TokenType};
+use crate::config::{General, Reader, Status, TokenType};
+use crate::disk::{self, Stamp};
 use crate::editor::OFFSET;
-use crate::util::raw_to_grapheme;
-use crate::{Event, EventStack, Position, Row, Size};
+use crate::editorconfig;
+use crate::format::Format;
+use crate::grammar::Grammar;
+use crate::highlight::Cache;
+use crate::journal;
+use crate::pane::View;
+use crate::row::Gutter;
+use crate::textmate::TextMate;
+use crate::util::{column_after, grapheme_width, raw_to_grapheme, text_end};
+use crate::{Buffer, Event, EventStack, Position, Size};
 use regex::Regex;
+use std::io;
+use std::rc::Rc;
 use std::{cmp, fs};
 use termion::event::Key;
-use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 // For holding the info in the command line
 pub struct CommandLine {
@@ -22,80 +32,111 @@ pub enum Type {
 }
 
 // Document struct (class) to manage files and text
+#[allow(clippy::struct_excessive_bools)]
 pub struct Document {
-    pub rows: Vec<Row>,         // For holding the contents of the document
-    pub path: String,           // For holding the path to the document
-    pub name: String,           // For holding the name of the document
-    pub dirty: bool,            // True if the current document has been edited
-    pub cmd_line: CommandLine,  // For holding the command line
-    pub line_offset: usize,     // For holding a line number offset
-    pub undo_stack: EventStack, // For holding the undo event stack
-    pub redo_stack: EventStack, // For holding the redo event stack
-    pub regex: Vec<TokenType>,  // For holding regular expressions
-    pub icon: String,           // For holding the icon of the document
-    pub kind: String,           // For holding the icon of the document
-    pub show_welcome: bool,     // Whether to show welcome in the document
-    pub cursor: Position,       // For holding the raw cursor location
-    pub offset: Position,       // For holding the offset on the X and Y axes
-    pub graphemes: usize,       // For holding the special grapheme cursor
+    pub buffer: Buffer,             // For holding the contents of the document
+    pub path: String,               // For holding the path to the document
+    pub name: String,               // For holding the name of the document
+    pub dirty: bool,                // True if the current document has been edited
+    pub outdated: bool,             // True if the file changed on disk while it had edits
+    pub read_only: bool,            // True if the file can't be written to without privileges
+    pub orphan: Option<String>,     // For holding edits left in the journal by a session that ended
+    pub journaled: Option<Rc<str>>, // For holding the text last written to the journal
+    pub cmd_line: CommandLine,      // For holding the command line
+    pub line_offset: usize,         // For holding a line number offset
+    pub general: General,           // For holding the settings for the document's language
+    pub config: usize,              // For holding which of the editor's configs applies
+    pub format: Format,             // For holding how the document is written to disk
+    pub stamp: Option<Stamp>,       // For holding what the file was like when last read or written
+    pub undo_stack: EventStack,     // For holding the undo event stack
+    pub redo_stack: EventStack,     // For holding the redo event stack
+    pub regex: Vec<TokenType>,      // For holding regular expressions
+    pub grammar: Option<Grammar>,   // For holding the parser, if the language has a grammar
+    pub textmate: Option<TextMate>, // For holding the TextMate grammar, if one was loaded
+    pub cache: Cache,               // For holding the syntax highlighting of each line
+    pub icon: String,               // For holding the icon of the document
+    pub kind: String,               // For holding the icon of the document
+    pub show_welcome: bool,         // Whether to show welcome in the document
+    pub cursor: Position,           // For holding the raw cursor location
+    pub offset: Position,           // For holding the offset on the X and Y axes
+    pub graphemes: usize,           // For holding the special grapheme cursor
+    pub mark: Option<Position>,     // For holding the anchor of the selection
 }
 
 // Add methods to the document struct
 impl Document {
     pub fn new(config: &Reader, status: &Status) -> Self {
         // Create a new, empty document
+        let general = config.general("");
         Self {
-            rows: vec![Row::from("")],
+            buffer: Buffer::new("", general.tab_width),
             name: String::from("[No name]"),
             dirty: false,
+            outdated: false,
+            read_only: false,
+            orphan: None,
+            journaled: None,
             cmd_line: Document::config_to_commandline(&status),
             path: String::new(),
             line_offset: config.general.line_number_padding_right
                 + config.general.line_number_padding_left,
+            general,
+            config: 0,
+            format: Format::default(),
+            stamp: None,
             undo_stack: EventStack::new(),
             redo_stack: EventStack::new(),
             regex: Reader::get_syntax_regex(&config, ""),
+            grammar: Grammar::new(config, ""),
+            textmate: TextMate::new(config, ""),
+            cache: Cache::default(),
             icon: String::new(),
             kind: String::new(),
             show_welcome: true,
             graphemes: 0,
             cursor: Position { x: 0, y: OFFSET },
             offset: Position { x: 0, y: 0 },
+            mark: None,
         }
     }
     pub fn open(config: &Reader, status: &Status, path: &str) -> Option<Self> {
         // Create a new document from a path
-        if let Ok(file) = fs::read_to_string(path) {
-            // File exists
-            let mut file = file.split('\n').collect::<Vec<&str>>();
-            // Handle newline on last line
-            if let Some(line) = file.iter().last() {
-                if line.is_empty() {
-                    let _ = file.pop();
-                }
-            }
-            // Handle empty document by automatically inserting a row
-            if file.is_empty() {
-                file.push("");
-            }
+        if let (Ok(bytes), Ok(metadata)) = (fs::read(path), fs::metadata(path)) {
+            // File exists, work out its line endings and encoding
+            let stamp = Some(Stamp::new(&bytes, &metadata));
+            let read_only = disk::read_only(path);
+            let (format, file) = Format::detect(&bytes);
+            let (general, format) = Self::settings(config, path, Some(format));
             let ext = path.split('.').last().unwrap_or(&"");
             Some(Self {
-                rows: file.iter().map(|row| Row::from(*row)).collect(),
+                buffer: Buffer::new(&file, general.tab_width),
                 name: path.to_string(),
                 dirty: false,
+                outdated: false,
+                read_only,
+                orphan: None,
+                journaled: None,
                 cmd_line: Document::config_to_commandline(&status),
                 path: path.to_string(),
                 line_offset: config.general.line_number_padding_right
                     + config.general.line_number_padding_left,
+                general,
+                config: 0,
+                format,
+                stamp,
                 undo_stack: EventStack::new(),
                 redo_stack: EventStack::new(),
                 regex: Reader::get_syntax_regex(&config, ext),
+                grammar: Grammar::new(config, ext),
+                textmate: TextMate::new(config, ext),
+                cache: Cache::default(),
                 kind: Self::identify(path).0.to_string(),
                 icon: Self::identify(path).1.to_string(),
                 show_welcome: false,
                 graphemes: 0,
                 cursor: Position { x: 0, y: OFFSET },
                 offset: Position { x: 0, y: 0 },
+                mark: None,
             })
         } else {
             // File doesn't exist
@@ -109,26 +150,77 @@ impl Document {
         } else {
             // Create blank document
             let ext = path.split('.').last().unwrap_or(&"");
+            let (general, format) = Self::settings(config, path, None);
             Self {
-                rows: vec![Row::from("")],
+                buffer: Buffer::new("", general.tab_width),
                 name: path.to_string(),
                 path: path.to_string(),
                 dirty: false,
+                outdated: false,
+                read_only: false,
+                orphan: None,
+                journaled: None,
                 cmd_line: Document::config_to_commandline(&status),
                 line_offset: config.general.line_number_padding_right
                     + config.general.line_number_padding_left,
+                general,
+                config: 0,
+                format,
+                stamp: None,
                 undo_stack: EventStack::new(),
                 redo_stack: EventStack::new(),
                 regex: Reader::get_syntax_regex(&config, ext),
+                grammar: Grammar::new(config, ext),
+                textmate: TextMate::new(config, ext),
+                cache: Cache::default(),
                 kind: Self::identify(path).0.to_string(),
                 icon: Self::identify(path).1.to_string(),
                 show_welcome: false,
                 graphemes: 0,
                 cursor: Position { x: 0, y: OFFSET },
                 offset: Position { x: 0, y: 0 },
+                mark: None,
             }
         }
     }
+    pub fn reconfigure(&mut self, config: &Reader) {
+        // Pick up a new config, working out how to highlight the document again
+        let ext = self.path.rsplit('.').next().unwrap_or_default();
+        let format;
+        (self.general, format) = Self::settings(config, &self.path, Some(self.format));
+        // Keep the line endings that the document has been converted to
+        self.format = Format {
+            line_ending: self.format.line_ending,
+            ..format
+        };
+        self.buffer.tab_width = self.general.tab_width;
+        self.line_offset =
+            self.general.line_number_padding_right + self.general.line_number_padding_left;
+        self.regex = Reader::get_syntax_regex(config, ext);
+        self.grammar = Grammar::new(config, ext);
+        self.textmate = TextMate::new(config, ext);
+        self.cache.invalidate(0);
+    }
+    pub fn gutter(&self) -> Gutter {
+        // Get how the line numbers are laid out for the document
+        Gutter {
+            width: self.line_offset,
+            left: self.general.line_number_padding_left,
+            right: self.general.line_number_padding_right,
+        }
+    }
+    fn settings(config: &Reader, path: &str, read: Option<Format>) -> (General, Format) {
+        // Work out the settings for a file from its language and any .editorconfig files
+        let ext = path.rsplit('.').next().unwrap_or_default();
+        let mut general = config.general(ext);
+        let mut format = read.unwrap_or_default();
+        editorconfig::apply(path, &mut general, &mut format);
+        if let Some(read) = read {
+            // Files stay in the encoding they were read in, which might not hold all their text
+            format.charset = read.charset;
+        }
+        (general, format)
+    }
     pub fn set_command_line(&mut self, text: String, msg: Type) {
         // Function to update the command line
         self.cmd_line = CommandLine { text, msg };
@@ -152,7 +244,7 @@ impl Document {
         match direction {
             Key::Down => {
                 // Move the cursor down
-                if self.cursor.y + self.offset.y + 1 - (OFFSET) < self.rows.len() {
+                if self.cursor.y + self.offset.y + 1 - (OFFSET) < self.buffer.len_lines() {
                     // If the proposed move is within the length of the document
                     if self.cursor.y == term.height.saturating_sub(3) {
                         self.offset.y = self.offset.y.saturating_add(1);
@@ -177,21 +269,14 @@ impl Document {
             }
             Key::Right => {
                 // Move the cursor right
-                let line = &self.rows[self.cursor.y + self.offset.y - OFFSET];
+                let line = self.buffer.line(self.cursor.y + self.offset.y - OFFSET);
                 // Work out the width of the character to traverse
-                let mut jump = 1;
-                if let Some(chr) = line.ext_chars().get(self.cursor.x + self.offset.x) {
-                    jump = UnicodeWidthStr::width(*chr);
-                }
+                let jump = line.width_at(self.cursor.x + self.offset.x).unwrap_or(1);
                 // Check the proposed move is within the current line length
                 if line.length() > self.cursor.x + self.offset.x {
-                    // Check for normal width character
-                    let indicator1 =
-                        self.cursor.x == term.width.saturating_sub(self.line_offset + jump + 1);
-                    // Check for half broken unicode character
-                    let indicator2 =
-                        self.cursor.x == term.width.saturating_sub(self.line_offset + jump);
-                    if indicator1 || indicator2 {
+                    // Scroll instead when the character would end past the edge of the screen
+                    let edge = term.width.saturating_sub(self.line_offset + 1);
+                    if self.cursor.x + jump >= edge {
                         self.offset.x = self.offset.x.saturating_add(jump);
                     } else {
                         self.cursor.x = self.cursor.x.saturating_add(jump);
@@ -201,17 +286,15 @@ impl Document {
             }
             Key::Left => {
                 // Move the cursor left
-                let line = &self.rows[self.cursor.y + self.offset.y - OFFSET];
+                let line = self.buffer.line(self.cursor.y + self.offset.y - OFFSET);
                 // Work out the width of the character to traverse
-                let mut jump = 1;
-                if let Some(chr) = line
-                    .ext_chars()
-                    .get((self.cursor.x + self.offset.x).saturating_sub(1))
-                {
-                    jump = UnicodeWidthStr::width(*chr);
-                }
-                if self.cursor.x == 0 {
-                    self.offset.x = self.offset.x.saturating_sub(jump);
+                let jump = line
+                    .width_at((self.cursor.x + self.offset.x).saturating_sub(1))
+                    .unwrap_or(1);
+                if self.cursor.x < jump {
+                    // Scroll back far enough to show the whole character
+                    self.offset.x = self.offset.x.saturating_sub(jump - self.cursor.x);
+                    self.cursor.x = 0;
                 } else {
                     self.cursor.x = self.cursor.x.saturating_sub(jump);
                 }
@@ -233,7 +316,10 @@ impl Document {
             Key::PageDown => {
                 // Move cursor to the bottom of the screen
                 self.cursor.y = cmp::min(
-                    self.rows.len().saturating_sub(1).saturating_add(OFFSET),
+                    self.buffer
+                        .len_lines()
+                        .saturating_sub(1)
+                        .saturating_add(OFFSET),
                     term.height.saturating_sub(3) as usize,
                 );
                 self.snap_cursor(term);
@@ -250,13 +336,10 @@ impl Document {
                 // Move cursor to the end of the line
                 let cursor = self.cursor;
                 let offset = self.offset;
-                let line = self.rows[cursor.y + offset.y - OFFSET].clone();
+                let line = self.buffer.line(cursor.y + offset.y - OFFSET);
                 if line.length() >= term.width.saturating_sub(self.line_offset) {
                     // Work out the width of the character to traverse
-                    let mut jump = 1;
-                    if let Some(chr) = line.ext_chars().get(line.length()) {
-                        jump = UnicodeWidthStr::width(*chr);
-                    }
+                    let jump = line.width_at(line.length()).unwrap_or(1);
                     self.offset.x = line
                         .length()
                         .saturating_add(jump + self.line_offset + 1)
@@ -272,8 +355,11 @@ impl Document {
     }
     pub fn snap_cursor(&mut self, term: &Size) {
         // Snap the cursor to the end of the row when outside
-        let current = self.rows[self.cursor.y + self.offset.y - OFFSET].clone();
-        if current.length() <= self.cursor.x + self.offset.x {
+        let length = self
+            .buffer
+            .line(self.cursor.y + self.offset.y - OFFSET)
+            .length();
+        if length <= self.cursor.x + self.offset.x {
             // If the cursor is out of bounds
             self.leap_cursor(Key::Home, term);
             self.leap_cursor(Key::End, term);
@@ -281,7 +367,7 @@ impl Document {
     }
     pub fn prevent_unicode_hell(&mut self) {
         // Make sure that the cursor isn't inbetween a unicode character
-        let line = &self.rows[self.cursor.y + self.offset.y - OFFSET];
+        let line = self.buffer.line(self.cursor.y + self.offset.y - OFFSET);
         if line.length() > self.cursor.x + self.offset.x {
             // As long as the cursor is within range
             let boundaries = line.boundaries();
@@ -296,9 +382,12 @@ impl Document {
     }
     pub fn recalculate_graphemes(&mut self) {
         // Recalculate the grapheme cursor after moving up and down
-        let current = self.rows[self.cursor.y + self.offset.y - OFFSET].clone();
-        let jumps = current.get_jumps();
+        let jumps = self
+            .buffer
+            .line(self.cursor.y + self.offset.y - OFFSET)
+            .get_jumps();
         let mut counter = 0;
+        self.graphemes = 0;
         for (mut counter2, i) in jumps.into_iter().enumerate() {
             if counter == self.cursor.x + self.offset.x {
                 break;
@@ -308,32 +397,237 @@ impl Document {
             counter += i;
         }
     }
-    pub fn recalculate_offset(&mut self, config: &Reader) {
+    pub fn position(&self) -> Position {
+        // Get the position of the cursor within the document
+        Position {
+            x: self.cursor.x + self.offset.x,
+            y: self.cursor.y + self.offset.y - OFFSET,
+        }
+    }
+    pub fn place_cursor(&mut self, pos: Position, term: &Size) {
+        // Move the cursor to a position in the document, scrolling only when needed
+        let max_y = term.height.saturating_sub(3 + OFFSET);
+        let max_x = term.width.saturating_sub(self.line_offset + 2);
+        if pos.y < self.offset.y {
+            self.offset.y = pos.y;
+        } else if pos.y > self.offset.y + max_y {
+            self.offset.y = pos.y - max_y;
+        }
+        if pos.x < self.offset.x {
+            self.offset.x = pos.x;
+        } else if pos.x > self.offset.x + max_x {
+            self.offset.x = pos.x - max_x;
+        }
+        self.cursor.y = pos.y - self.offset.y + OFFSET;
+        self.cursor.x = pos.x - self.offset.x;
+        self.prevent_unicode_hell();
+        self.recalculate_graphemes();
+    }
+    pub fn view(&self) -> View {
+        // Get the part of the document that is being looked at
+        View {
+            cursor: self.cursor,
+            offset: self.offset,
+            graphemes: self.graphemes,
+            mark: self.mark,
+        }
+    }
+    pub fn set_view(&mut self, view: View) {
+        // Look at another part of the document
+        self.cursor = view.cursor;
+        self.offset = view.offset;
+        self.graphemes = view.graphemes;
+        self.mark = view.mark;
+    }
+    pub fn fit_view(&mut self, term: &Size) {
+        // Keep the cursor and selection within the text and on the screen
+        let mark = self.mark.map(|mark| self.clamp(mark));
+        self.mark = mark;
+        let pos = self.clamp(self.position());
+        self.place_cursor(pos, term);
+    }
+    fn clamp(&self, pos: Position) -> Position {
+        // Move a position back inside the document
+        let y = cmp::min(pos.y, self.buffer.len_lines().saturating_sub(1));
+        let x = cmp::min(pos.x, self.buffer.line(y).length());
+        Position { x, y }
+    }
+    pub fn scroll(&mut self, direction: Key, amount: usize, term: &Size) {
+        // Scroll the view, keeping the cursor on its line while that line is visible
+        let pos = self.position();
+        let last = self.buffer.len_lines().saturating_sub(1);
+        let max_y = term.height.saturating_sub(3 + OFFSET);
+        match direction {
+            Key::Up => self.offset.y = self.offset.y.saturating_sub(amount),
+            Key::Down => self.offset.y = cmp::min(self.offset.y + amount, last),
+            _ => (),
+        }
+        let y = cmp::min(pos.y.clamp(self.offset.y, self.offset.y + max_y), last);
+        let x = cmp::min(pos.x, self.buffer.line(y).length());
+        self.place_cursor(Position { x, y }, term);
+    }
+    pub fn select(&mut self, direction: Key, term: &Size) {
+        // Extend the selection from the mark with a movement key
+        if self.mark.is_none() {
+            self.mark = Some(self.position());
+        }
+        match direction {
+            Key::Left | Key::Right | Key::Up | Key::Down => self.move_cursor(direction, term),
+            _ => self.leap_cursor(direction, term),
+        }
+    }
+    pub fn selection(&self) -> Option<(Position, Position)> {
+        // Get the start and end of the selection, in document order
+        let mark = self.mark?;
+        let head = self.position();
+        match (mark.y, mark.x).cmp(&(head.y, head.x)) {
+            cmp::Ordering::Less => Some((mark, head)),
+            cmp::Ordering::Greater => Some((head, mark)),
+            cmp::Ordering::Equal => None,
+        }
+    }
+    pub fn selected_columns(&self, y: usize) -> Option<(usize, usize)> {
+        // Work out which columns of a line are selected
+        let (start, end) = self.selection()?;
+        if y < start.y || y > end.y {
+            return None;
+        }
+        Some((
+            if y == start.y { start.x } else { 0 },
+            if y == end.y { end.x } else { usize::MAX },
+        ))
+    }
+    pub fn delete_selection(&mut self, term: &Size) -> bool {
+        // Remove the selected text as a single undoable event
+        if let Some((start, end)) = self.selection() {
+            let start_x = raw_to_grapheme(start.x, &self.buffer.line(start.y));
+            let end_x = raw_to_grapheme(end.x, &self.buffer.line(end.y));
+            let text = self.buffer.remove_range((start_x, start.y), (end_x, end.y));
+            self.undo_stack.commit();
+            self.undo_stack.push(Event::DeleteText(start, text));
+            self.mark = None;
+            self.dirty = true;
+            self.show_welcome = false;
+            self.place_cursor(start, term);
+            true
+        } else {
+            self.mark = None;
+            false
+        }
+    }
+    pub fn selected_text(&self) -> Option<String> {
+        // Get the text within the selection
+        let (start, end) = self.selection()?;
+        let start_x = raw_to_grapheme(start.x, &self.buffer.line(start.y));
+        let end_x = raw_to_grapheme(end.x, &self.buffer.line(end.y));
+        Some(self.buffer.range((start_x, start.y), (end_x, end.y)))
+    }
+    pub fn select_line(&mut self, term: &Size) {
+        // Select the current line along with a newline
+        let y = self.position().y;
+        if y + 1 < self.buffer.len_lines() {
+            self.mark = Some(Position { x: 0, y });
+            self.place_cursor(Position { x: 0, y: y + 1 }, term);
+        } else if y > 0 {
+            let x = self.buffer.line(y - 1).length();
+            self.mark = Some(Position { x, y: y - 1 });
+            self.leap_cursor(Key::End, term);
+        } else {
+            self.mark = Some(Position { x: 0, y });
+            self.leap_cursor(Key::End, term);
+        }
+    }
+    pub fn paste(&mut self, text: &str, term: &Size) {
+        // Insert a block of text, replacing the selection, as a single undoable event
+        self.undo_stack.commit();
+        self.delete_selection(term);
+        let pos = self.position();
+        self.buffer.insert_str(text, self.graphemes, pos.y);
+        self.undo_stack
+            .push(Event::InsertText(pos, text.to_string()));
+        self.undo_stack.commit();
+        self.redo_stack.empty();
+        self.dirty = true;
+        self.show_welcome = false;
+        self.place_cursor(text_end(pos, text, self.general.tab_width), term);
+    }
+    pub fn indent_selection(&mut self, term: &Size) {
+        // Indent every line that the selection touches
+        if let (Some((start, end)), Some(mark)) = (self.selection(), self.mark) {
+            let head = self.position();
+            // Leave out the last line if none of it is selected
+            let last = if end.x == 0 && end.y > start.y {
+                end.y - 1
+            } else {
+                end.y
+            };
+            let indent = self.indent();
+            let width = column_after(&indent, 0, self.general.tab_width);
+            self.undo_stack.commit();
+            for y in start.y..=last {
+                self.buffer.insert_str(&indent, 0, y);
+                self.undo_stack
+                    .push(Event::InsertText(Position { x: 0, y }, indent.clone()));
+            }
+            self.undo_stack.commit();
+            // Shift the selection along with the text
+            let shift = |p: Position| Position {
+                x: if p.y <= last { p.x + width } else { p.x },
+                y: p.y,
+            };
+            self.mark = Some(shift(mark));
+            self.place_cursor(shift(head), term);
+        }
+    }
+    pub fn recalculate_offset(&mut self) {
         // Calculate the offset for the line numbers
-        self.line_offset = self.rows.len().to_string().len()
-            + config.general.line_number_padding_right
-            + config.general.line_number_padding_left;
+        self.line_offset = self.buffer.len_lines().to_string().len()
+            + self.general.line_number_padding_right
+            + self.general.line_number_padding_left;
     }
-    pub fn character(&mut self, c: char, term: &Size, config: &Reader) {
+    pub fn highlight(&mut self, config: &Reader, until: usize) {
+        // Bring the syntax highlighting up to date, redoing it from the first edited line
+        if let Some(y) = self.buffer.take_edited() {
+            self.cache.invalidate(y);
+        }
+        let edits = self.buffer.take_edits();
+        if let Some(grammar) = &mut self.grammar {
+            if let Some(y) = grammar.parse(&self.buffer, edits.as_deref()) {
+                self.cache.invalidate(y);
+            }
+        }
+        self.cache.update(
+            &self.buffer,
+            &self.regex,
+            self.grammar.as_ref(),
+            self.textmate.as_ref(),
+            &config.highlights,
+            until,
+        );
+    }
+    pub fn character(&mut self, c: char, term: &Size) {
         // The user pressed a character key
         self.dirty = true;
         self.show_welcome = false;
         match c {
-            '\n' => self.return_key(term), // The user pressed the return key
+            '\t' if self.selection().is_some() => self.indent_selection(term),
+            '\n' => {
+                // The user pressed the return key
+                self.delete_selection(term);
+                self.return_key(term);
+            }
             '\t' => {
-                // The user pressed the tab key
-                self.tab(&config, term);
-                self.undo_stack.push(Event::InsertTab(Position {
-                    x: self.cursor.x + self.offset.x,
-                    y: self.cursor.y + self.offset.y - OFFSET,
-                }));
+                // The user pressed the tab key, which inserts a tab character or spaces
+                let pos = self.position();
+                self.tab(term);
+                self.undo_stack.push(Event::InsertTab(pos));
             }
             _ => {
                 // Other characters
                 // TODO: Update relavent lines here
-                self.dirty = true;
-                self.show_welcome = false;
-                self.rows[self.cursor.y + self.offset.y - OFFSET].insert(c, self.graphemes);
+                self.delete_selection(term);
+                self.buffer
+                    .insert(c, self.graphemes, self.cursor.y + self.offset.y - OFFSET);
                 self.undo_stack.push(Event::InsertMid(
                     Position {
                         x: self.cursor.x + self.offset.x,
@@ -351,11 +645,25 @@ impl Document {
         // Wipe the redo stack to avoid conflicts
         self.redo_stack.empty();
     }
-    pub fn tab(&mut self, config: &Reader, term: &Size) {
-        // Insert a tab
+    pub fn indent(&self) -> String {
+        // Get the text that a single level of indentation is made of
+        if self.general.hard_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.general.tab_width)
+        }
+    }
+    pub fn tab(&mut self, term: &Size) {
+        // Insert a tab, as a tab character when tabs aren't expanded
         // TODO: Update relavent lines here
-        for _ in 0..config.general.tab_width {
-            self.rows[self.cursor.y + self.offset.y - OFFSET].insert(' ', self.graphemes);
+        let (c, count) = if self.general.hard_tabs {
+            ('\t', 1)
+        } else {
+            (' ', self.general.tab_width)
+        };
+        for _ in 0..count {
+            self.buffer
+                .insert(c, self.graphemes, self.cursor.y + self.offset.y - OFFSET);
             self.move_cursor(Key::Right, term);
         }
     }
@@ -366,19 +674,22 @@ impl Document {
         // TODO: Update relavent lines here
         if self.cursor.x + self.offset.x == 0 {
             // Return key pressed at the start of the line
-            self.rows
-                .insert(self.cursor.y + self.offset.y - OFFSET, Row::from(""));
+            self.buffer
+                .insert_line(self.cursor.y + self.offset.y - OFFSET, "");
             self.undo_stack.push(Event::ReturnStart(Position {
                 x: self.cursor.x + self.offset.x,
                 y: self.cursor.y + self.offset.y - OFFSET,
             }));
             self.move_cursor(Key::Down, term);
         } else if self.cursor.x + self.offset.x
-            == self.rows[self.cursor.y + self.offset.y - OFFSET].length()
+            == self
+                .buffer
+                .line(self.cursor.y + self.offset.y - OFFSET)
+                .length()
         {
             // Return key pressed at the end of the line
-            self.rows
-                .insert(self.cursor.y + self.offset.y + 1 - OFFSET, Row::from(""));
+            self.buffer
+                .insert_line(self.cursor.y + self.offset.y + 1 - OFFSET, "");
             self.undo_stack.push(Event::ReturnEnd(Position {
                 x: self.cursor.x + self.offset.x,
                 y: self.cursor.y + self.offset.y - OFFSET,
@@ -388,18 +699,15 @@ impl Document {
             self.recalculate_graphemes();
         } else {
             // Return key pressed in the middle of the line
-            let current = self.rows[self.cursor.y + self.offset.y - OFFSET].chars();
-            let before = Row::from(&current[..self.graphemes].join("")[..]);
-            let after = Row::from(&current[self.graphemes..].join("")[..]);
-            self.rows
-                .insert(self.cursor.y + self.offset.y + 1 - OFFSET, after);
-            self.rows[self.cursor.y + self.offset.y - OFFSET] = before.clone();
+            let breakpoint = self.cursor.x + self.offset.x;
+            self.buffer
+                .split_line(self.graphemes, self.cursor.y + self.offset.y - OFFSET);
             self.undo_stack.push(Event::ReturnMid(
                 Position {
                     x: self.cursor.x + self.offset.x,
                     y: self.cursor.y + self.offset.y - OFFSET,
                 },
-                before.length(),
+                breakpoint,
             ));
             self.move_cursor(Key::Down, term);
             self.leap_cursor(Key::Home, term);
@@ -412,17 +720,20 @@ impl Document {
         self.dirty = true;
         self.show_welcome = false;
         // TODO: Update relavent lines here
-        if self.cursor.x + self.offset.x == 0 && self.cursor.y + self.offset.y - OFFSET != 0 {
+        if self.delete_selection(term) {
+            // Backspace over a selection
+            self.undo_stack.commit();
+        } else if self.cursor.x + self.offset.x == 0 && self.cursor.y + self.offset.y - OFFSET != 0
+        {
             // Backspace at the start of a line
-            let current = self.rows[self.cursor.y + self.offset.y - OFFSET]
-                .string
-                .clone();
-            let prev = self.rows[self.cursor.y + self.offset.y - 1 - OFFSET].clone();
-            self.rows[self.cursor.y + self.offset.y - 1 - OFFSET] =
-                Row::from(&(prev.string.clone() + &current)[..]);
-            self.rows.remove(self.cursor.y + self.offset.y - OFFSET);
+            let prev = self
+                .buffer
+                .line(self.cursor.y + self.offset.y - 1 - OFFSET)
+                .length();
+            self.buffer
+                .join_line(self.cursor.y + self.offset.y - 1 - OFFSET);
             self.move_cursor(Key::Up, term);
-            self.cursor.x = prev.length();
+            self.cursor.x = prev;
             self.recalculate_graphemes();
             self.undo_stack.push(Event::BackspaceStart(Position {
                 x: self.cursor.x + self.offset.x,
@@ -432,91 +743,103 @@ impl Document {
         } else {
             // Backspace in the middle of a line
             self.move_cursor(Key::Left, term);
-            let ch = self.rows[self.cursor.y + self.offset.y - OFFSET].clone();
-            self.rows[self.cursor.y + self.offset.y - OFFSET].delete(self.graphemes);
-            if let Some(ch) = ch.chars().get(self.graphemes) {
-                if let Ok(ch) = ch.parse() {
-                    self.undo_stack.push(Event::BackspaceMid(
-                        Position {
-                            x: self.cursor.x + self.offset.x,
-                            y: self.cursor.y + self.offset.y - OFFSET,
-                        },
-                        ch,
-                    ));
-                }
+            if let Some(ch) = self
+                .buffer
+                .delete(self.graphemes, self.cursor.y + self.offset.y - OFFSET)
+            {
+                self.undo_stack.push(Event::BackspaceMid(
+                    Position {
+                        x: self.cursor.x + self.offset.x,
+                        y: self.cursor.y + self.offset.y - OFFSET,
+                    },
+                    ch,
+                ));
             }
         }
     }
-    pub fn redo(&mut self, config: &Reader, term: &Size) {
+    fn remove_text(&mut self, start: Position, end: Position) {
+        // Remove the text between two positions in the document
+        let start_x = raw_to_grapheme(start.x, &self.buffer.line(start.y));
+        let end_x = raw_to_grapheme(end.x, &self.buffer.line(end.y));
+        self.buffer.remove_range((start_x, start.y), (end_x, end.y));
+    }
+    pub fn redo(&mut self, term: &Size) {
         // Redo an action
+        self.mark = None;
         if let Some(events) = self.redo_stack.pop() {
             for event in events.iter().rev() {
                 // Reverse the undo action
                 match event {
                     // TODO: Update relavent lines here
                     Event::InsertTab(pos) => {
-                        self.cursor.y = pos.y - self.offset.y + OFFSET;
-                        self.cursor.x =
-                            pos.x.saturating_sub(config.general.tab_width) - self.offset.x;
-                        self.recalculate_graphemes();
-                        self.tab(&config, &term);
+                        self.place_cursor(*pos, term);
+                        self.tab(term);
                     }
                     Event::InsertMid(pos, c) => {
-                        let c_len = UnicodeWidthChar::width(*c).map_or(0, |c| c);
-                        self.cursor.y = pos.y - self.offset.y + OFFSET;
-                        self.cursor.x = pos.x.saturating_add(c_len) - self.offset.x;
-                        self.recalculate_graphemes();
-                        self.rows[pos.y].insert(*c, pos.x);
+                        let c_len = grapheme_width(&c.to_string(), pos.x, self.general.tab_width);
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        self.buffer.insert(*c, x, pos.y);
+                        self.place_cursor(
+                            Position {
+                                x: pos.x + c_len,
+                                y: pos.y,
+                            },
+                            term,
+                        );
                     }
                     Event::BackspaceMid(pos, _) => {
-                        self.cursor.y = pos.y - self.offset.y + OFFSET;
-                        self.cursor.x = pos.x - self.offset.x;
-                        self.recalculate_graphemes();
-                        self.rows[pos.y].delete(pos.x);
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        self.buffer.delete(x, pos.y);
+                        self.place_cursor(*pos, term);
                     }
                     Event::ReturnEnd(pos) => {
                         self.cursor.y = pos.y - self.offset.y + OFFSET;
                         self.cursor.x = pos.x - self.offset.x;
                         self.recalculate_graphemes();
-                        self.rows.insert(pos.y + 1, Row::from(""));
+                        self.buffer.insert_line(pos.y + 1, "");
                         self.move_cursor(Key::Down, &term);
                     }
                     Event::ReturnStart(pos) => {
                         self.cursor.y = pos.y - self.offset.y + OFFSET;
                         self.cursor.x = pos.x - self.offset.x;
                         self.recalculate_graphemes();
-                        self.rows.insert(pos.y, Row::from(""));
+                        self.buffer.insert_line(pos.y, "");
                         self.move_cursor(Key::Down, &term);
                     }
                     Event::ReturnMid(pos, breakpoint) => {
                         self.cursor.y = pos.y - self.offset.y + OFFSET;
                         self.cursor.x = pos.x - self.offset.x;
                         self.recalculate_graphemes();
-                        let current = self.rows[pos.y].string.clone();
-                        let before = Row::from(&current[..*breakpoint]);
-                        let after = Row::from(&current[*breakpoint..]);
-                        self.rows.insert(pos.y + 1, after);
-                        self.rows[pos.y] = before;
+                        let x = raw_to_grapheme(*breakpoint, &self.buffer.line(pos.y));
+                        self.buffer.split_line(x, pos.y);
                         self.move_cursor(Key::Down, &term);
                         self.leap_cursor(Key::Home, &term);
                     }
                     Event::BackspaceStart(pos) => {
                         self.cursor.y = pos.y - self.offset.y + OFFSET;
                         self.recalculate_graphemes();
-                        let current = self.rows[pos.y + 1].string.clone();
-                        let prev = self.rows[pos.y].clone();
-                        self.rows[pos.y + 1] = Row::from(&(prev.string.clone() + &current)[..]);
-                        self.rows.remove(pos.y);
+                        let prev = self.buffer.line(pos.y).length();
+                        self.buffer.join_line(pos.y);
                         self.move_cursor(Key::Up, &term);
-                        self.cursor.x = prev.length();
+                        self.cursor.x = prev;
                         self.recalculate_graphemes();
                     }
                     Event::UpdateLine(pos, _, after) => {
-                        self.rows[*pos] = *after.clone();
+                        self.buffer.set_line(*pos, after);
                         self.snap_cursor(&term);
                         self.prevent_unicode_hell();
                         self.recalculate_graphemes();
                     }
+                    Event::InsertText(pos, text) => {
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        self.buffer.insert_str(text, x, pos.y);
+                        self.place_cursor(text_end(*pos, text, self.general.tab_width), term);
+                    }
+                    Event::DeleteText(pos, text) => {
+                        let end = text_end(*pos, text, self.general.tab_width);
+                        self.remove_text(*pos, end);
+                        self.place_cursor(*pos, term);
+                    }
                 }
                 self.dirty = true;
                 self.show_welcome = false;
@@ -526,69 +849,79 @@ impl Document {
             self.set_command_line("Empty Redo Stack".to_string(), Type::Error);
         }
     }
-    pub fn undo(&mut self, config: &Reader, term: &Size) {
+    pub fn undo(&mut self, term: &Size) {
         // Initiate an undo action
         self.undo_stack.commit();
+        self.mark = None;
         if let Some(events) = self.undo_stack.pop() {
             for event in &events {
                 // Undo the previous action
                 match event {
                     // TODO: Update relavent lines here
                     Event::InsertTab(pos) => {
-                        for i in 1..=config.general.tab_width {
-                            self.rows[pos.y].delete(pos.x - i);
-                            self.move_cursor(Key::Left, &term);
+                        // A tab character goes in one, and spaces go one by one
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        let count = match self.buffer.line(pos.y).chars().get(x) {
+                            Some(&"\t") => 1,
+                            _ => self.general.tab_width,
+                        };
+                        for _ in 0..count {
+                            self.buffer.delete(x, pos.y);
                         }
+                        self.place_cursor(*pos, term);
                     }
-                    Event::InsertMid(pos, c) => {
-                        let c_len = UnicodeWidthChar::width(*c).map_or(0, |c| c);
-                        self.cursor.y = pos.y - self.offset.y + OFFSET;
-                        self.cursor.x = pos.x.saturating_add(c_len) - self.offset.x;
-                        self.recalculate_graphemes();
-                        let string = self.rows[pos.y].string.clone();
-                        self.rows[pos.y].delete(raw_to_grapheme(pos.x, &string));
-                        for _ in 0..c_len {
-                            self.move_cursor(Key::Left, term);
-                        }
+                    Event::InsertMid(pos, _) => {
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        self.buffer.delete(x, pos.y);
+                        self.place_cursor(*pos, term);
                     }
                     Event::BackspaceMid(pos, c) => {
-                        self.rows[pos.y].insert(*c, pos.x);
-                        self.move_cursor(Key::Right, term);
+                        let c_len = grapheme_width(&c.to_string(), pos.x, self.general.tab_width);
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        self.buffer.insert(*c, x, pos.y);
+                        self.place_cursor(
+                            Position {
+                                x: pos.x + c_len,
+                                y: pos.y,
+                            },
+                            term,
+                        );
                     }
                     Event::ReturnEnd(pos) => {
-                        self.rows.remove(pos.y + 1);
+                        self.buffer.remove_line(pos.y + 1);
                         self.move_cursor(Key::Up, term);
                         self.leap_cursor(Key::End, term);
                     }
                     Event::ReturnStart(pos) => {
-                        self.rows.remove(pos.y);
+                        self.buffer.remove_line(pos.y);
                         self.move_cursor(Key::Up, term);
                     }
-                    Event::ReturnMid(pos, breakpoint) => {
-                        let current = self.rows[pos.y].string.clone();
-                        let after = self.rows[pos.y + 1].string.clone();
-                        self.rows.remove(pos.y);
-                        self.rows[pos.y] = Row::from(&(current + &after)[..]);
-                        self.move_cursor(Key::Up, term);
-                        self.leap_cursor(Key::Home, term);
-                        for _ in 0..*breakpoint {
-                            self.move_cursor(Key::Right, term);
-                        }
+                    Event::ReturnMid(pos, _) => {
+                        self.buffer.join_line(pos.y);
+                        self.place_cursor(*pos, term);
                     }
                     Event::BackspaceStart(pos) => {
-                        let before = Row::from(&self.rows[pos.y].string[..pos.x]);
-                        let after = Row::from(&self.rows[pos.y].string[pos.x..]);
-                        self.rows[pos.y] = after;
-                        self.rows.insert(pos.y, before);
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        self.buffer.split_line(x, pos.y);
                         self.move_cursor(Key::Down, term);
                         self.leap_cursor(Key::Home, term);
                     }
                     Event::UpdateLine(pos, before, _) => {
-                        self.rows[*pos] = *before.clone();
+                        self.buffer.set_line(*pos, before);
                         self.snap_cursor(term);
                         self.prevent_unicode_hell();
                         self.recalculate_graphemes();
                     }
+                    Event::InsertText(pos, text) => {
+                        let end = text_end(*pos, text, self.general.tab_width);
+                        self.remove_text(*pos, end);
+                        self.place_cursor(*pos, term);
+                    }
+                    Event::DeleteText(pos, text) => {
+                        let x = raw_to_grapheme(pos.x, &self.buffer.line(pos.y));
+                        self.buffer.insert_str(text, x, pos.y);
+                        self.place_cursor(text_end(*pos, text, self.general.tab_width), term);
+                    }
                 }
                 self.dirty = true;
                 self.show_welcome = false;
@@ -598,19 +931,73 @@ impl Document {
             self.set_command_line("Empty Undo Stack".to_string(), Type::Error);
         }
     }
-    pub fn save(&self) -> std::io::Result<()> {
+    pub fn save(&mut self) -> io::Result<()> {
         // Save a file
-        fs::write(&self.path, self.render())
+        let path = self.path.clone();
+        self.save_as(&path)
+    }
+    pub fn save_as(&mut self, path: &str) -> io::Result<()> {
+        // Save a file to a specific path, streaming the buffer to disk through a temporary file
+        let (format, buffer) = (self.format, &self.buffer);
+        disk::write(path, self.general.backup, |file| format.write(buffer, file))?;
+        self.stamp = Stamp::read(path);
+        self.read_only = false;
+        self.forget_journal();
+        Ok(())
+    }
+    pub fn save_elevated(&mut self, helper: &str, password: Option<&str>) -> io::Result<()> {
+        // Save a file that can't be written to through a program such as sudo
+        let (format, buffer) = (self.format, &self.buffer);
+        disk::write_elevated(&self.path, helper, password, |file| {
+            format.write(buffer, file)
+        })?;
+        self.stamp = Stamp::read(&self.path);
+        self.forget_journal();
+        Ok(())
     }
-    pub fn save_as(&self, path: &str) -> std::io::Result<()> {
-        // Save a file to a specific path
-        fs::write(path, self.render())
+    pub fn reload(&mut self, config: &Reader) -> io::Result<()> {
+        // Read the file again after something else wrote to it, leaving the cursor where it was
+        let bytes = fs::read(&self.path)?;
+        let metadata = fs::metadata(&self.path)?;
+        let (format, file) = Format::detect(&bytes);
+        self.format = Self::settings(config, &self.path, Some(format)).1;
+        self.replace(&file);
+        self.stamp = Some(Stamp::new(&bytes, &metadata));
+        self.read_only = disk::read_only(&self.path);
+        self.dirty = false;
+        self.outdated = false;
+        self.forget_journal();
+        Ok(())
+    }
+    pub fn recover(&mut self, text: &str) {
+        // Take on the edits left in a journal, which still need to be saved
+        self.replace(text);
+        self.dirty = true;
+        self.orphan = None;
+        self.journaled = Some(self.buffer.text());
+    }
+    fn replace(&mut self, text: &str) {
+        // Swap out the whole text of the document, starting the undo history again
+        self.buffer = Buffer::new(text, self.general.tab_width);
+        self.undo_stack = EventStack::new();
+        self.redo_stack = EventStack::new();
+        self.cache.invalidate(0);
+    }
+    pub fn forget_journal(&mut self) {
+        // Delete the journal once its edits have been saved or thrown away
+        if self.journaled.take().is_some() {
+            journal::remove(&self.path);
+        }
+    }
+    pub fn changed_on_disk(&self) -> bool {
+        // Check if something else has written to the file since it was opened or saved
+        !self.path.is_empty() && disk::changed(&self.path, self.stamp)
     }
     pub fn scan(&self, needle: &str, offset: usize) -> Vec<Position> {
         // Find all the points where "needle" occurs
         let mut result = vec![];
         if let Ok(re) = Regex::new(needle) {
-            for (i, row) in self.rows.iter().enumerate() {
+            for (i, row) in self.buffer.lines().enumerate() {
                 for o in re.find_iter(&row.string) {
                     result.push(Position {
                         x: o.start(),
@@ -621,15 +1008,6 @@ impl Document {
         }
         result
     }
-    pub fn render(&self) -> String {
-        // Render the lines of a document for writing
-        self.rows
-            .iter()
-            .map(|x| x.string.clone())
-            .collect::<Vec<String>>()
-            .join("\n")
-            + "\n"
-    }
     pub fn identify(path: &str) -> (&str, &str) {
         // Identify which type of file the current buffer is
         match path.split('.').last() {
@@ -684,3 +1062,120 @@ impl Document {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{Charset, LineEnding};
+    use std::{env, process};
+
+    const TERM: Size = Size {
+        width: 80,
+        height: 24,
+    };
+
+    fn document(text: &str, hard_tabs: bool) -> Document {
+        // Make a document holding some text, with the default settings
+        let (config, status) = Reader::read(&[]);
+        let mut doc = Document::new(&config, &status);
+        doc.general.hard_tabs = hard_tabs;
+        doc.buffer = Buffer::new(text, doc.general.tab_width);
+        doc
+    }
+
+    fn at(doc: &mut Document, x: usize, y: usize) {
+        // Move the cursor to a column of a line
+        doc.place_cursor(Position { x, y }, &TERM);
+    }
+
+    fn state(doc: &Document) -> (String, usize, usize) {
+        // Get the text of a document along with where the cursor is
+        let pos = doc.position();
+        (doc.buffer.text().to_string(), pos.x, pos.y)
+    }
+
+    #[test]
+    fn backspace_after_tab() {
+        let mut doc = document("\tabc", true);
+        at(&mut doc, 6, 0);
+        doc.backspace(&TERM);
+        assert_eq!(state(&doc), ("\tac".to_string(), 5, 0));
+        doc.undo(&TERM);
+        assert_eq!(state(&doc), ("\tabc".to_string(), 6, 0));
+        doc.redo(&TERM);
+        assert_eq!(state(&doc), ("\tac".to_string(), 5, 0));
+    }
+
+    #[test]
+    fn type_after_tab() {
+        let mut doc = document("\tabc", true);
+        at(&mut doc, 5, 0);
+        doc.character('x', &TERM);
+        assert_eq!(state(&doc), ("\taxbc".to_string(), 6, 0));
+        doc.undo(&TERM);
+        assert_eq!(state(&doc), ("\tabc".to_string(), 5, 0));
+        doc.redo(&TERM);
+        assert_eq!(state(&doc), ("\taxbc".to_string(), 6, 0));
+    }
+
+    #[test]
+    fn tab_key() {
+        // Tabs are undone and redone in one go, whether they are tab characters or spaces
+        let mut doc = document("a\tb", true);
+        at(&mut doc, 5, 0);
+        doc.character('\t', &TERM);
+        assert_eq!(state(&doc), ("a\tb\t".to_string(), 8, 0));
+        doc.undo(&TERM);
+        assert_eq!(state(&doc), ("a\tb".to_string(), 5, 0));
+        doc.redo(&TERM);
+        assert_eq!(state(&doc), ("a\tb\t".to_string(), 8, 0));
+        let mut doc = document("a\tb", false);
+        at(&mut doc, 4, 0);
+        doc.character('\t', &TERM);
+        assert_eq!(state(&doc), ("a\t    b".to_string(), 8, 0));
+        doc.undo(&TERM);
+        assert_eq!(state(&doc), ("a\tb".to_string(), 4, 0));
+        doc.redo(&TERM);
+        assert_eq!(state(&doc), ("a\t    b".to_string(), 8, 0));
+    }
+
+    #[test]
+    fn indent_with_tabs() {
+        // The selection moves along with the text by the width of the tab
+        let mut doc = document("ab\ncd", true);
+        doc.mark = Some(Position { x: 1, y: 0 });
+        at(&mut doc, 1, 1);
+        doc.character('\t', &TERM);
+        assert_eq!(state(&doc), ("\tab\n\tcd".to_string(), 5, 1));
+        let mark = doc.mark.unwrap();
+        assert_eq!((mark.x, mark.y), (5, 0));
+    }
+
+    #[test]
+    fn editorconfig_charset() {
+        // Files keep the encoding they were read in, and only new files take it from the folder
+        let folder = env::temp_dir().join(format!("ox-document-{}", process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(
+            folder.join(".editorconfig"),
+            "[*]\ncharset = latin1\nend_of_line = crlf\n",
+        )
+        .unwrap();
+        let path = folder.join("wide.txt").to_string_lossy().to_string();
+        fs::write(&path, "\u{100}\n").unwrap();
+        let (config, status) = Reader::read(&[]);
+        let mut doc = Document::open(&config, &status, &path).unwrap();
+        assert_eq!(doc.format.charset, Charset::Utf8);
+        assert_eq!(doc.format.line_ending, LineEnding::Crlf);
+        // Picking up the config again leaves conversions made by the user alone
+        doc.format.line_ending = LineEnding::Lf;
+        doc.reconfigure(&config);
+        assert_eq!(doc.format.charset, Charset::Utf8);
+        assert_eq!(doc.format.line_ending, LineEnding::Lf);
+        let new = folder.join("new.txt").to_string_lossy().to_string();
+        let doc = Document::from(&config, &status, &new);
+        assert_eq!(doc.format.charset, Charset::Latin1);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
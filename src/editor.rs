@@ -1,14 +1,31 @@
 // Editor.rs - Controls the editor and brings everything together
-use crate::config::{Reader, Status};
+use crate::clipboard::{copy_command, paste_command, KillRing};
+use crate::command::{complete, Command, Setting};
+use crate::config::{Colour, Reader, Status};
+use crate::diff;
+use crate::disk::{self, Stamp};
 use crate::document::Type;
-use crate::util::{is_ahead, is_behind, title, trim_end, Exp};
-use crate::{Document, Event, Row, Terminal, VERSION};
+use crate::format::{Charset, Format, LineEnding};
+use crate::journal;
+use crate::keys::input_name;
+use crate::modal::{self, Action, Feed, Modal, Mode, Motion, Operator, Place};
+use crate::pane::{Layout, Pane, Rect, Split, View};
+use crate::terminal::{Input, PASTE_END, PASTE_START};
+use crate::util::{absolute, is_ahead, is_behind, raw_to_grapheme, title, trim_end, Exp};
+use crate::watch::Watcher;
+use crate::{Buffer, Document, Event, Size, Terminal, VERSION};
 use clap::App;
 use regex::Regex;
-use std::time::{Duration, Instant};
-use std::{io::Error, thread};
-use termion::event::Key;
-use termion::input::{Keys, TermRead};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::{cmp, mem};
+use termion::event::{Event as TermEvent, Key, MouseButton, MouseEvent};
+use termion::input::{EventsAndRaw, TermReadEventsAndRaw};
 use termion::{async_stdin, color, style, AsyncReader};
 
 // Set up color resets
@@ -18,6 +35,18 @@ pub const RESET_FG: color::Fg<color::Reset> = color::Fg(color::Reset);
 // Set up offset rules
 pub const OFFSET: usize = 1;
 
+// The amount of lines to move per turn of the mouse wheel
+pub const SCROLL_AMOUNT: usize = 3;
+
+// The macro register to use when one isn't named
+pub const DEFAULT_REGISTER: &str = "default";
+
+// The most input a macro can replay, to catch macros that replay themselves
+pub const MACRO_LIMIT: usize = 100_000;
+
+// How often to write the unsaved edits of each document to its journal, in seconds
+pub const JOURNAL_PERIOD: u64 = 2;
+
 // Enum for holding prompt events
 enum PromptEvent {
     Update,
@@ -25,6 +54,9 @@ enum PromptEvent {
     KeyPress(Key),
 }
 
+// For finding the ways that the input to a prompt could be completed
+type Completer = dyn Fn(&str) -> Vec<String>;
+
 // For representing positions
 #[derive(Clone, Copy, Debug)]
 pub struct Position {
@@ -32,17 +64,58 @@ pub struct Position {
     pub y: usize,
 }
 
+// A config, along with the files it was read from
+struct Layered {
+    config: Reader,                    // Storage for configuration
+    status: Status,                    // Holding the status of the config
+    paths: Vec<String>,                // The config files, from the least to the most specific
+    modified: Vec<Option<SystemTime>>, // When each config file was last written to
+}
+
+impl Layered {
+    fn find(configs: &mut Vec<Self>, user: &str, file: Option<&str>) -> usize {
+        // Find the config for a file, reading it if no other file has the same config files
+        let paths = Reader::layers(user, file);
+        if let Some(i) = configs.iter().position(|layered| layered.paths == paths) {
+            return i;
+        }
+        let (config, status) = Reader::read(&paths);
+        configs.push(Self {
+            config,
+            status,
+            modified: modified(&paths),
+            paths,
+        });
+        configs.len() - 1
+    }
+}
+
 // The main editor struct
 pub struct Editor {
-    pub config: Reader,             // Storage for configuration
-    pub status: Status,             // Holding the status of the config
-    quit: bool,                     // Toggle for cleanly quitting the editor
-    term: Terminal,                 // For the handling of the terminal
-    doc: Vec<Document>,             // For holding our document
-    tab: usize,                     // Holds the number of the current tab
-    last_keypress: Option<Instant>, // For holding the time of the last input event
-    stdin: Keys<AsyncReader>,       // Asynchronous stdin
-    exp: Exp,                       // For holding expressions
+    configs: Vec<Layered>,               // The configs that the open documents use
+    config_file: String,                 // The user's config file, as it was given
+    quit: bool,                          // Toggle for cleanly quitting the editor
+    term: Terminal,                      // For the handling of the terminal
+    doc: Vec<Document>,                  // For holding our document
+    tab: usize,                          // Holds the number of the current tab
+    last_keypress: Option<Instant>,      // For holding the time of the last input event
+    stdin: EventsAndRaw<AsyncReader>,    // Asynchronous stdin
+    exp: Exp,                            // For holding expressions
+    clipboard: KillRing,                 // For holding cut and copied text across tabs
+    macros: HashMap<String, Vec<Input>>, // For holding recorded macros by register
+    recording: Option<String>,           // The register that input is being recorded into
+    recorded: Vec<Input>,                // For holding the input recorded so far
+    command_start: usize,                // How much input came before the current command
+    pending: VecDeque<Input>,            // For holding input waiting to be replayed
+    playing: bool,                       // True while a macro is being replayed
+    modal: Modal,                        // For holding the state of modal editing
+    panes: Vec<Pane>,                    // For holding the panes on the screen
+    layout: Layout,                      // How the panes are arranged
+    pane: usize,                         // Holds the number of the focused pane
+    area: Size,                          // The focused pane's size, laid out like the terminal
+    watcher: Watcher,                    // For noticing when other programs write to open files
+    idle: bool,                          // True while waiting for the next command
+    journal_time: Instant,               // When the journals were last written
 }
 
 // Implementing methods for our editor struct / class
@@ -52,28 +125,70 @@ impl Editor {
         let args = args.get_matches();
         // Set up the arguments
         let files: Vec<&str> = args.values_of("files").unwrap_or_default().collect();
-        let config = Reader::read(args.value_of("config").unwrap_or_default());
+        let config_file = args.value_of("config").unwrap_or_default().to_string();
+        // Each file takes the project config found by walking up from it
+        let mut configs = vec![];
         let mut documents = vec![];
         if files.is_empty() {
-            documents.push(Document::new(&config.0, &config.1));
+            let i = Layered::find(&mut configs, &config_file, None);
+            let mut doc = Document::new(&configs[i].config, &configs[i].status);
+            doc.config = i;
+            documents.push(doc);
         } else {
             for file in &files {
-                documents.push(Document::from(&config.0, &config.1, file));
+                let i = Layered::find(&mut configs, &config_file, Some(file));
+                let mut doc = Document::from(&configs[i].config, &configs[i].status, file);
+                doc.config = i;
+                documents.push(doc);
             }
         }
+        let macros = configs
+            .iter()
+            .flat_map(|layered| layered.config.registers.clone())
+            .collect();
+        let mut watcher = Watcher::new();
+        for file in &files {
+            watcher.watch(file);
+        }
+        let panes = vec![Pane {
+            doc: 0,
+            view: documents[0].view(),
+        }];
         // Create the new editor instance
-        Ok(Self {
+        let mut editor = Self {
             quit: false,
             // Display information about the config file into text for the status line
-            term: Terminal::new()?,
+            term: Terminal::new(configs[0].config.general.colours)?,
             tab: 0,
             doc: documents,
             last_keypress: None,
-            stdin: async_stdin().keys(),
-            config: config.0.clone(),
-            status: config.1,
+            stdin: async_stdin().events_and_raw(),
+            configs,
+            config_file,
             exp: Exp::new(),
-        })
+            clipboard: KillRing::default(),
+            macros,
+            recording: None,
+            recorded: vec![],
+            command_start: 0,
+            pending: VecDeque::new(),
+            playing: false,
+            modal: Modal::new(),
+            panes,
+            layout: Layout::Pane(0),
+            pane: 0,
+            area: Size {
+                width: 0,
+                height: 0,
+            },
+            watcher,
+            idle: false,
+            journal_time: Instant::now(),
+        };
+        for i in 0..editor.doc.len() {
+            editor.find_orphan(i);
+        }
+        Ok(editor)
     }
     pub fn run(&mut self) {
         // Run the editor instance
@@ -86,67 +201,840 @@ impl Editor {
     fn read_key(&mut self) -> Key {
         // Wait until a key is pressed and then return it
         loop {
-            if let Some(key) = self.stdin.next() {
-                // When a keypress was detected
+            if let Input::Key(key) = self.read_input() {
+                return key;
+            }
+        }
+    }
+    fn read_input(&mut self) -> Input {
+        // Wait until an input event occurs, replaying macros first, and record it
+        if let Some(input) = self.pending.pop_front() {
+            return input;
+        }
+        let input = self.read_event();
+        if self.recording.is_some() && !matches!(input, Input::Mouse(_)) {
+            self.recorded.push(input.clone());
+        }
+        input
+    }
+    fn read_event(&mut self) -> Input {
+        // Wait until an input event occurs and then return it
+        loop {
+            if let Some(event) = self.stdin.next() {
+                // When an input event was detected
                 self.last_keypress = Some(Instant::now());
-                if let Ok(key) = key {
-                    return key;
-                } else {
-                    continue;
+                match event {
+                    Ok((TermEvent::Unsupported(bytes), _)) if bytes == PASTE_START => {
+                        return Input::Paste(self.read_paste());
+                    }
+                    Ok((event, _)) => {
+                        if let Some(input) = Terminal::decode(event) {
+                            return input;
+                        }
+                    }
+                    Err(_) => (),
                 }
             } else {
                 // Run code that we want to run when the key isn't pressed
                 if self.term.check_resize() {
                     // The terminal has changed in size
-                    if self.doc[self.tab].cursor.y > self.term.size.height.saturating_sub(3) {
-                        // Prevent cursor going off the screen and breaking everything
-                        self.doc[self.tab].cursor.y = self.term.size.height.saturating_sub(3);
-                    }
+                    self.relayout();
                     // Re-render everything to the new size
                     self.update();
                 }
                 // Check for a period of inactivity
                 if let Some(time) = self.last_keypress {
                     // Check to see if it's over the config undo period
-                    if time.elapsed().as_secs() >= self.config.general.undo_period {
+                    if time.elapsed().as_secs() >= self.doc[self.tab].general.undo_period {
                         // Commit the undo changes to the stack
                         self.doc[self.tab].undo_stack.commit();
                         self.last_keypress = None;
                     }
                 }
+                // Pick up changes to the config files when asked to watch them
+                for i in 0..self.configs.len() {
+                    let layered = &self.configs[i];
+                    if layered.config.general.watch_config
+                        && modified(&layered.paths) != layered.modified
+                    {
+                        self.reload_config(i);
+                        self.update();
+                    }
+                }
+                // Pick up changes that other programs make to open files
+                let written = self.watcher.changed();
+                if !written.is_empty() {
+                    self.written_elsewhere(&written);
+                    self.update();
+                }
+                // Keep unsaved edits somewhere they can be recovered from after a crash
+                if self.journal_time.elapsed().as_secs() >= JOURNAL_PERIOD {
+                    self.write_journals();
+                    self.journal_time = Instant::now();
+                }
+                // Ask what to do about edits that clash, unless something else is being asked
+                if self.idle && self.doc[self.tab].orphan.is_some() {
+                    self.idle = false;
+                    self.orphan_prompt();
+                    self.idle = true;
+                    self.update();
+                } else if self.idle && self.doc[self.tab].outdated {
+                    self.idle = false;
+                    self.outdated_prompt();
+                    self.idle = true;
+                    self.update();
+                }
                 // FPS cap to stop using the entire CPU
                 thread::sleep(Duration::from_millis(16));
             }
         }
     }
+    fn read_paste(&mut self) -> String {
+        // Collect pasted text until the terminal sends the end marker
+        let mut result = String::new();
+        let mut carriage = false;
+        let mut last = Instant::now();
+        while last.elapsed() < Duration::from_secs(1) {
+            match self.stdin.next() {
+                Some(Ok((TermEvent::Unsupported(bytes), _))) if bytes == PASTE_END => break,
+                Some(Ok((TermEvent::Key(Key::Char(c)), raw))) => {
+                    // Treat a carriage return followed by a line feed as one newline
+                    if !(carriage && raw == b"\n") {
+                        result.push(c);
+                    }
+                    carriage = raw == b"\r";
+                    last = Instant::now();
+                }
+                Some(_) => last = Instant::now(),
+                None => thread::sleep(Duration::from_millis(5)),
+            }
+        }
+        result
+    }
     fn process_input(&mut self) {
         // Read a key and act on it
-        let key = self.read_key();
+        self.command_start = self.recorded.len();
+        self.idle = true;
+        let input = self.read_input();
+        self.idle = false;
+        let key = match input {
+            Input::Shift(key) => {
+                // Extend the selection
+                self.doc[self.tab].select(key, &self.area);
+                return;
+            }
+            Input::Paste(text) => {
+                // Insert the pasted text as one block
+                if self.config().general.modal && self.modal.mode == Mode::Insert {
+                    self.modal.inserted.push(Input::Paste(text.clone()));
+                }
+                let tab = self.doc[self.tab].indent();
+                self.doc[self.tab].paste(&text.replace('\t', &tab), &self.area);
+                return;
+            }
+            Input::Mouse(event) => {
+                self.mouse(event);
+                return;
+            }
+            Input::Key(key) => key,
+        };
+        if self.config().general.modal && self.modal_key(key) {
+            return;
+        }
+        if let Some(command) = self.config().keymap.get(&key).cloned() {
+            // Run the command that the key is bound to
+            self.execute(command);
+            return;
+        }
         match key {
-            Key::Char(c) => self.doc[self.tab].character(c, &self.term.size, &self.config),
-            Key::Backspace => self.doc[self.tab].backspace(&self.term.size),
-            Key::Ctrl('q') => self.quit(),
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('w') => self.save_as(),
-            Key::Ctrl('p') => self.save_all(),
-            Key::Ctrl('n') => self.new_document(),
-            Key::Ctrl('o') => self.open_document(),
-            Key::Ctrl('f') => self.search(),
-            Key::Ctrl('u') => self.doc[self.tab].undo(&self.config, &self.term.size),
-            Key::Ctrl('y') => self.doc[self.tab].redo(&self.config, &self.term.size),
-            Key::Ctrl('r') => self.replace(),
-            Key::Ctrl('a') => self.replace_all(),
-            Key::Ctrl('d') => self.prev_tab(),
-            Key::Ctrl('h') => self.next_tab(),
-            Key::Left | Key::Right | Key::Up | Key::Down => {
-                self.doc[self.tab].move_cursor(key, &self.term.size)
-            }
-            Key::PageDown | Key::PageUp | Key::Home | Key::End => {
-                self.doc[self.tab].leap_cursor(key, &self.term.size)
+            Key::Char(c) => self.doc[self.tab].character(c, &self.area),
+            Key::Backspace => self.doc[self.tab].backspace(&self.area),
+            _ => (),
+        }
+    }
+    fn modal_key(&mut self, key: Key) -> bool {
+        // Handle a key in modal editing, returning false if it should be handled as usual
+        if self.modal.mode == Mode::Insert {
+            if key == Key::Esc {
+                self.leave_insert();
+                return true;
+            }
+            if matches!(key, Key::Char(_) | Key::Backspace) {
+                self.modal.inserted.push(Input::Key(key));
+            }
+            return false;
+        }
+        match self.modal.feed(key) {
+            Feed::Action(action) => {
+                self.modal_action(action);
+                true
+            }
+            Feed::Pending | Feed::Invalid => true,
+            Feed::Unknown => false,
+        }
+    }
+    fn modal_action(&mut self, action: Action) {
+        // Carry out an action typed in normal or visual mode
+        match action {
+            Action::Move(motion, count) => self.modal_move(motion, count),
+            Action::Operate(operator, motion, count) => {
+                self.operate(operator, motion, count);
+                match operator {
+                    Operator::Change => self.modal.change = Some(action),
+                    Operator::Delete if motion != Motion::Selection => {
+                        self.modal.last_change = Some((action, vec![]));
+                    }
+                    _ => (),
+                }
+            }
+            Action::Insert(place) => {
+                self.modal_insert(place);
+                self.modal.change = Some(action);
+            }
+            Action::Paste(place, count) => {
+                self.modal_paste(place, count.unwrap_or(1));
+                self.modal.last_change = Some((action, vec![]));
+            }
+            Action::Undo => self.doc[self.tab].undo(&self.area),
+            Action::Redo => self.doc[self.tab].redo(&self.area),
+            Action::Repeat(count) => self.repeat(count),
+            Action::Visual if self.modal.mode != Mode::Visual => {
+                self.modal.mode = Mode::Visual;
+                self.doc[self.tab].mark = Some(self.doc[self.tab].position());
+            }
+            Action::Visual | Action::Escape => {
+                self.modal.mode = Mode::Normal;
+                self.doc[self.tab].mark = None;
+            }
+            Action::Command => self.command_mode(),
+        }
+    }
+    fn modal_move(&mut self, motion: Motion, count: Option<usize>) {
+        // Move the cursor, extending the selection in visual mode
+        let doc = &mut self.doc[self.tab];
+        let from = (doc.graphemes, doc.position().y);
+        let to = modal::target(&doc.buffer, from, motion, count);
+        if self.modal.mode != Mode::Visual {
+            doc.mark = None;
+        }
+        let pos = Position {
+            x: modal::width(&doc.buffer, to),
+            y: to.1,
+        };
+        doc.place_cursor(pos, &self.area);
+    }
+    fn operate(&mut self, operator: Operator, motion: Motion, count: Option<usize>) {
+        // Delete, change or yank the text that a motion moves over
+        let doc = &mut self.doc[self.tab];
+        let range = if motion == Motion::Selection {
+            let Some((start, end)) = doc.selection() else {
+                self.modal.mode = Mode::Normal;
+                return;
+            };
+            // The character under the cursor is part of the selection
+            let start_x = raw_to_grapheme(start.x, &doc.buffer.line(start.y));
+            let end_x = raw_to_grapheme(end.x, &doc.buffer.line(end.y));
+            modal::Range {
+                start: (start_x, start.y),
+                end: (cmp::min(end_x + 1, modal::len(&doc.buffer, end.y)), end.y),
+                lines: None,
+            }
+        } else {
+            let from = (doc.graphemes, doc.position().y);
+            modal::range(&doc.buffer, from, operator, motion, count)
+        };
+        let text = match range.lines {
+            Some((top, bottom)) => format!(
+                "{}\n",
+                doc.buffer
+                    .range((0, top), (modal::len(&doc.buffer, bottom), bottom))
+            ),
+            None => doc.buffer.range(range.start, range.end),
+        };
+        let start = Position {
+            x: modal::width(&doc.buffer, range.start),
+            y: range.start.1,
+        };
+        let end = Position {
+            x: modal::width(&doc.buffer, range.end),
+            y: range.end.1,
+        };
+        if operator == Operator::Yank {
+            if range.lines.is_none() {
+                doc.place_cursor(start, &self.area);
+            }
+        } else {
+            doc.undo_stack.commit();
+            self.modal.history = doc.undo_stack.len();
+            doc.mark = Some(start);
+            doc.place_cursor(end, &self.area);
+            doc.delete_selection(&self.area);
+            doc.undo_stack.commit();
+            doc.redo_stack.empty();
+        }
+        doc.mark = None;
+        self.modal.mode = Mode::Normal;
+        if !text.is_empty() {
+            self.send_to_host(&text);
+            self.clipboard.push(text);
+        }
+        if operator == Operator::Change {
+            self.modal.mode = Mode::Insert;
+            self.modal.inserted.clear();
+        }
+    }
+    fn modal_insert(&mut self, place: Place) {
+        // Move to where the text should go and start inserting
+        let doc = &mut self.doc[self.tab];
+        let size = &self.area;
+        doc.undo_stack.commit();
+        self.modal.history = doc.undo_stack.len();
+        match place {
+            Place::Before => (),
+            Place::After => {
+                if doc.graphemes < modal::len(&doc.buffer, doc.position().y) {
+                    doc.move_cursor(Key::Right, size);
+                }
+            }
+            Place::LineStart => {
+                let y = doc.position().y;
+                let x = modal::first_non_blank(&doc.buffer, y);
+                let x = modal::width(&doc.buffer, (x, y));
+                doc.place_cursor(Position { x, y }, size);
+            }
+            Place::LineEnd => doc.leap_cursor(Key::End, size),
+            Place::Below => {
+                doc.leap_cursor(Key::End, size);
+                doc.return_key(size);
+            }
+            Place::Above => {
+                doc.leap_cursor(Key::Home, size);
+                doc.return_key(size);
+                doc.move_cursor(Key::Up, size);
+            }
+        }
+        doc.redo_stack.empty();
+        self.modal.mode = Mode::Insert;
+        self.modal.inserted.clear();
+    }
+    fn leave_insert(&mut self) {
+        // Go back to normal mode, remembering the change so it can be repeated
+        self.modal.mode = Mode::Normal;
+        if let Some(change) = self.modal.change.take() {
+            self.modal.last_change = Some((change, mem::take(&mut self.modal.inserted)));
+        }
+        let doc = &mut self.doc[self.tab];
+        // Undo the whole change in one go
+        doc.undo_stack.commit();
+        doc.undo_stack.squash(self.modal.history);
+        if doc.graphemes > 0 {
+            doc.move_cursor(Key::Left, &self.area);
+        }
+    }
+    fn modal_paste(&mut self, place: Place, count: usize) {
+        // Put the clipboard after or before the cursor, or around the line for whole lines
+        let Some(text) = self.clipboard_text() else {
+            return;
+        };
+        let text = text.repeat(count);
+        let doc = &mut self.doc[self.tab];
+        let size = &self.area;
+        doc.mark = None;
+        if let Some(lines) = text.strip_suffix('\n') {
+            let y = doc.position().y;
+            let below = place == Place::After;
+            if below && y + 1 == doc.buffer.len_lines() {
+                doc.leap_cursor(Key::End, size);
+                doc.paste(&format!("\n{lines}"), size);
+            } else {
+                let y = if below { y + 1 } else { y };
+                doc.place_cursor(Position { x: 0, y }, size);
+                doc.paste(&text, size);
+            }
+            let y = if below { y + 1 } else { y };
+            doc.place_cursor(Position { x: 0, y }, size);
+        } else {
+            if place == Place::After && doc.graphemes < modal::len(&doc.buffer, doc.position().y) {
+                doc.move_cursor(Key::Right, size);
+            }
+            doc.paste(&text, size);
+            doc.move_cursor(Key::Left, size);
+        }
+    }
+    fn repeat(&mut self, count: Option<usize>) {
+        // Do the last change again, typing any inserted text again too
+        let Some((action, inserted)) = self.modal.last_change.clone() else {
+            return;
+        };
+        let action = match (action, count) {
+            (Action::Operate(operator, motion, _), Some(_)) => {
+                Action::Operate(operator, motion, count)
+            }
+            (Action::Paste(place, _), Some(_)) => Action::Paste(place, count),
+            (action, _) => action,
+        };
+        self.modal_action(action);
+        if self.modal.mode == Mode::Insert {
+            self.pending.push_front(Input::Key(Key::Esc));
+            for input in inserted.into_iter().rev() {
+                self.pending.push_front(input);
+            }
+        }
+    }
+    fn mouse(&mut self, event: MouseEvent) {
+        // Handle clicking, dragging and scrolling
+        match event {
+            MouseEvent::Press(MouseButton::WheelUp, x, y) => {
+                self.scroll_pane(
+                    Key::Up,
+                    x.saturating_sub(1) as usize,
+                    y.saturating_sub(1) as usize,
+                );
+            }
+            MouseEvent::Press(MouseButton::WheelDown, x, y) => {
+                self.scroll_pane(
+                    Key::Down,
+                    x.saturating_sub(1) as usize,
+                    y.saturating_sub(1) as usize,
+                );
+            }
+            MouseEvent::Press(MouseButton::Left, x, y) => {
+                let (x, y) = (x.saturating_sub(1) as usize, y.saturating_sub(1) as usize);
+                if y < OFFSET {
+                    // Switch to the tab that was clicked on
+                    self.click_tab(x);
+                    return;
+                }
+                if let Some(index) = self.pane_at(x, y) {
+                    // Focus the pane that was clicked on
+                    self.focus(index);
+                }
+                if let Some(pos) = self.screen_to_document(x, y) {
+                    // Move the cursor to where the click was
+                    self.doc[self.tab].mark = None;
+                    self.doc[self.tab].place_cursor(pos, &self.area);
+                }
+            }
+            MouseEvent::Hold(x, y) => {
+                // Drag out a selection from where the button was pressed
+                let (x, y) = (x.saturating_sub(1) as usize, y.saturating_sub(1) as usize);
+                if let Some(pos) = self.screen_to_document(x, y) {
+                    if self.doc[self.tab].mark.is_none() {
+                        self.doc[self.tab].mark = Some(self.doc[self.tab].position());
+                    }
+                    self.doc[self.tab].place_cursor(pos, &self.area);
+                }
             }
             _ => (),
         }
     }
+    fn screen_to_document(&self, x: usize, y: usize) -> Option<Position> {
+        // Convert a position on the screen to a position in the document
+        let doc = &self.doc[self.tab];
+        let rect = self.rect(self.pane)?;
+        if !rect.contains(x, y) {
+            return None;
+        }
+        let (x, y) = (x - rect.x, y - rect.y);
+        let y = cmp::min(doc.offset.y + y, doc.buffer.len_lines().saturating_sub(1));
+        let x = cmp::min(
+            doc.offset.x + x.saturating_sub(doc.line_offset),
+            doc.buffer.line(y).length(),
+        );
+        Some(Position { x, y })
+    }
+    fn click_tab(&mut self, x: usize) {
+        // Switch to the tab under a column of the tab line
+        let mut end = 0;
+        for (num, tab) in self.tabs().0 {
+            end += self.exp.ansi_len(&tab);
+            if x < end {
+                self.tab = num;
+                return;
+            }
+        }
+    }
+    fn command_mode(&mut self) {
+        // Read a command from the command line and run it
+        if let Some(input) = self.complete_prompt("Command", &|_, _, _| {}, Some(&complete)) {
+            match Command::parse(&input) {
+                Ok(command) => self.execute(command),
+                Err(message) => self.doc[self.tab].set_command_line(message, Type::Error),
+            }
+        }
+    }
+    fn execute(&mut self, command: Command) {
+        // Carry out a command
+        match command {
+            Command::Save => self.save(),
+            Command::SaveAs(None) => self.save_as(),
+            Command::SaveAs(Some(path)) => self.save_to(&path),
+            Command::SaveAll => self.save_all(),
+            Command::New => self.new_document(),
+            Command::Open(None) => self.open_document(),
+            Command::Open(Some(path)) => self.open_path(&path),
+            Command::Quit => self.quit(),
+            Command::Search => self.search(),
+            Command::Replace(None) => self.replace(),
+            Command::Replace(Some((target, arrow))) => self.replace_with(&target, &arrow),
+            Command::ReplaceAll(None) => self.replace_all(),
+            Command::ReplaceAll(Some((target, arrow))) => self.replace_all_with(&target, &arrow),
+            Command::Undo => self.doc[self.tab].undo(&self.area),
+            Command::Redo => self.doc[self.tab].redo(&self.area),
+            Command::Copy => self.copy(),
+            Command::Cut => self.cut(),
+            Command::Paste => self.paste(),
+            Command::CycleClipboard => self.cycle_clipboard(),
+            Command::NextTab => self.next_tab(),
+            Command::PrevTab => self.prev_tab(),
+            Command::Split(split) => self.split(split),
+            Command::NextPane => self.focus((self.pane + 1) % self.panes.len()),
+            Command::PrevPane => {
+                self.focus((self.pane + self.panes.len() - 1) % self.panes.len());
+            }
+            Command::GrowPane => self.resize_pane(true),
+            Command::ShrinkPane => self.resize_pane(false),
+            Command::ClosePane => self.close_pane(),
+            Command::Goto(line) => self.goto_line(line),
+            Command::Move(key @ (Key::Left | Key::Right | Key::Up | Key::Down)) => {
+                self.doc[self.tab].mark = None;
+                self.doc[self.tab].move_cursor(key, &self.area);
+            }
+            Command::Move(key) => {
+                self.doc[self.tab].mark = None;
+                self.doc[self.tab].leap_cursor(key, &self.area);
+            }
+            Command::Prompt => self.command_mode(),
+            Command::Record(register) => self.record(register),
+            Command::Play(register, count) => self.play(register, count.unwrap_or(1)),
+            Command::Export(register) => self.export(register),
+            Command::Set(setting, value) => self.set(setting, value),
+            Command::Reload => self.reload_config(self.doc[self.tab].config),
+            Command::EditConfig => self.edit_config(),
+            Command::IndentWith(hard_tabs) => self.indent_with(hard_tabs),
+            Command::LineEnding(line_ending) => self.line_ending(line_ending),
+            Command::Encoding(charset) => self.encoding(charset),
+        }
+    }
+    fn record(&mut self, register: Option<String>) {
+        // Start recording input into a register, or stop if already recording
+        if let Some(name) = self.recording.take() {
+            // Leave out the input that asked for recording to stop
+            self.recorded.truncate(self.command_start);
+            let count = self.recorded.len();
+            self.macros
+                .insert(name.clone(), mem::take(&mut self.recorded));
+            self.doc[self.tab].set_command_line(
+                format!("Recorded {count} inputs into macro {name}"),
+                Type::Info,
+            );
+        } else {
+            let name = register.unwrap_or_else(|| DEFAULT_REGISTER.to_string());
+            self.doc[self.tab].set_command_line(format!("Recording macro {name}"), Type::Info);
+            self.recorded.clear();
+            self.recording = Some(name);
+        }
+    }
+    fn play(&mut self, register: Option<String>, count: usize) {
+        // Replay a macro a number of times, so that it can be undone in one go
+        let name = register.unwrap_or_else(|| DEFAULT_REGISTER.to_string());
+        let Some(inputs) = self.macros.get(&name) else {
+            self.doc[self.tab]
+                .set_command_line(format!("No macro recorded in {name}"), Type::Error);
+            return;
+        };
+        // Queue the input in front of anything still waiting to be replayed
+        for input in inputs.iter().rev().cycle().take(inputs.len() * count) {
+            self.pending.push_front(input.clone());
+        }
+        if self.playing {
+            // This macro was played by another one, which will replay the queue
+            return;
+        }
+        self.playing = true;
+        let tab = self.tab;
+        self.doc[tab].undo_stack.commit();
+        let start = self.doc[tab].undo_stack.len();
+        let mut played = 0;
+        while !self.pending.is_empty() && !self.quit {
+            if played == MACRO_LIMIT {
+                self.pending.clear();
+                self.doc[self.tab].set_command_line(
+                    "Macro stopped after replaying too much input".to_string(),
+                    Type::Error,
+                );
+                break;
+            }
+            self.process_input();
+            played += 1;
+        }
+        self.playing = false;
+        if let Some(doc) = self.doc.get_mut(tab) {
+            // Merge all the changes into a single undo patch
+            doc.undo_stack.commit();
+            doc.undo_stack.squash(start);
+        }
+    }
+    fn export(&mut self, register: Option<String>) {
+        // Copy a macro to the clipboard, written so it can be pasted into the config
+        let name = register.unwrap_or_else(|| DEFAULT_REGISTER.to_string());
+        let Some(inputs) = self.macros.get(&name) else {
+            self.doc[self.tab]
+                .set_command_line(format!("No macro recorded in {name}"), Type::Error);
+            return;
+        };
+        let entries: Vec<String> = inputs
+            .iter()
+            .filter_map(input_name)
+            .map(|entry| format!("{entry:?}"))
+            .collect();
+        let text = format!("{name:?}: [{}],", entries.join(", "));
+        self.clipboard.push(text.clone());
+        self.send_to_host(&text);
+        self.doc[self.tab].set_command_line(
+            format!("Copied macro {name}, paste it into the macros section of your config"),
+            Type::Info,
+        );
+    }
+    fn goto_line(&mut self, line: usize) {
+        // Move the cursor to the start of a line, counting from one
+        if line == 0 || line > self.doc[self.tab].buffer.len_lines() {
+            self.doc[self.tab]
+                .set_command_line(format!("Line {line} is out of range"), Type::Error);
+            return;
+        }
+        self.doc[self.tab].mark = None;
+        self.goto(&Position {
+            x: 0,
+            y: line - 1 + OFFSET,
+        });
+        self.doc[self.tab].recalculate_graphemes();
+    }
+    fn set(&mut self, setting: Setting, value: usize) {
+        // Change a setting for the current document and the rest of the session
+        let name = match setting {
+            Setting::TabWidth => "tab_width",
+            Setting::UndoPeriod => "undo_period",
+            Setting::LineNumberPaddingLeft => "line_number_padding_left",
+            Setting::LineNumberPaddingRight => "line_number_padding_right",
+        };
+        let config = &mut self.configs[self.doc[self.tab].config].config;
+        for general in [&mut config.general, &mut self.doc[self.tab].general] {
+            match setting {
+                Setting::TabWidth => general.tab_width = value,
+                Setting::UndoPeriod => general.undo_period = value as u64,
+                Setting::LineNumberPaddingLeft => general.line_number_padding_left = value,
+                Setting::LineNumberPaddingRight => general.line_number_padding_right = value,
+            }
+        }
+        // Line up tabs and the highlighting on them with the new tab width
+        let doc = &mut self.doc[self.tab];
+        doc.buffer.tab_width = doc.general.tab_width;
+        doc.cache.invalidate(0);
+        self.doc[self.tab].set_command_line(format!("Set {name} to {value}"), Type::Info);
+    }
+    fn indent_with(&mut self, hard_tabs: bool) {
+        // Choose whether the tab key inserts a tab character or spaces in the current document
+        self.doc[self.tab].general.hard_tabs = hard_tabs;
+        let style = if hard_tabs { "tabs" } else { "spaces" };
+        self.doc[self.tab].set_command_line(format!("Indenting with {style}"), Type::Info);
+    }
+    fn line_ending(&mut self, line_ending: LineEnding) {
+        // Convert the current document to other line endings, which happens when it is saved
+        let doc = &mut self.doc[self.tab];
+        doc.format.line_ending = line_ending;
+        doc.dirty = true;
+        let name = line_ending.name();
+        doc.set_command_line(format!("Converted to {name} line endings"), Type::Info);
+    }
+    fn encoding(&mut self, charset: Charset) {
+        // Convert the current document to another encoding, which happens when it is saved
+        let doc = &mut self.doc[self.tab];
+        doc.format.charset = charset;
+        doc.dirty = true;
+        let lost = charset == Charset::Latin1 && doc.buffer.text().chars().any(|c| c > '\u{ff}');
+        if lost {
+            let text = "Characters that Latin-1 can't hold will be saved as ?".to_string();
+            doc.set_command_line(text, Type::Warning);
+        } else {
+            let name = charset.name();
+            doc.set_command_line(format!("Converted to {name}"), Type::Info);
+        }
+    }
+    fn config(&self) -> &Reader {
+        // Get the config for the current document
+        &self.configs[self.doc[self.tab].config].config
+    }
+    fn configure(&mut self, path: Option<&str>) -> usize {
+        // Find the config for a file, picking up the macros in it if it had to be read
+        let count = self.configs.len();
+        let i = Layered::find(&mut self.configs, &self.config_file, path);
+        if self.configs.len() > count {
+            self.macros.extend(self.configs[i].config.registers.clone());
+        }
+        i
+    }
+    fn reload_config(&mut self, i: usize) {
+        // Read a config again, keeping the running config if the new one can't be read
+        let layered = &mut self.configs[i];
+        layered.modified = modified(&layered.paths);
+        let (text, msg) = match Reader::reload(&layered.paths) {
+            Ok((config, status)) => {
+                Terminal::set_colours(config.general.colours);
+                for doc in self.doc.iter_mut().filter(|doc| doc.config == i) {
+                    doc.reconfigure(&config);
+                }
+                self.macros.extend(config.registers.clone());
+                layered.config = config;
+                layered.status = status;
+                match &layered.status {
+                    Status::Parse(error) => (format!("Failed to parse: {error:?}"), Type::Error),
+                    _ => ("Config reloaded".to_string(), Type::Info),
+                }
+            }
+            Err(error) => (format!("Failed to parse: {error:?}"), Type::Error),
+        };
+        self.doc[self.tab].set_command_line(text, msg);
+    }
+    fn edit_config(&mut self) {
+        // Open the user's config file in a tab, creating it if it doesn't exist yet
+        let path = self.configs[self.doc[self.tab].config].paths[1].clone();
+        if let Some(tab) = self.doc.iter().position(|doc| same_file(&doc.path, &path)) {
+            self.tab = tab;
+        } else {
+            let i = self.configure(Some(&path));
+            let mut doc = Document::from(&self.configs[i].config, &self.configs[i].status, &path);
+            doc.config = i;
+            self.doc.push(doc);
+            self.tab = self.doc.len().saturating_sub(1);
+        }
+        self.doc[self.tab].set_command_line(
+            "Saving the config file will reload it".to_string(),
+            Type::Info,
+        );
+    }
+    fn pane_area(&self) -> Rect {
+        // Get the part of the screen that the panes share
+        Rect {
+            x: 0,
+            y: OFFSET,
+            width: self.term.size.width,
+            height: self.term.size.height.saturating_sub(3),
+        }
+    }
+    fn rect(&self, index: usize) -> Option<Rect> {
+        // Find where a pane is on the screen
+        let (panes, _) = self.layout.arrange(self.pane_area());
+        panes
+            .into_iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, rect)| rect)
+    }
+    fn pane_at(&self, x: usize, y: usize) -> Option<usize> {
+        // Find the pane under a point on the screen
+        let (panes, _) = self.layout.arrange(self.pane_area());
+        panes
+            .into_iter()
+            .find(|(_, rect)| rect.contains(x, y))
+            .map(|(i, _)| i)
+    }
+    fn relayout(&mut self) {
+        // Work out the size of the focused pane, keeping the cursor within it
+        self.panes[self.pane].doc = self.tab;
+        if let Some(rect) = self.rect(self.pane) {
+            let area = Size {
+                width: rect.width,
+                height: rect.height + 3,
+            };
+            if area != self.area {
+                self.area = area;
+                self.doc[self.tab].fit_view(&self.area);
+            }
+        }
+    }
+    fn load_view(&mut self, index: usize, size: &Size) -> View {
+        // Look at a document through a pane's view, returning the view it replaced
+        let tab = self.panes[index].doc;
+        let live = self.doc[tab].view();
+        self.doc[tab].set_view(self.panes[index].view);
+        self.doc[tab].fit_view(size);
+        live
+    }
+    fn store_view(&mut self, index: usize, live: View) {
+        // Remember where a pane is looking and put back the view it replaced
+        let tab = self.panes[index].doc;
+        self.panes[index].view = self.doc[tab].view();
+        self.doc[tab].set_view(live);
+    }
+    fn focus(&mut self, index: usize) {
+        // Move to another pane, keeping where each pane was looking
+        if index == self.pane || index >= self.panes.len() {
+            return;
+        }
+        self.panes[self.pane] = Pane {
+            doc: self.tab,
+            view: self.doc[self.tab].view(),
+        };
+        self.pane = index;
+        self.tab = self.panes[index].doc;
+        self.doc[self.tab].set_view(self.panes[index].view);
+        self.relayout();
+        self.doc[self.tab].fit_view(&self.area);
+    }
+    fn split(&mut self, split: Split) {
+        // Divide the focused pane in two, showing the same document in both
+        self.panes.push(Pane {
+            doc: self.tab,
+            view: self.doc[self.tab].view(),
+        });
+        let new = self.panes.len() - 1;
+        self.layout.split(self.pane, new, split);
+        self.relayout();
+    }
+    fn resize_pane(&mut self, grow: bool) {
+        // Move the border of the focused pane
+        if !self.layout.resize(self.pane, grow) {
+            self.doc[self.tab]
+                .set_command_line("There is only one pane".to_string(), Type::Warning);
+        }
+        self.relayout();
+    }
+    fn close_pane(&mut self) {
+        // Remove the focused pane, leaving its document open in a tab
+        if self.panes.len() <= 1 {
+            self.doc[self.tab]
+                .set_command_line("There is only one pane".to_string(), Type::Warning);
+            return;
+        }
+        let closed = self.pane;
+        self.layout.remove(closed);
+        self.panes.remove(closed);
+        // Focus the pane that took its place
+        self.pane = closed.saturating_sub(1);
+        self.tab = self.panes[self.pane].doc;
+        self.doc[self.tab].set_view(self.panes[self.pane].view);
+        self.relayout();
+        self.doc[self.tab].fit_view(&self.area);
+    }
+    fn scroll_pane(&mut self, direction: Key, x: usize, y: usize) {
+        // Scroll the pane under the mouse, without moving focus to it
+        match self.pane_at(x, y) {
+            Some(index) if index != self.pane => {
+                let Some(rect) = self.rect(index) else {
+                    return;
+                };
+                let size = Size {
+                    width: rect.width,
+                    height: rect.height + 3,
+                };
+                let live = self.load_view(index, &size);
+                let tab = self.panes[index].doc;
+                self.doc[tab].scroll(direction, SCROLL_AMOUNT, &size);
+                self.store_view(index, live);
+            }
+            _ => self.doc[self.tab].scroll(direction, SCROLL_AMOUNT, &self.area),
+        }
+    }
     fn next_tab(&mut self) {
         if self.tab.saturating_add(1) < self.doc.len() {
             self.tab = self.tab.saturating_add(1);
@@ -158,6 +1046,7 @@ impl Editor {
     fn quit(&mut self) {
         // For handling a quit event
         if self.dirty_prompt('q', "quit") {
+            self.doc[self.tab].forget_journal();
             if self.doc.len() <= 1 {
                 // Quit Ox
                 self.quit = true;
@@ -165,81 +1054,371 @@ impl Editor {
                 // Close current tab and move right
                 self.doc.remove(self.tab);
                 self.tab -= 1;
+                self.repoint_panes(self.tab + 1);
                 self.doc[self.tab].set_command_line("Closed tab".to_string(), Type::Info);
             } else {
                 // Close current tab and move left
                 self.doc.remove(self.tab);
+                self.repoint_panes(self.tab);
                 self.doc[self.tab].set_command_line("Closed tab".to_string(), Type::Info);
             }
         }
     }
+    fn repoint_panes(&mut self, closed: usize) {
+        // Show the current tab in panes that were showing a closed document
+        let view = self.doc[self.tab].view();
+        for pane in &mut self.panes {
+            if pane.doc == closed {
+                *pane = Pane {
+                    doc: self.tab,
+                    view,
+                };
+            } else if pane.doc > closed {
+                pane.doc -= 1;
+            }
+        }
+    }
     fn new_document(&mut self) {
         // Handle new document event
-        self.doc.push(Document::new(&self.config, &self.status));
+        let i = self.configure(None);
+        let mut doc = Document::new(&self.configs[i].config, &self.configs[i].status);
+        doc.config = i;
+        self.doc.push(doc);
         self.tab = self.doc.len().saturating_sub(1);
         self.doc[self.tab].dirty = false;
         self.doc[self.tab].show_welcome = true;
         self.doc[self.tab].cursor.y = OFFSET;
         self.doc[self.tab].offset.y = 0;
-        self.doc[self.tab].leap_cursor(Key::Home, &self.term.size);
+        self.doc[self.tab].leap_cursor(Key::Home, &self.area);
     }
     fn open_document(&mut self) {
         // Handle open document event
         // TODO: Highlight entire file here
         if let Some(result) = self.prompt("Open", &|_, _, _| {}) {
-            if let Some(doc) = Document::open(&self.config, &self.status, &result[..]) {
-                // Overwrite the current document
-                self.doc.push(doc);
-                self.tab = self.doc.len().saturating_sub(1);
-                self.doc[self.tab].dirty = false;
-                self.doc[self.tab].show_welcome = false;
-                self.doc[self.tab].cursor.y = OFFSET;
-                self.doc[self.tab].offset.y = 0;
-                self.doc[self.tab].leap_cursor(Key::Home, &self.term.size);
+            self.open_path(&result);
+        }
+    }
+    fn open_path(&mut self, path: &str) {
+        // Open a file in a new tab
+        let i = self.configure(Some(path));
+        if let Some(mut doc) =
+            Document::open(&self.configs[i].config, &self.configs[i].status, path)
+        {
+            // Overwrite the current document
+            doc.config = i;
+            self.doc.push(doc);
+            self.tab = self.doc.len().saturating_sub(1);
+            self.doc[self.tab].dirty = false;
+            self.doc[self.tab].show_welcome = false;
+            self.doc[self.tab].cursor.y = OFFSET;
+            self.doc[self.tab].offset.y = 0;
+            self.doc[self.tab].leap_cursor(Key::Home, &self.area);
+            self.watcher.watch(path);
+            self.find_orphan(self.tab);
+        } else {
+            self.doc[self.tab].set_command_line("File couldn't be opened".to_string(), Type::Error);
+        }
+    }
+    fn written_elsewhere(&mut self, written: &[PathBuf]) {
+        // Reload files that other programs wrote to, or flag them if they have edits of their own
+        for i in 0..self.doc.len() {
+            let path = self.doc[i].path.clone();
+            if path.is_empty()
+                || !written.contains(&absolute(Path::new(&path)))
+                || !self.doc[i].changed_on_disk()
+            {
+                continue;
+            }
+            let config = &self.configs[self.doc[i].config].config;
+            if self.doc[i].dirty {
+                self.doc[i].outdated = true;
+            } else if self.doc[i].reload(config).is_ok() {
+                self.doc[i].fit_view(&self.area);
+                self.doc[i].set_command_line(
+                    format!("Reloaded {path}, which was changed on disk"),
+                    Type::Info,
+                );
+            }
+        }
+    }
+    fn outdated_prompt(&mut self) {
+        // Ask whether to keep the edits to a file that was changed on disk or take the new file
+        let path = self.doc[self.tab].path.clone();
+        loop {
+            self.doc[self.tab].set_command_line(
+                format!("{path} was changed on disk! Keep [y]ours, take [t]heirs or see the [d]ifference"),
+                Type::Warning,
+            );
+            self.update();
+            match self.read_key() {
+                Key::Char('y') | Key::Esc => {
+                    // Only warn again if the file changes once more
+                    self.doc[self.tab].stamp = Stamp::read(&path);
+                    self.doc[self.tab].outdated = false;
+                    self.doc[self.tab]
+                        .set_command_line(format!("Kept your edits to {path}"), Type::Info);
+                    return;
+                }
+                Key::Char('t') => {
+                    let config = &self.configs[self.doc[self.tab].config].config;
+                    if self.doc[self.tab].reload(config).is_ok() {
+                        self.doc[self.tab].fit_view(&self.area);
+                        self.doc[self.tab].set_command_line(format!("Reloaded {path}"), Type::Info);
+                    } else {
+                        self.doc[self.tab].outdated = false;
+                        self.doc[self.tab]
+                            .set_command_line(format!("Failed to reload {path}"), Type::Error);
+                    }
+                    return;
+                }
+                Key::Char('d') => {
+                    // Ask again once the difference has been looked at
+                    self.show_difference();
+                    return;
+                }
+                _ => (),
+            }
+        }
+    }
+    fn write_journals(&mut self) {
+        // Write the unsaved edits of each document with a file to its journal
+        for doc in &mut self.doc {
+            if !doc.dirty || doc.path.is_empty() || doc.orphan.is_some() {
+                continue;
+            }
+            let text = doc.buffer.text();
+            if doc
+                .journaled
+                .as_ref()
+                .is_some_and(|last| Rc::ptr_eq(last, &text))
+            {
+                continue;
+            }
+            if journal::write(&doc.path, &text).is_ok() {
+                doc.journaled = Some(text);
+            }
+        }
+    }
+    fn find_orphan(&mut self, i: usize) {
+        // Look for edits to a document left in its journal by a session that ended early
+        let path = self.doc[i].path.clone();
+        if let Some(text) = journal::orphan(&path) {
+            if *text == *self.doc[i].buffer.text() {
+                journal::remove(&path);
             } else {
-                self.doc[self.tab]
-                    .set_command_line("File couldn't be opened".to_string(), Type::Error);
+                self.doc[i].orphan = Some(text);
+            }
+        }
+    }
+    fn orphan_prompt(&mut self) {
+        // Ask whether to recover the edits to a file from a session that ended early
+        let path = self.doc[self.tab].path.clone();
+        loop {
+            self.doc[self.tab].set_command_line(
+                format!("{path} has edits left from a crash! [r]ecover them, see the [d]ifference or throw them [a]way"),
+                Type::Warning,
+            );
+            self.update();
+            match self.read_key() {
+                Key::Char('r') => {
+                    if let Some(text) = self.doc[self.tab].orphan.take() {
+                        self.doc[self.tab].recover(&text);
+                        self.doc[self.tab].fit_view(&self.area);
+                    }
+                    self.doc[self.tab]
+                        .set_command_line(format!("Recovered the edits to {path}"), Type::Info);
+                    return;
+                }
+                Key::Char('d') => {
+                    // Ask again once the difference has been looked at
+                    let text = self.doc[self.tab].orphan.clone().unwrap_or_default();
+                    let diff = diff::unified(
+                        &self.doc[self.tab].buffer.text(),
+                        &text,
+                        &format!("{path} (saved)"),
+                        &format!("{path} (recovered)"),
+                    );
+                    let name = format!("{} (difference)", self.doc[self.tab].name);
+                    self.open_text(&name, &diff);
+                    self.doc[self.tab].set_command_line(
+                        format!("How the unsaved edits differ from {path}"),
+                        Type::Info,
+                    );
+                    return;
+                }
+                Key::Char('a') => {
+                    self.doc[self.tab].orphan = None;
+                    journal::remove(&path);
+                    self.doc[self.tab]
+                        .set_command_line(format!("Threw away the edits to {path}"), Type::Info);
+                    return;
+                }
+                _ => (),
             }
         }
     }
+    fn show_difference(&mut self) {
+        // Open a tab showing how the file on disk differs from the document
+        let doc = &self.doc[self.tab];
+        let Ok(bytes) = fs::read(&doc.path) else {
+            let message = format!("Failed to read {}", doc.path);
+            self.doc[self.tab].set_command_line(message, Type::Error);
+            return;
+        };
+        let theirs = Format::detect(&bytes).1;
+        let text = diff::unified(
+            &theirs,
+            &doc.buffer.text(),
+            &format!("{} (on disk)", doc.path),
+            &format!("{} (yours)", doc.path),
+        );
+        let message = format!("How {} differs from the file on disk", doc.name);
+        let name = format!("{} (difference)", doc.name);
+        self.open_text(&name, &text);
+        self.doc[self.tab].set_command_line(message, Type::Info);
+    }
+    fn open_text(&mut self, name: &str, text: &str) {
+        // Open some text in a new tab, without a file behind it
+        let i = self.doc[self.tab].config;
+        let mut doc = Document::new(&self.configs[i].config, &self.configs[i].status);
+        doc.config = i;
+        doc.buffer = Buffer::new(text, doc.general.tab_width);
+        doc.name = name.to_string();
+        doc.show_welcome = false;
+        self.doc.push(doc);
+        self.tab = self.doc.len().saturating_sub(1);
+        self.doc[self.tab].leap_cursor(Key::Home, &self.area);
+    }
     fn save(&mut self) {
         // Handle save event
         let path = self.doc[self.tab].path.clone();
-        if self.doc[self.tab].save().is_ok() {
-            // The document saved successfully
-            self.doc[self.tab].dirty = false;
-            self.doc[self.tab]
-                .set_command_line(format!("File saved to {} successfully", path), Type::Info);
-        } else {
-            // The document couldn't save due to permission errors
-            self.doc[self.tab]
-                .set_command_line(format!("Failed to save file to {}", path), Type::Error);
+        if !self.clobber_prompt(&path) {
+            return;
+        }
+        match self.doc[self.tab].save() {
+            Ok(()) => {
+                // The document saved successfully
+                self.doc[self.tab].dirty = false;
+                self.doc[self.tab]
+                    .set_command_line(format!("File saved to {} successfully", path), Type::Info);
+                // Pick up the changes to any config that the file is part of
+                for i in 0..self.configs.len() {
+                    if self.configs[i]
+                        .paths
+                        .iter()
+                        .any(|config| same_file(config, &path))
+                    {
+                        self.reload_config(i);
+                    }
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                // The document needs more privileges to save
+                self.elevated_save(&path);
+            }
+            Err(_) => {
+                // The document couldn't save
+                self.doc[self.tab]
+                    .set_command_line(format!("Failed to save file to {}", path), Type::Error);
+            }
         }
         // Commit to undo stack on document save
         self.doc[self.tab].undo_stack.commit();
     }
+    fn elevated_save(&mut self, path: &str) {
+        // Offer to save a file through a program such as sudo, asking for a password if needed
+        let helper = self.config().general.privilege_helper.clone();
+        if !self.force_prompt("Permission denied!", 's', &format!("save with {helper}")) {
+            return;
+        }
+        let password = match disk::needs_password(&helper) {
+            Ok(false) => None,
+            Ok(true) if disk::takes_password(&helper) => {
+                if let Some(password) = self.password_prompt(&format!("[{helper}] Password")) {
+                    Some(password)
+                } else {
+                    self.doc[self.tab].set_command_line("Save cancelled".to_string(), Type::Info);
+                    return;
+                }
+            }
+            Ok(true) => {
+                let text = format!("{helper} needs a password, which Ox can only give to sudo");
+                self.doc[self.tab].set_command_line(text, Type::Error);
+                return;
+            }
+            Err(error) => {
+                let text = format!("Failed to run {helper}: {error}");
+                self.doc[self.tab].set_command_line(text, Type::Error);
+                return;
+            }
+        };
+        self.doc[self.tab].set_command_line(format!("Saving with {helper}..."), Type::Info);
+        self.update();
+        match self.doc[self.tab].save_elevated(&helper, password.as_deref()) {
+            Ok(()) => {
+                self.doc[self.tab].dirty = false;
+                self.doc[self.tab].set_command_line(
+                    format!("File saved to {path} with {helper} successfully"),
+                    Type::Info,
+                );
+            }
+            Err(error) => self.doc[self.tab].set_command_line(
+                format!("Failed to save file to {path} with {helper}: {error}"),
+                Type::Error,
+            ),
+        }
+    }
+    fn password_prompt(&mut self, prompt: &str) -> Option<String> {
+        // Read a password from the command line without showing it or recording it in a macro
+        let mut result = String::new();
+        loop {
+            let hidden = "*".repeat(result.chars().count());
+            self.doc[self.tab].set_command_line(format!("{prompt}: {hidden}"), Type::Info);
+            self.update();
+            match self.read_event() {
+                Input::Key(Key::Char('\n')) => return Some(result),
+                Input::Key(Key::Char(c)) => result.push(c),
+                Input::Key(Key::Backspace) => {
+                    result.pop();
+                }
+                Input::Key(Key::Esc) => return None,
+                Input::Paste(text) => result.push_str(text.lines().next().unwrap_or_default()),
+                _ => (),
+            }
+        }
+    }
     fn save_as(&mut self) {
         // Handle save as event
         if let Some(result) = self.prompt("Save as", &|_, _, _| {}) {
-            if self.doc[self.tab].save_as(&result[..]).is_ok() {
-                // The document could save as
-                let ext = result.split('.').last().unwrap_or(&"");
-                self.doc[self.tab].dirty = false;
-                self.doc[self.tab]
-                    .set_command_line(format!("File saved to {} successfully", result), Type::Info);
-                self.doc[self.tab].kind = Document::identify(&result).0.to_string();
-                self.doc[self.tab].icon = Document::identify(&result).1.to_string();
-                self.doc[self.tab].name = result.clone();
-                self.doc[self.tab].path = result.clone();
-                self.doc[self.tab].regex = Reader::get_syntax_regex(&self.config, ext);
-            } else {
-                // The document couldn't save to the file
-                self.doc[self.tab]
-                    .set_command_line(format!("Failed to save file to {}", result), Type::Error);
-            }
+            self.save_to(&result);
         } else {
             // User pressed the escape key
             self.doc[self.tab].set_command_line("Save as cancelled".to_string(), Type::Info);
+            self.doc[self.tab].undo_stack.commit();
+        }
+    }
+    fn save_to(&mut self, result: &str) {
+        // Save the document to a new path
+        if !self.clobber_prompt(result) {
+            return;
+        }
+        if self.doc[self.tab].save_as(result).is_ok() {
+            // The document could save as
+            self.doc[self.tab].dirty = false;
+            self.doc[self.tab]
+                .set_command_line(format!("File saved to {} successfully", result), Type::Info);
+            self.doc[self.tab].kind = Document::identify(result).0.to_string();
+            self.doc[self.tab].icon = Document::identify(result).1.to_string();
+            self.doc[self.tab].name = result.to_string();
+            self.doc[self.tab].path = result.to_string();
+            let i = self.configure(Some(result));
+            self.doc[self.tab].config = i;
+            self.doc[self.tab].reconfigure(&self.configs[i].config);
+            self.watcher.watch(result);
+        } else {
+            // The document couldn't save to the file
+            self.doc[self.tab]
+                .set_command_line(format!("Failed to save file to {}", result), Type::Error);
         }
         // Commit to the undo stack on save as
         self.doc[self.tab].undo_stack.commit();
@@ -247,7 +1426,13 @@ impl Editor {
     fn save_all(&mut self) {
         for i in 0..self.doc.len() {
             let path = self.doc[i].path.clone();
-            if self.doc[i].save().is_ok() {
+            if self.doc[i].changed_on_disk() {
+                // Leave files that were changed elsewhere to be looked at one by one
+                self.doc[i].set_command_line(
+                    format!("Skipped {path}, which was changed on disk"),
+                    Type::Warning,
+                );
+            } else if self.doc[i].save().is_ok() {
                 // The document saved successfully
                 self.doc[i].dirty = false;
                 self.doc[i]
@@ -261,6 +1446,79 @@ impl Editor {
             self.doc[i].undo_stack.commit();
         }
     }
+    fn copy(&mut self) {
+        // Copy the selection, or the current line when nothing is selected
+        let text = if let Some(text) = self.doc[self.tab].selected_text() {
+            text
+        } else {
+            let y = self.doc[self.tab].position().y;
+            format!("{}\n", self.doc[self.tab].buffer.line(y).string)
+        };
+        self.doc[self.tab].set_command_line(
+            format!("Copied {} line(s)", text.matches('\n').count().max(1)),
+            Type::Info,
+        );
+        self.send_to_host(&text);
+        self.clipboard.push(text);
+    }
+    fn cut(&mut self) {
+        // Cut the selection, or the current line when nothing is selected
+        if self.doc[self.tab].selection().is_none() {
+            self.copy();
+            self.doc[self.tab].select_line(&self.area);
+        } else {
+            self.copy();
+        }
+        self.doc[self.tab].undo_stack.commit();
+        self.doc[self.tab].delete_selection(&self.area);
+        self.doc[self.tab].undo_stack.commit();
+        self.doc[self.tab].redo_stack.empty();
+    }
+    fn paste(&mut self) {
+        // Paste from the host clipboard command if there is one, or the kill ring
+        if let Some(text) = self.clipboard_text() {
+            self.doc[self.tab].paste(&text, &self.area);
+        }
+    }
+    fn clipboard_text(&mut self) -> Option<String> {
+        // Get the text to paste, reading the host clipboard command if there is one
+        let command = self.config().clipboard.paste_command.clone();
+        if !command.is_empty() {
+            match paste_command(&command) {
+                Ok(text) => self.clipboard.push(text),
+                Err(_) => self.doc[self.tab].set_command_line(
+                    "Failed to read from the clipboard command".to_string(),
+                    Type::Error,
+                ),
+            }
+        }
+        let text = self.clipboard.top().cloned();
+        if text.is_none() {
+            self.doc[self.tab].set_command_line("Clipboard is empty".to_string(), Type::Warning);
+        }
+        text
+    }
+    fn cycle_clipboard(&mut self) {
+        // Bring an older clipboard entry to the top, ready to paste
+        self.clipboard.rotate();
+        if let Some(text) = self.clipboard.top() {
+            let preview = text.lines().next().unwrap_or_default().to_string();
+            self.doc[self.tab].set_command_line(format!("Clipboard: {preview}"), Type::Info);
+        }
+    }
+    fn send_to_host(&mut self, text: &str) {
+        // Share copied text with the host clipboard, if configured
+        if self.config().clipboard.osc52 {
+            self.term.copy(text);
+        }
+        let command = &self.config().clipboard.copy_command;
+        if !command.is_empty() && copy_command(command, text).is_err() {
+            self.doc[self.tab].set_command_line(
+                "Failed to write to the clipboard command".to_string(),
+                Type::Error,
+            );
+        }
+    }
     fn search(&mut self) {
         // For searching the file
         let initial_cursor = self.doc[self.tab].cursor;
@@ -341,174 +1599,181 @@ impl Editor {
         self.doc[self.tab].set_command_line("Search exited".to_string(), Type::Info);
     }
     fn replace(&mut self) {
+        // Ask for the target and replacement, then replace text within the document
+        if let Some(target) = self.prompt("Replace", &|_, _, _| {}) {
+            if let Some(arrow) = self.prompt("With", &|_, _, _| {}) {
+                self.replace_with(&target, &arrow);
+            }
+        }
+    }
+    fn replace_with(&mut self, target: &str, arrow: &str) {
         // Replace text within the document
         let initial_cursor = self.doc[self.tab].cursor;
         let initial_offset = self.doc[self.tab].offset;
-        // After saving the cursor position, ask the user for the information
-        if let Some(target) = self.prompt("Replace", &|_, _, _| {}) {
-            if let Some(arrow) = self.prompt("With", &|_, _, _| {}) {
-                // Construct a regular expression for searching
-                let re = Regex::new(&target).unwrap();
-                let mut search_points = self.doc[self.tab].scan(&target, OFFSET);
-                // Search forward as the user types
-                for p in &search_points {
-                    if is_ahead(
-                        &Position {
-                            x: self.doc[self.tab].cursor.x + self.doc[self.tab].offset.x,
-                            y: self.doc[self.tab].cursor.y + self.doc[self.tab].offset.y,
-                        },
-                        &p,
-                    ) {
-                        self.goto(&p);
-                        self.doc[self.tab].recalculate_graphemes();
-                        self.update();
-                        break;
+        // Construct a regular expression for searching
+        let Ok(re) = Regex::new(target) else {
+            self.doc[self.tab].set_command_line(format!("Invalid pattern: {target}"), Type::Error);
+            return;
+        };
+        let mut search_points = self.doc[self.tab].scan(target, OFFSET);
+        // Search forward as the user types
+        for p in &search_points {
+            if is_ahead(
+                &Position {
+                    x: self.doc[self.tab].cursor.x + self.doc[self.tab].offset.x,
+                    y: self.doc[self.tab].cursor.y + self.doc[self.tab].offset.y,
+                },
+                &p,
+            ) {
+                self.goto(&p);
+                self.doc[self.tab].recalculate_graphemes();
+                self.update();
+                break;
+            }
+        }
+        loop {
+            // Handle key press events while in replace mode
+            let key = self.read_key();
+            match key {
+                Key::Up | Key::Left => {
+                    // User wishes to search backwards
+                    for p in search_points.iter().rev() {
+                        if is_behind(
+                            &Position {
+                                x: self.doc[self.tab].cursor.x + self.doc[self.tab].offset.x,
+                                y: self.doc[self.tab].cursor.y + self.doc[self.tab].offset.y,
+                            },
+                            &p,
+                        ) {
+                            self.goto(&p);
+                            self.doc[self.tab].recalculate_graphemes();
+                            self.update();
+                            break;
+                        }
                     }
                 }
-                loop {
-                    // Handle key press events while in replace mode
-                    let key = self.read_key();
-                    match key {
-                        Key::Up | Key::Left => {
-                            // User wishes to search backwards
-                            for p in (&search_points).iter().rev() {
-                                if is_behind(
-                                    &Position {
-                                        x: self.doc[self.tab].cursor.x
-                                            + self.doc[self.tab].offset.x,
-                                        y: self.doc[self.tab].cursor.y
-                                            + self.doc[self.tab].offset.y,
-                                    },
-                                    &p,
-                                ) {
-                                    self.goto(&p);
-                                    self.doc[self.tab].recalculate_graphemes();
-                                    self.update();
-                                    break;
-                                }
-                            }
-                        }
-                        Key::Down | Key::Right => {
-                            // User wishes to search forwards
-                            for p in &search_points {
-                                if is_ahead(
-                                    &Position {
-                                        x: self.doc[self.tab].cursor.x
-                                            + self.doc[self.tab].offset.x,
-                                        y: self.doc[self.tab].cursor.y
-                                            + self.doc[self.tab].offset.y
-                                            - OFFSET,
-                                    },
-                                    &p,
-                                ) {
-                                    self.goto(&p);
-                                    self.doc[self.tab].recalculate_graphemes();
-                                    self.update();
-                                    break;
-                                }
-                            }
-                        }
-                        Key::Char('\n') | Key::Char('y') | Key::Char(' ') => {
-                            let cursor = self.doc[self.tab].cursor;
-                            let offset = self.doc[self.tab].offset;
-                            // Commit current changes to undo stack
-                            self.doc[self.tab].undo_stack.commit();
-                            // Calculate the new line after the replacement
-                            let line = self.doc[self.tab].rows[self.doc[self.tab].cursor.y
-                                + self.doc[self.tab].offset.y
-                                - OFFSET]
-                                .clone();
-                            let before = self.doc[self.tab].rows[self.doc[self.tab].cursor.y
-                                + self.doc[self.tab].offset.y
-                                - OFFSET]
-                                .clone();
-                            let after = Row::from(&*re.replace_all(&line.string[..], &arrow[..]));
-                            // Check there was actually a change
-                            if before.string != after.string {
-                                // Push the replace event to the undo stack
-                                self.doc[self.tab].undo_stack.push(Event::UpdateLine(
-                                    cursor.y + offset.y - OFFSET,
-                                    Box::new(before.clone()),
-                                    Box::new(after.clone()),
-                                ));
-                                // TODO: Update relavent lines here
-                                self.doc[self.tab].rows[cursor.y + offset.y - OFFSET] = after;
-                            }
-                            self.update();
-                            self.doc[self.tab].snap_cursor(&self.term.size);
-                            self.doc[self.tab].prevent_unicode_hell();
+                Key::Down | Key::Right => {
+                    // User wishes to search forwards
+                    for p in &search_points {
+                        if is_ahead(
+                            &Position {
+                                x: self.doc[self.tab].cursor.x + self.doc[self.tab].offset.x,
+                                y: self.doc[self.tab].cursor.y + self.doc[self.tab].offset.y
+                                    - OFFSET,
+                            },
+                            &p,
+                        ) {
+                            self.goto(&p);
                             self.doc[self.tab].recalculate_graphemes();
-                            // Update search locations
-                            search_points = self.doc[self.tab].scan(&target, OFFSET);
+                            self.update();
+                            break;
                         }
-                        Key::Esc => break,
-                        _ => (),
                     }
                 }
-                // Restore cursor position and exit
-                self.doc[self.tab].cursor = initial_cursor;
-                self.doc[self.tab].offset = initial_offset;
-                self.doc[self.tab].set_command_line("Replace finished".to_string(), Type::Info);
+                Key::Char('\n') | Key::Char('y') | Key::Char(' ') => {
+                    let cursor = self.doc[self.tab].cursor;
+                    let offset = self.doc[self.tab].offset;
+                    // Commit current changes to undo stack
+                    self.doc[self.tab].undo_stack.commit();
+                    // Calculate the new line after the replacement
+                    let before = self.doc[self.tab]
+                        .buffer
+                        .line(cursor.y + offset.y - OFFSET)
+                        .string
+                        .to_string();
+                    let after = re.replace_all(&before, arrow).to_string();
+                    // Check there was actually a change
+                    if before != after {
+                        // TODO: Update relavent lines here
+                        self.doc[self.tab]
+                            .buffer
+                            .set_line(cursor.y + offset.y - OFFSET, &after);
+                        // Push the replace event to the undo stack
+                        self.doc[self.tab].undo_stack.push(Event::UpdateLine(
+                            cursor.y + offset.y - OFFSET,
+                            before,
+                            after,
+                        ));
+                    }
+                    self.update();
+                    self.doc[self.tab].snap_cursor(&self.area);
+                    self.doc[self.tab].prevent_unicode_hell();
+                    self.doc[self.tab].recalculate_graphemes();
+                    // Update search locations
+                    search_points = self.doc[self.tab].scan(target, OFFSET);
+                }
+                Key::Esc => break,
+                _ => (),
             }
         }
+        // Restore cursor position and exit
+        self.doc[self.tab].cursor = initial_cursor;
+        self.doc[self.tab].offset = initial_offset;
+        self.doc[self.tab].set_command_line("Replace finished".to_string(), Type::Info);
     }
     fn replace_all(&mut self) {
-        // Replace all occurances of a substring
+        // Ask for the target and replacement, then replace every occurance
         if let Some(target) = self.prompt("Replace", &|_, _, _| {}) {
             if let Some(arrow) = self.prompt("With", &|_, _, _| {}) {
-                // Commit undo stack changes
-                self.doc[self.tab].undo_stack.commit();
-                let re = Regex::new(&target).unwrap();
-                let lines = self.doc[self.tab].rows.clone();
-                // Replace every occurance
-                for (c, line) in lines.iter().enumerate() {
-                    let before = self.doc[self.tab].rows[c].clone();
-                    let after = Row::from(&*re.replace_all(&line.string[..], &arrow[..]));
-                    if before.string != after.string {
-                        self.doc[self.tab].undo_stack.push(Event::UpdateLine(
-                            c,
-                            Box::new(before.clone()),
-                            Box::new(after.clone()),
-                        ));
-                        // TODO: Update relavent lines here
-                        self.doc[self.tab].rows[c] = after;
-                    }
-                }
+                self.replace_all_with(&target, &arrow);
             }
         }
-        self.doc[self.tab].snap_cursor(&self.term.size);
+    }
+    fn replace_all_with(&mut self, target: &str, arrow: &str) {
+        // Replace all occurances of a substring
+        let Ok(re) = Regex::new(target) else {
+            self.doc[self.tab].set_command_line(format!("Invalid pattern: {target}"), Type::Error);
+            return;
+        };
+        // Commit undo stack changes
+        self.doc[self.tab].undo_stack.commit();
+        // Replace every occurance
+        for c in 0..self.doc[self.tab].buffer.len_lines() {
+            let before = self.doc[self.tab].buffer.line(c).string.to_string();
+            let after = re.replace_all(&before, arrow).to_string();
+            if before != after {
+                // TODO: Update relavent lines here
+                self.doc[self.tab].buffer.set_line(c, &after);
+                self.doc[self.tab]
+                    .undo_stack
+                    .push(Event::UpdateLine(c, before, after));
+            }
+        }
+        self.doc[self.tab].snap_cursor(&self.area);
         self.doc[self.tab].prevent_unicode_hell();
         self.doc[self.tab].recalculate_graphemes();
         self.doc[self.tab].set_command_line("Replaced targets".to_string(), Type::Info);
     }
     fn dirty_prompt(&mut self, key: char, subject: &str) -> bool {
         // For events that require changes to the document
-        if self.doc[self.tab].dirty {
-            // Handle unsaved changes
-            self.doc[self.tab].set_command_line(
-                format!(
-                    "Unsaved Changes! Ctrl + {} to force {}",
-                    key.to_uppercase(),
-                    subject
-                ),
-                Type::Warning,
-            );
-            self.update();
-            match self.read_key() {
-                Key::Char('\n') => return true,
-                Key::Ctrl(k) => {
-                    if k == key {
-                        return true;
-                    } else {
-                        self.doc[self.tab]
-                            .set_command_line(format!("{} cancelled", title(subject)), Type::Info);
-                    }
-                }
-                _ => self.doc[self.tab]
-                    .set_command_line(format!("{} cancelled", title(subject)), Type::Info),
-            }
+        !self.doc[self.tab].dirty || self.force_prompt("Unsaved Changes!", key, subject)
+    }
+    fn clobber_prompt(&mut self, path: &str) -> bool {
+        // For writing over a file that ox didn't write last
+        if path == self.doc[self.tab].path {
+            !self.doc[self.tab].changed_on_disk()
+                || self.force_prompt("File changed on disk!", 's', "save")
         } else {
-            return true;
+            !Path::new(path).exists() || self.force_prompt("File already exists!", 's', "overwrite")
+        }
+    }
+    fn force_prompt(&mut self, warning: &str, key: char, subject: &str) -> bool {
+        // Warn about an event, going ahead with it only if asked to
+        self.doc[self.tab].set_command_line(
+            format!(
+                "{} Ctrl + {} to force {}",
+                warning,
+                key.to_uppercase(),
+                subject
+            ),
+            Type::Warning,
+        );
+        self.update();
+        match self.read_key() {
+            Key::Char('\n') => return true,
+            Key::Ctrl(k) if k == key => return true,
+            _ => self.doc[self.tab]
+                .set_command_line(format!("{} cancelled", title(subject)), Type::Info),
         }
         false
     }
@@ -518,12 +1783,48 @@ impl Editor {
         func: &dyn Fn(&mut Self, PromptEvent, &str),
     ) -> Option<String> {
         // Create a new prompt
+        self.complete_prompt(prompt, func, None)
+    }
+    fn complete_prompt(
+        &mut self,
+        prompt: &str,
+        func: &dyn Fn(&mut Self, PromptEvent, &str),
+        complete: Option<&Completer>,
+    ) -> Option<String> {
+        // Create a new prompt, where tab cycles through ways to complete the input
         self.doc[self.tab].set_command_line(format!("{}: ", prompt), Type::Info);
         self.update();
         let mut result = String::new();
+        let mut completions: Option<(Vec<String>, usize)> = None;
         'p: loop {
-            let key = self.read_key();
+            let key = match self.read_input() {
+                Input::Key(key) => key,
+                Input::Paste(text) => {
+                    // Only take the first line of pasted text
+                    result.push_str(text.lines().next().unwrap_or_default());
+                    Key::Null
+                }
+                Input::Shift(_) | Input::Mouse(_) => continue,
+            };
+            if key != Key::Char('\t') {
+                completions = None;
+            }
             match key {
+                Key::Char('\t') if complete.is_some() => {
+                    // Complete the input, recomputing once there's nothing to cycle through
+                    let (options, index) = completions
+                        .get_or_insert_with(|| (complete.map(|f| f(&result)).unwrap(), 0));
+                    if let Some(option) = options.get(*index) {
+                        result.clone_from(option);
+                        *index += 1;
+                    }
+                    if options.len() <= 1 {
+                        completions = None;
+                    } else if *index >= options.len() {
+                        *index = 0;
+                    }
+                    func(self, PromptEvent::CharPress, &result);
+                }
                 Key::Char(c) => {
                     // Update the prompt contents
                     if c == '\n' {
@@ -544,6 +1845,7 @@ impl Editor {
                     func(self, PromptEvent::KeyPress(key), &result);
                     return None;
                 }
+                Key::Null => func(self, PromptEvent::CharPress, &result),
                 _ => func(self, PromptEvent::KeyPress(key), &result),
             }
             self.doc[self.tab].set_command_line(format!("{}: {}", prompt, result), Type::Info);
@@ -554,8 +1856,8 @@ impl Editor {
     }
     fn goto(&mut self, pos: &Position) {
         // Move the cursor to a specific location
-        let max_y = self.term.size.height.saturating_sub(3);
-        let max_x = (self.term.size.width).saturating_sub(self.doc[self.tab].line_offset);
+        let max_y = self.area.height.saturating_sub(3);
+        let max_x = (self.area.width).saturating_sub(self.doc[self.tab].line_offset);
         let halfway_y = max_y / 2;
         let halfway_x = max_x / 2;
         if self.doc[self.tab].offset.x == 0 && pos.y < max_y && pos.x < max_x {
@@ -588,36 +1890,39 @@ impl Editor {
         // Move the cursor and render the screen
         self.term.hide_cursor();
         self.term.goto(&Position { x: 0, y: 0 });
-        self.doc[self.tab].recalculate_offset(&self.config);
+        self.relayout();
+        self.doc[self.tab].recalculate_offset();
         self.render();
+        let rect = self.rect(self.pane).unwrap_or_else(|| self.pane_area());
         self.term.goto(&Position {
-            x: self.doc[self.tab]
-                .cursor
-                .x
-                .saturating_add(self.doc[self.tab].line_offset),
-            y: self.doc[self.tab].cursor.y,
+            x: rect.x
+                + self.doc[self.tab]
+                    .cursor
+                    .x
+                    .saturating_add(self.doc[self.tab].line_offset),
+            y: rect.y + self.doc[self.tab].cursor.y - OFFSET,
         });
         self.term.show_cursor();
         self.term.flush();
     }
-    fn welcome_message(&self, text: &str, colour: color::Fg<color::Rgb>) -> String {
+    fn welcome_message(&self, text: &str, colour: color::Fg<Colour>, width: usize) -> String {
         // Render the welcome message
-        let pad = " ".repeat((self.term.size.width / 2).saturating_sub(text.len() / 2));
+        let pad = " ".repeat((width / 2).saturating_sub(text.len() / 2));
         let pad_right = " ".repeat(
-            (self.term.size.width.saturating_sub(1))
+            (width.saturating_sub(1))
                 .saturating_sub(text.len() + pad.len())
-                .saturating_sub(self.config.general.line_number_padding_left),
+                .saturating_sub(self.config().general.line_number_padding_left),
         );
         format!(
             "{}{}{}~{}{}{}{}{}{}",
-            Reader::rgb_bg(self.config.theme.editor_bg),
-            Reader::rgb_fg(self.config.theme.line_number_fg),
-            " ".repeat(self.config.general.line_number_padding_left),
+            Reader::rgb_bg(self.config().theme.editor_bg),
+            Reader::rgb_fg(self.config().theme.line_number_fg),
+            " ".repeat(self.config().general.line_number_padding_left),
             RESET_FG,
             colour,
             trim_end(
                 &format!("{}{}", pad, text),
-                self.term.size.width.saturating_sub(1)
+                width.saturating_sub(1 + self.config().general.line_number_padding_left)
             ),
             pad_right,
             RESET_FG,
@@ -628,8 +1933,22 @@ impl Editor {
         // Produce the status line
         // Create the left part of the status line
         let left = format!(
-            " {}{} \u{2502} {} {} ",
+            "{} {}{}{} \u{2502} {} {} {}",
+            if self.config().general.modal {
+                match self.modal.mode {
+                    Mode::Normal => " NORMAL \u{2502}",
+                    Mode::Insert => " INSERT \u{2502}",
+                    Mode::Visual => " VISUAL \u{2502}",
+                }
+            } else {
+                ""
+            },
             self.doc[self.tab].name,
+            if self.doc[self.tab].read_only {
+                " [RO]"
+            } else {
+                ""
+            },
             if self.doc[self.tab].dirty {
                 "[+] \u{fb12} "
             } else {
@@ -637,12 +1956,17 @@ impl Editor {
             },
             self.doc[self.tab].kind,
             self.doc[self.tab].icon,
+            self.recording
+                .as_ref()
+                .map_or(String::new(), |name| format!("\u{2502} recording {name} ")),
         );
         // Create the right part of the status line
         let right = format!(
-            " \u{fa70} {} / {} \u{2502} \u{fae6}({}, {}) ",
+            " {} \u{2502} {} \u{2502} \u{fa70} {} / {} \u{2502} \u{fae6}({}, {}) ",
+            self.doc[self.tab].format.charset.name(),
+            self.doc[self.tab].format.line_ending.name(),
             self.doc[self.tab].cursor.y + self.doc[self.tab].offset.y + 1 - OFFSET,
-            self.doc[self.tab].rows.len(),
+            self.doc[self.tab].buffer.len_lines(),
             self.doc[self.tab].cursor.x + self.doc[self.tab].offset.x,
             self.doc[self.tab].cursor.y + self.doc[self.tab].offset.y,
         );
@@ -652,8 +1976,8 @@ impl Editor {
         format!(
             "{}{}{}{}{}{}{}",
             style::Bold,
-            Reader::rgb_fg(self.config.theme.status_fg),
-            Reader::rgb_bg(self.config.theme.status_bg),
+            Reader::rgb_fg(self.config().theme.status_fg),
+            Reader::rgb_bg(self.config().theme.status_bg),
             trim_end(
                 &format!("{}{}{}", left, padding, right),
                 self.term.size.width
@@ -663,50 +1987,58 @@ impl Editor {
             style::Reset,
         )
     }
-    fn add_background(&self, text: &str) -> String {
-        // Add a background colour to a line
+    fn add_background(&self, text: &str, width: usize) -> String {
+        // Add a background colour to a line, filling it out to a width
+        let padding = width.saturating_sub(self.exp.ansi_len(text));
         format!(
             "{}{}{}{}",
-            Reader::rgb_bg(self.config.theme.editor_bg),
+            Reader::rgb_bg(self.config().theme.editor_bg),
             text,
-            self.term.align_left(&text),
+            " ".repeat(padding),
             RESET_BG
         )
     }
     fn command_line(&self) -> String {
         // Render the command line
         let line = &self.doc[self.tab].cmd_line.text;
+        let width = self.term.size.width;
         // Add the correct styling
         match self.doc[self.tab].cmd_line.msg {
-            Type::Error => self.add_background(&format!(
-                "{}{}{}{}{}",
-                style::Bold,
-                color::Fg(color::Red),
-                self.add_background(&trim_end(&line, self.term.size.width)),
-                color::Fg(color::Reset),
-                style::Reset
-            )),
-            Type::Warning => self.add_background(&format!(
-                "{}{}{}{}{}",
-                style::Bold,
-                color::Fg(color::Yellow),
-                self.add_background(&trim_end(&line, self.term.size.width)),
-                color::Fg(color::Reset),
-                style::Reset
-            )),
-            Type::Info => self.add_background(&trim_end(&line, self.term.size.width)),
+            Type::Error => self.add_background(
+                &format!(
+                    "{}{}{}{}{}",
+                    style::Bold,
+                    color::Fg(color::Red),
+                    self.add_background(&trim_end(line, width), width),
+                    color::Fg(color::Reset),
+                    style::Reset
+                ),
+                width,
+            ),
+            Type::Warning => self.add_background(
+                &format!(
+                    "{}{}{}{}{}",
+                    style::Bold,
+                    color::Fg(color::Yellow),
+                    self.add_background(&trim_end(line, width), width),
+                    color::Fg(color::Reset),
+                    style::Reset
+                ),
+                width,
+            ),
+            Type::Info => self.add_background(&trim_end(line, width), width),
         }
     }
-    fn tab_line(&mut self) -> String {
-        // Render the tab line
+    fn tabs(&self) -> (Vec<(usize, String)>, bool) {
+        // Work out which tabs fit on the tab line, and whether any were cut off the end
         let mut result = vec![];
         let mut widths = vec![];
-        let active = Reader::rgb_bg(self.config.theme.editor_bg);
-        let inactive = Reader::rgb_bg(self.config.theme.status_bg);
+        let active = Reader::rgb_bg(self.config().theme.editor_bg);
+        let inactive = Reader::rgb_bg(self.config().theme.status_bg);
         // Iterate through documents and create their tab text
         for (num, doc) in self.doc.iter().enumerate() {
             let this = format!(
-                "{} {}{}{} {}{}|",
+                "{} {}{}{}{} {}{}|",
                 if num == self.tab {
                     format!("{}{}", style::Bold, active)
                 } else {
@@ -718,12 +2050,13 @@ impl Editor {
                     format!("{} ", doc.icon)
                 },
                 doc.name,
+                if doc.read_only { "[RO]" } else { "" },
                 if doc.dirty { "[+]" } else { "" },
                 style::Reset,
                 inactive.to_string(),
             );
             widths.push(self.exp.ansi_len(this.as_str()));
-            result.push(this);
+            result.push((num, this));
         }
         // Determine if the tab can be rendered on screen
         let mut more_right = true;
@@ -737,13 +2070,20 @@ impl Editor {
                 widths.remove(0);
             }
         }
-        if widths.iter().sum::<usize>() < self.term.size.width.saturating_sub(3) && !more_right {
+        (result, more_right)
+    }
+    fn tab_line(&mut self) -> String {
+        // Render the tab line
+        let (tabs, more_right) = self.tabs();
+        let mut result: Vec<String> = tabs.into_iter().map(|(_, tab)| tab).collect();
+        let width: usize = result.iter().map(|tab| self.exp.ansi_len(tab)).sum();
+        if width < self.term.size.width.saturating_sub(3) && !more_right {
             result.push("...".to_string());
         }
         let result = result.join("");
         format!(
             "{}{}{}{}",
-            Reader::rgb_bg(self.config.theme.status_bg),
+            Reader::rgb_bg(self.config().theme.status_bg),
             result,
             self.term.align_left(&result),
             RESET_BG,
@@ -751,79 +2091,151 @@ impl Editor {
     }
     fn render(&mut self) {
         // Draw the screen to the terminal
-        let offset = self.doc[self.tab].offset;
+        let (panes, dividers) = self.layout.arrange(self.pane_area());
+        let mut parts = vec![];
+        for (index, rect) in panes {
+            parts.push((rect, self.render_pane(index, rect)));
+        }
+        for (split, rect) in dividers {
+            parts.push((rect, self.divider(split, rect)));
+        }
+        parts.sort_by_key(|(rect, _)| rect.x);
+        // Stitch the panes and dividers together, row by row
         let mut frame = vec![self.tab_line()];
-        let rendered = self.doc[self.tab].render();
-        let reg = self.doc[self.tab].regex.clone();
-        for row in OFFSET..self.term.size.height {
-            let row = row.saturating_sub(OFFSET);
-            if let Some(r) = self.doc[self.tab].rows.get_mut(offset.y + row) {
-                r.update_syntax(&self.config, &reg, &rendered, offset.y + row);
-            }
-            if row == self.term.size.height - 1 - OFFSET {
-                // Render command line
-                frame.push(self.command_line());
-            } else if row == self.term.size.height - 2 - OFFSET {
-                // Render status line
-                frame.push(self.status_line());
-            } else if row == self.term.size.height / 4 - OFFSET && self.doc[self.tab].show_welcome {
-                frame.push(self.welcome_message(
+        let area = self.pane_area();
+        for y in area.y..area.y + area.height {
+            frame.push(
+                parts
+                    .iter()
+                    .filter(|(rect, _)| y >= rect.y && y < rect.y + rect.height)
+                    .map(|(rect, rows)| rows[y - rect.y].as_str())
+                    .collect(),
+            );
+        }
+        frame.push(self.status_line());
+        frame.push(self.command_line());
+        print!("{}", frame.join("\r\n"));
+    }
+    fn render_pane(&mut self, index: usize, rect: Rect) -> Vec<String> {
+        // Draw the rows of a pane, looking at its document through its own view
+        let size = Size {
+            width: rect.width,
+            height: rect.height + 3,
+        };
+        let tab = self.panes[index].doc;
+        let live = if index == self.pane {
+            None
+        } else {
+            Some(self.load_view(index, &size))
+        };
+        self.doc[tab].recalculate_offset();
+        let bottom = self.doc[tab].offset.y + rect.height;
+        let config = &self.configs[self.doc[tab].config].config;
+        self.doc[tab].highlight(config, bottom);
+        let doc = &self.doc[tab];
+        let width = rect.width;
+        let welcome = rect.height / 4;
+        let mut rows = vec![];
+        for row in 0..rect.height {
+            if row == welcome && doc.show_welcome {
+                rows.push(self.welcome_message(
                     &format!("Ox editor  v{}", VERSION),
-                    Reader::rgb_fg(self.config.theme.editor_fg),
+                    Reader::rgb_fg(config.theme.editor_fg),
+                    width,
                 ));
-            } else if row == (self.term.size.height / 4).saturating_add(1) - OFFSET
-                && self.doc[self.tab].show_welcome
-            {
-                frame.push(self.welcome_message(
+            } else if row == welcome + 1 && doc.show_welcome {
+                rows.push(self.welcome_message(
                     "A Rust powered editor by Luke",
-                    Reader::rgb_fg(self.config.theme.editor_fg),
+                    Reader::rgb_fg(config.theme.editor_fg),
+                    width,
                 ));
-            } else if row == (self.term.size.height / 4).saturating_add(3) - OFFSET
-                && self.doc[self.tab].show_welcome
-            {
-                frame.push(self.welcome_message(
+            } else if row == welcome + 3 && doc.show_welcome {
+                rows.push(self.welcome_message(
                     "Ctrl + Q: Exit   ",
-                    Reader::rgb_fg(self.config.theme.status_fg),
+                    Reader::rgb_fg(config.theme.status_fg),
+                    width,
                 ));
-            } else if row == (self.term.size.height / 4).saturating_add(4) - OFFSET
-                && self.doc[self.tab].show_welcome
-            {
-                frame.push(self.welcome_message(
+            } else if row == welcome + 4 && doc.show_welcome {
+                rows.push(self.welcome_message(
                     "Ctrl + S: Save   ",
-                    Reader::rgb_fg(self.config.theme.status_fg),
+                    Reader::rgb_fg(config.theme.status_fg),
+                    width,
                 ));
-            } else if row == (self.term.size.height / 4).saturating_add(5) - OFFSET
-                && self.doc[self.tab].show_welcome
-            {
-                frame.push(self.welcome_message(
+            } else if row == welcome + 5 && doc.show_welcome {
+                rows.push(self.welcome_message(
                     "Ctrl + W: Save as",
-                    Reader::rgb_fg(self.config.theme.status_fg),
+                    Reader::rgb_fg(config.theme.status_fg),
+                    width,
                 ));
-            } else if let Some(line) = self.doc[self.tab]
-                .rows
-                .get(self.doc[self.tab].offset.y + row)
-            {
+            } else if let Some(mut line) = doc.buffer.get(doc.offset.y + row) {
                 // Render lines of code
-                frame.push(self.add_background(&line.render(
-                    self.doc[self.tab].offset.x,
-                    self.term.size.width,
-                    self.doc[self.tab].offset.y + row,
-                    self.doc[self.tab].line_offset,
-                    &self.config,
-                )));
+                if let Some(tokens) = doc.cache.tokens(doc.offset.y + row) {
+                    line.syntax.clone_from(tokens);
+                }
+                rows.push(self.add_background(
+                    &line.render(
+                        doc.offset.x,
+                        width,
+                        doc.offset.y + row,
+                        doc.gutter(),
+                        &config.theme,
+                        doc.selected_columns(doc.offset.y + row),
+                    ),
+                    width,
+                ));
             } else {
                 // Render empty lines
-                frame.push(format!(
+                rows.push(format!(
                     "{}{}{}",
-                    Reader::rgb_fg(self.config.theme.line_number_fg),
-                    self.add_background(&format!(
-                        "{}~",
-                        " ".repeat(self.config.general.line_number_padding_left)
-                    )),
+                    Reader::rgb_fg(config.theme.line_number_fg),
+                    self.add_background(
+                        &trim_end(
+                            &format!("{}~", " ".repeat(config.general.line_number_padding_left)),
+                            width
+                        ),
+                        width
+                    ),
                     RESET_FG
                 ));
             }
         }
-        print!("{}", frame.join("\r\n"));
+        if let Some(live) = live {
+            self.store_view(index, live);
+        }
+        rows
     }
+    fn divider(&self, split: Split, rect: Rect) -> Vec<String> {
+        // Draw the line between two panes
+        let line = match split {
+            Split::Vertical => "\u{2502}".to_string(),
+            Split::Horizontal => "\u{2500}".repeat(rect.width),
+        };
+        let row = format!(
+            "{}{}{}{}{}",
+            Reader::rgb_bg(self.config().theme.editor_bg),
+            Reader::rgb_fg(self.config().theme.line_number_fg),
+            line,
+            RESET_FG,
+            RESET_BG,
+        );
+        vec![row; rect.height]
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    // Check if two paths lead to the same file, which has to exist
+    let a = fs::canonicalize(a).ok();
+    a.is_some() && a == fs::canonicalize(b).ok()
+}
+
+fn modified(paths: &[String]) -> Vec<Option<SystemTime>> {
+    // Find when each file was last written to
+    paths
+        .iter()
+        .map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
 }
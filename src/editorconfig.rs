@@ -0,0 +1,402 @@
+// EditorConfig.rs - For reading the .editorconfig files that apply to a document
+use crate::config::General;
+use crate::format::{Charset, Format, LineEnding};
+use crate::util::absolute;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// The name of the files to look for
+const FILE: &str = ".editorconfig";
+
+pub fn apply(path: &str, general: &mut General, format: &mut Format) {
+    // Change the settings of a document to follow the .editorconfig files above it
+    let properties = properties(path);
+    let get = |key: &str| properties.get(key).map(String::as_str);
+    match get("indent_style") {
+        Some("tab") => general.hard_tabs = true,
+        Some("space") => general.hard_tabs = false,
+        _ => (),
+    }
+    let tab_width = get("tab_width").and_then(|width| width.parse().ok());
+    let indent_size = match get("indent_size") {
+        Some("tab") => tab_width,
+        size => size.and_then(|size| size.parse().ok()).or(tab_width),
+    };
+    if let Some(width) = indent_size.filter(|&width| width > 0) {
+        general.tab_width = width;
+    }
+    if let Some(line_ending) = get("end_of_line").and_then(LineEnding::parse) {
+        format.line_ending = line_ending;
+    }
+    if let Some(charset) = get("charset").and_then(Charset::parse) {
+        format.charset = charset;
+    }
+    match get("trim_trailing_whitespace") {
+        Some("true") => format.trim = true,
+        Some("false") => format.trim = false,
+        _ => (),
+    }
+    match get("insert_final_newline") {
+        Some("true") => format.final_newline = true,
+        Some("false") => format.final_newline = false,
+        _ => (),
+    }
+}
+
+fn properties(path: &str) -> HashMap<String, String> {
+    // Gather the properties for a file, with closer files taking precedence
+    let mut result = HashMap::new();
+    if path.is_empty() {
+        return result;
+    }
+    let path = absolute(Path::new(path));
+    let target = path.to_string_lossy();
+    let mut files = vec![];
+    for dir in path.ancestors().skip(1) {
+        if let Ok(contents) = fs::read_to_string(dir.join(FILE)) {
+            let root = is_root(&contents);
+            files.push((dir.to_path_buf(), contents));
+            if root {
+                break;
+            }
+        }
+    }
+    for (dir, contents) in files.iter().rev() {
+        let mut matching = false;
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                matching = matches(section, dir, &target);
+            } else if let Some((key, value)) = line.split_once('=') {
+                if matching {
+                    let key = key.trim().to_lowercase();
+                    let value = value.trim().to_lowercase();
+                    if value == "unset" {
+                        result.remove(&key);
+                    } else {
+                        result.insert(key, value);
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+fn is_root(contents: &str) -> bool {
+    // Check if a file says not to look any further up, which it must do before any section
+    contents
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .any(|(key, value)| {
+            key.trim().eq_ignore_ascii_case("root") && value.trim().eq_ignore_ascii_case("true")
+        })
+}
+
+fn matches(glob: &str, dir: &Path, path: &str) -> bool {
+    // Check if a section applies to a file, relative to the folder holding the .editorconfig
+    let mut ranges = vec![];
+    let pattern = translate(glob.strip_prefix('/').unwrap_or(glob), &mut ranges);
+    // Patterns without a slash match files of that name in any folder
+    let within = if glob.contains('/') { "" } else { "(?:.*/)?" };
+    let dir = regex::escape(dir.to_string_lossy().trim_end_matches('/'));
+    let Ok(re) = Regex::new(&format!("^{dir}/{within}{pattern}$")) else {
+        return false;
+    };
+    re.captures(path).is_some_and(|captures| {
+        ranges.iter().enumerate().all(|(i, (low, high))| {
+            captures
+                .get(i + 1)
+                .and_then(|number| number.as_str().parse::<i64>().ok())
+                .is_some_and(|number| (*low..=*high).contains(&number))
+        })
+    })
+}
+
+fn translate(glob: &str, ranges: &mut Vec<(i64, i64)>) -> String {
+    // Turn a glob into a regular expression, noting the number ranges it checks for
+    let chars: Vec<char> = glob.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                result.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                if result.ends_with('/') && chars.get(i + 1) == Some(&'/') {
+                    // A whole folder of ** can stand for no folders at all
+                    result.pop();
+                    result.push_str("(?:/|/.*/)");
+                    i += 1;
+                } else {
+                    result.push_str(".*");
+                }
+            }
+            '*' => result.push_str("[^/]*"),
+            '?' => result.push_str("[^/]"),
+            '[' => match chars[i + 1..].iter().position(|&c| c == ']') {
+                Some(length) if !chars[i + 1..=i + length].contains(&'/') => {
+                    let class: String = chars[i + 1..=i + length].iter().collect();
+                    let (negate, class) = match class.strip_prefix('!') {
+                        Some(class) => ("^", class.to_string()),
+                        None => ("", class),
+                    };
+                    let class = class.replace('\\', "\\\\").replace('[', "\\[");
+                    result.push('[');
+                    result.push_str(negate);
+                    result.push_str(&class.replace('^', "\\^"));
+                    result.push(']');
+                    i += length + 1;
+                }
+                _ => result.push_str("\\["),
+            },
+            '{' => match closing(&chars, i) {
+                Some(end) => {
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    result.push_str(&braces(&inner, ranges));
+                    i = end;
+                }
+                None => result.push_str("\\{"),
+            },
+            c => result.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    result
+}
+
+fn braces(inner: &str, ranges: &mut Vec<(i64, i64)>) -> String {
+    // Translate what is between a pair of braces: a number range or a list of choices
+    if let Some((low, high)) = inner.split_once("..") {
+        if let (Ok(low), Ok(high)) = (low.parse::<i64>(), high.parse::<i64>()) {
+            ranges.push((low.min(high), low.max(high)));
+            return "([+-]?\\d+)".to_string();
+        }
+    }
+    let choices = split(inner);
+    if choices.len() < 2 {
+        return format!("\\{{{}\\}}", translate(inner, ranges));
+    }
+    let choices: Vec<String> = choices
+        .iter()
+        .map(|choice| translate(choice, ranges))
+        .collect();
+    format!("(?:{})", choices.join("|"))
+}
+
+fn closing(chars: &[char], open: usize) -> Option<usize> {
+    // Find the brace that closes the one at an index, allowing for nested braces
+    let mut depth = 0;
+    let mut i = open;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+fn split(inner: &str) -> Vec<String> {
+    // Split the choices within braces at the commas that aren't in nested braces
+    let mut result = vec![String::new()];
+    let mut depth = 0;
+    let mut escaped = false;
+    for c in inner.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(String::new());
+                continue;
+            }
+            _ => (),
+        }
+        if let Some(last) = result.last_mut() {
+            last.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn glob(glob: &str, file: &str) -> bool {
+        // Check a section against a file in the folder of the .editorconfig
+        matches(glob, Path::new("/project"), &format!("/project/{file}"))
+    }
+
+    fn folder(name: &str) -> PathBuf {
+        // Make an empty folder to lay out .editorconfig files in
+        let folder = env::temp_dir().join(format!("ox-editorconfig-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn star() {
+        assert!(glob("a*e.c", "ace.c"));
+        assert!(glob("a*e.c", "abcde.c"));
+        assert!(glob("a*e.c", "ae.c"));
+        assert!(glob("a*e.c", "sub/ace.c"));
+        assert!(!glob("a*e.c", "a/e.c"));
+        assert!(glob("*", "sub/any.txt"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(glob("a?e.c", "abe.c"));
+        assert!(!glob("a?e.c", "ae.c"));
+        assert!(!glob("a?e.c", "a/e.c"));
+    }
+
+    #[test]
+    fn star_star() {
+        assert!(glob("a**z.c", "a/z.c"));
+        assert!(glob("a**z.c", "amnz.c"));
+        assert!(glob("a**z.c", "am/nz.c"));
+        assert!(glob("a**z.c", "a/mn/z.c"));
+        assert!(glob("b/**z.c", "b/z.c"));
+        assert!(glob("b/**z.c", "b/mn/z.c"));
+        assert!(glob("c**/z.c", "c/z.c"));
+        assert!(glob("c**/z.c", "cmn/z.c"));
+        assert!(glob("c**/z.c", "c/mn/z.c"));
+        assert!(glob("d/**/z.c", "d/z.c"));
+        assert!(glob("d/**/z.c", "d/mn/z.c"));
+        assert!(glob("d/**/z.c", "d/mn/op/z.c"));
+        assert!(!glob("d/**/z.c", "dz.c"));
+    }
+
+    #[test]
+    fn brackets() {
+        assert!(glob("[ab].a", "a.a"));
+        assert!(glob("[ab].a", "b.a"));
+        assert!(!glob("[ab].a", "c.a"));
+        assert!(glob("[!ab].b", "c.b"));
+        assert!(!glob("[!ab].b", "a.b"));
+        assert!(glob("[a-c].c", "b.c"));
+        assert!(!glob("[a-c].c", "d.c"));
+        assert!(glob("[!a-c].d", "d.d"));
+        assert!(!glob("[!a-c].d", "b.d"));
+        // A slash can't be in a class, so the brackets are taken as they are
+        assert!(glob("ab[e/]cd.i", "ab[e/]cd.i"));
+        assert!(!glob("ab[e/]cd.i", "abecd.i"));
+        assert!(glob("ab[/c", "ab[/c"));
+    }
+
+    #[test]
+    fn braces() {
+        assert!(glob("*.{py,js,html}", "a.py"));
+        assert!(glob("*.{py,js,html}", "a.js"));
+        assert!(glob("*.{py,js,html}", "a.html"));
+        assert!(!glob("*.{py,js,html}", "a.c"));
+        assert!(glob("{a,{b,c}}.n", "c.n"));
+        // Braces without a choice in them are taken as they are
+        assert!(glob("{single}.b", "{single}.b"));
+        assert!(!glob("{single}.b", "single.b"));
+        assert!(glob("{}.c", "{}.c"));
+        assert!(glob("{word,{also},this}.g", "word.g"));
+        assert!(glob("{word,{also},this}.g", "{also}.g"));
+        assert!(glob("{word,{also},this}.g", "this.g"));
+        assert!(!glob("{word,{also},this}.g", "also.g"));
+    }
+
+    #[test]
+    fn number_ranges() {
+        assert!(glob("{3..120}", "3"));
+        assert!(glob("{3..120}", "15"));
+        assert!(glob("{3..120}", "120"));
+        assert!(!glob("{3..120}", "1"));
+        assert!(!glob("{3..120}", "121"));
+        assert!(!glob("{3..120}", "5a"));
+        assert!(glob("file{-2..2}.txt", "file-1.txt"));
+        // Ranges of words aren't ranges at all
+        assert!(glob("{aardvark..antelope}", "{aardvark..antelope}"));
+        assert!(!glob("{aardvark..antelope}", "aardvark"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(glob("a\\*.e", "a*.e"));
+        assert!(!glob("a\\*.e", "ab.e"));
+        assert!(glob("a+(b).f", "a+(b).f"));
+    }
+
+    #[test]
+    fn slashes() {
+        // Sections with a slash in them are relative to the folder of the .editorconfig
+        assert!(glob("Bar/*", "Bar/foo.txt"));
+        assert!(!glob("Bar/*", "Bar/sub/foo.txt"));
+        assert!(!glob("Bar/*", "sub/Bar/foo.txt"));
+        assert!(glob("/a.txt", "a.txt"));
+        assert!(!glob("/a.txt", "sub/a.txt"));
+    }
+
+    #[test]
+    fn root() {
+        assert!(is_root("root = true\n[*]\nindent_style = tab"));
+        assert!(is_root("# Top\nROOT=True\n"));
+        assert!(!is_root("[*]\nroot = true"));
+        assert!(!is_root("root = false"));
+    }
+
+    #[test]
+    fn layering() {
+        let top = folder("layering");
+        let project = top.join("project");
+        let sub = project.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        let write = |dir: &Path, contents: &str| fs::write(dir.join(FILE), contents).unwrap();
+        write(&top, "[*]\ncharset = latin1\n");
+        write(
+            &project,
+            "root = true\n[*]\nindent_style = space\nindent_size = 4\n",
+        );
+        write(
+            &sub,
+            "[*.rs]\nindent_size = 2\n[*.md]\nindent_style = unset\n",
+        );
+        let file = |name: &str| properties(&sub.join(name).to_string_lossy());
+        // Closer files win, and nothing above the root is read
+        let rust = file("main.rs");
+        assert_eq!(rust.get("indent_size").map(String::as_str), Some("2"));
+        assert_eq!(rust.get("indent_style").map(String::as_str), Some("space"));
+        assert_eq!(rust.get("charset"), None);
+        // Unset takes a property back out, as if it was never set
+        let markdown = file("notes.md");
+        assert_eq!(markdown.get("indent_style"), None);
+        assert_eq!(markdown.get("indent_size").map(String::as_str), Some("4"));
+        // Without the root, the properties from further up apply too
+        write(&project, "[*]\nindent_style = space\n");
+        assert_eq!(
+            file("main.rs").get("charset").map(String::as_str),
+            Some("latin1")
+        );
+        fs::remove_dir_all(&top).unwrap();
+    }
+}
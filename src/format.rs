@@ -0,0 +1,315 @@
+// Format.rs - For how the text of a document is laid out on disk
+use crate::Buffer;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+// The characters that end each line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,   // Unix
+    Crlf, // Windows
+    Cr,   // Classic Mac OS
+}
+
+// The character encoding of a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    Utf8,    // UTF-8 without a byte order mark
+    Utf8Bom, // UTF-8 with a byte order mark
+    Latin1,  // ISO-8859-1
+    Utf16Be, // Big endian UTF-16, with a byte order mark
+    Utf16Le, // Little endian UTF-16, with a byte order mark
+}
+
+// Everything about a file that isn't kept in the buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    pub line_ending: LineEnding, // What to end each line with
+    pub charset: Charset,        // How to encode the text
+    pub trim: bool,              // Whether to remove whitespace from the end of lines
+    pub final_newline: bool,     // Whether to end the file with a line ending
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        // Unix line endings in UTF-8, ending the file with a newline
+        Self {
+            line_ending: LineEnding::Lf,
+            charset: Charset::Utf8,
+            trim: false,
+            final_newline: true,
+        }
+    }
+}
+
+// The names that line endings and encodings are written as in commands and .editorconfig files
+pub const LINE_ENDINGS: &[&str] = &["lf", "crlf", "cr"];
+pub const CHARSETS: &[&str] = &["utf-8", "utf-8-bom", "latin1", "utf-16be", "utf-16le"];
+
+impl LineEnding {
+    pub fn parse(name: &str) -> Option<Self> {
+        // Read a line ending from its name
+        match name {
+            "lf" => Some(Self::Lf),
+            "crlf" => Some(Self::Crlf),
+            "cr" => Some(Self::Cr),
+            _ => None,
+        }
+    }
+    fn detect(text: &str) -> Self {
+        // Work out which line ending a text uses most
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        let cr = text.matches('\r').count() - crlf;
+        if crlf > lf && crlf >= cr {
+            Self::Crlf
+        } else if cr > lf && cr > crlf {
+            Self::Cr
+        } else {
+            Self::Lf
+        }
+    }
+    pub fn name(self) -> &'static str {
+        // Get the name of the line ending, as shown in the status line
+        match self {
+            Self::Lf => "LF",
+            Self::Crlf => "CRLF",
+            Self::Cr => "CR",
+        }
+    }
+    pub fn as_str(self) -> &'static str {
+        // Get the characters that end a line
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+}
+
+impl Charset {
+    pub fn parse(name: &str) -> Option<Self> {
+        // Read an encoding from its name
+        match name {
+            "utf-8" => Some(Self::Utf8),
+            "utf-8-bom" => Some(Self::Utf8Bom),
+            "latin1" => Some(Self::Latin1),
+            "utf-16be" => Some(Self::Utf16Be),
+            "utf-16le" => Some(Self::Utf16Le),
+            _ => None,
+        }
+    }
+    fn detect(bytes: &[u8]) -> Self {
+        // Work out the encoding of a file from its byte order mark, or whether it is valid UTF-8
+        [Self::Utf8Bom, Self::Utf16Be, Self::Utf16Le]
+            .iter()
+            .copied()
+            .find(|charset| bytes.starts_with(charset.bom()))
+            .unwrap_or_else(|| {
+                if std::str::from_utf8(bytes).is_ok() {
+                    Self::Utf8
+                } else {
+                    Self::Latin1
+                }
+            })
+    }
+    pub fn name(self) -> &'static str {
+        // Get the name of the encoding, as shown in the status line
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf8Bom => "UTF-8 BOM",
+            Self::Latin1 => "Latin-1",
+            Self::Utf16Be => "UTF-16 BE",
+            Self::Utf16Le => "UTF-16 LE",
+        }
+    }
+    fn bom(self) -> &'static [u8] {
+        // Get the byte order mark that starts a file
+        match self {
+            Self::Utf8 | Self::Latin1 => &[],
+            Self::Utf8Bom => &[0xEF, 0xBB, 0xBF],
+            Self::Utf16Be => &[0xFE, 0xFF],
+            Self::Utf16Le => &[0xFF, 0xFE],
+        }
+    }
+    fn encode(self, text: &str) -> Vec<u8> {
+        // Turn text into bytes, writing a question mark for anything Latin-1 can't hold
+        match self {
+            Self::Utf8 | Self::Utf8Bom => text.as_bytes().to_vec(),
+            Self::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+                .collect(),
+            Self::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Self::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        }
+    }
+    fn decode(self, bytes: &[u8]) -> Option<String> {
+        // Turn the bytes of a file into text, failing if they aren't valid
+        let bytes = bytes.strip_prefix(self.bom()).unwrap_or(bytes);
+        match self {
+            Self::Utf8 | Self::Utf8Bom => String::from_utf8(bytes.to_vec()).ok(),
+            Self::Latin1 => Some(bytes.iter().map(|&b| char::from(b)).collect()),
+            Self::Utf16Be | Self::Utf16Le => {
+                let pairs = bytes.chunks_exact(2);
+                // A byte left over means it isn't really UTF-16
+                if !pairs.remainder().is_empty() {
+                    return None;
+                }
+                let units: Vec<u16> = pairs
+                    .map(|pair| match self {
+                        Self::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                        _ => u16::from_le_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                String::from_utf16(&units).ok()
+            }
+        }
+    }
+}
+
+impl Format {
+    pub fn detect(bytes: &[u8]) -> (Self, String) {
+        // Work out how a file was written, returning its text with plain newlines between lines
+        let mut charset = Charset::detect(bytes);
+        let text = charset.decode(bytes).unwrap_or_else(|| {
+            // Anything can be read as Latin-1, such as UTF-16 with unpaired surrogates
+            charset = Charset::Latin1;
+            bytes.iter().map(|&b| char::from(b)).collect()
+        });
+        let line_ending = LineEnding::detect(&text);
+        let mut text = text.replace("\r\n", "\n");
+        if line_ending == LineEnding::Cr {
+            text = text.replace('\r', "\n");
+        }
+        let final_newline = text.is_empty() || text.ends_with('\n');
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        let format = Self {
+            line_ending,
+            charset,
+            trim: false,
+            final_newline,
+        };
+        (format, text)
+    }
+    pub fn write<W: Write>(self, buffer: &Buffer, mut writer: W) -> io::Result<()> {
+        // Write a buffer out line by line in this format
+        writer.write_all(self.charset.bom())?;
+        let ending = self.charset.encode(self.line_ending.as_str());
+        let last = buffer.len_lines().saturating_sub(1);
+        for (y, row) in buffer.lines().enumerate() {
+            let line = if self.trim {
+                row.string.trim_end()
+            } else {
+                &row.string
+            };
+            writer.write_all(&self.charset.encode(line))?;
+            if y < last || self.final_newline {
+                writer.write_all(&ending)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> Format {
+        // Read a file and write it back out, checking that nothing about it changed
+        let (format, text) = Format::detect(bytes);
+        let mut written = vec![];
+        format.write(&Buffer::new(&text, 4), &mut written).unwrap();
+        assert_eq!(written, bytes);
+        format
+    }
+
+    fn utf16_bytes(text: &str, big_endian: bool) -> Vec<u8> {
+        // Encode some text as UTF-16 with a byte order mark
+        let mut bytes = if big_endian {
+            vec![0xFE, 0xFF]
+        } else {
+            vec![0xFF, 0xFE]
+        };
+        for unit in text.encode_utf16() {
+            if big_endian {
+                bytes.extend(unit.to_be_bytes());
+            } else {
+                bytes.extend(unit.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(round_trip(b"one\ntwo\n").line_ending, LineEnding::Lf);
+        assert_eq!(round_trip(b"one\r\ntwo\r\n").line_ending, LineEnding::Crlf);
+        assert_eq!(round_trip(b"one\rtwo\r").line_ending, LineEnding::Cr);
+        assert_eq!(round_trip(b"\n\n").line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn mixed_line_endings() {
+        // The line ending used most wins, with ties going to LF
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\nb\nc\r\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\rb\rc\r\n"), LineEnding::Cr);
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Lf);
+        // Every line is read with plain newlines, whatever it ended with
+        let (format, text) = Format::detect(b"a\r\nb\r\nc\n");
+        assert_eq!(text, "a\nb\nc");
+        let mut written = vec![];
+        format.write(&Buffer::new(&text, 4), &mut written).unwrap();
+        assert_eq!(written, b"a\r\nb\r\nc\r\n");
+    }
+
+    #[test]
+    fn final_newline() {
+        assert!(round_trip(b"one\ntwo\n").final_newline);
+        assert!(!round_trip(b"one\ntwo").final_newline);
+        assert!(!round_trip(b"one\r\ntwo").final_newline);
+        assert!(round_trip(b"\n").final_newline);
+        // Empty files get a line ending once something is typed into them
+        assert!(Format::detect(b"").0.final_newline);
+    }
+
+    #[test]
+    fn utf8_bom() {
+        let bytes = b"\xEF\xBB\xBFcaf\xC3\xA9\r\n";
+        assert_eq!(round_trip(bytes).charset, Charset::Utf8Bom);
+        assert_eq!(Format::detect(bytes).1, "caf\u{e9}");
+        assert_eq!(round_trip("caf\u{e9}\n".as_bytes()).charset, Charset::Utf8);
+    }
+
+    #[test]
+    fn utf16() {
+        let little = utf16_bytes("caf\u{e9} \u{1f600}\r\nend\r\n", false);
+        let format = round_trip(&little);
+        assert_eq!(format.charset, Charset::Utf16Le);
+        assert_eq!(format.line_ending, LineEnding::Crlf);
+        assert_eq!(Format::detect(&little).1, "caf\u{e9} \u{1f600}\nend");
+        let big = utf16_bytes("one\ntwo", true);
+        let format = round_trip(&big);
+        assert_eq!(format.charset, Charset::Utf16Be);
+        assert!(!format.final_newline);
+    }
+
+    #[test]
+    fn odd_length_utf16() {
+        // A byte left over is kept by reading the file as Latin-1 instead
+        let mut bytes = utf16_bytes("hi\n", false);
+        bytes.push(b'!');
+        assert_eq!(round_trip(&bytes).charset, Charset::Latin1);
+    }
+
+    #[test]
+    fn latin1() {
+        let bytes = b"caf\xE9\nna\xEFve\n";
+        assert_eq!(round_trip(bytes).charset, Charset::Latin1);
+        assert_eq!(Format::detect(bytes).1, "caf\u{e9}\nna\u{ef}ve");
+    }
+}
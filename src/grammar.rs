@@ -0,0 +1,147 @@
+// Grammar.rs - For highlighting with the tree-sitter grammars bundled with ox
+use crate::config::Reader;
+use crate::Buffer;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Node, Parser, Query, QueryCursor, Tree};
+
+// How the names that grammars give to what they capture map onto the highlights in the config
+const KINDS: &[(&str, &str)] = &[
+    ("comment", "comments"),
+    ("keyword", "keywords"),
+    ("variable.builtin", "keywords"),
+    ("string.special.symbol", "symbols"),
+    ("string", "strings"),
+    ("character", "characters"),
+    ("number", "digits"),
+    ("constant.builtin", "booleans"),
+    ("function.macro", "macros"),
+    ("function", "functions"),
+    ("type", "structs"),
+    ("constructor", "structs"),
+    ("attribute", "attributes"),
+];
+
+// A parser for a document, along with what it last made of the document
+pub struct Grammar {
+    parser: Parser,             // For parsing the document
+    query: Query,               // For picking out what to highlight
+    kinds: Vec<Option<String>>, // The highlight to use for each capture in the query
+    tree: Option<Tree>,         // The last parse of the document
+}
+
+impl Grammar {
+    pub fn new(config: &Reader, extension: &str) -> Option<Self> {
+        // Find the grammar for a file extension, if there is one and they are turned on
+        if !config.general(extension).tree_sitter {
+            return None;
+        }
+        let lang = config
+            .languages
+            .iter()
+            .find(|lang| lang.extensions.iter().any(|ext| ext == extension))?;
+        let (language, highlights): (Language, &str) = match lang.name.as_str() {
+            "Rust" => (
+                tree_sitter_rust::LANGUAGE.into(),
+                tree_sitter_rust::HIGHLIGHTS_QUERY,
+            ),
+            "Ruby" => (
+                tree_sitter_ruby::LANGUAGE.into(),
+                tree_sitter_ruby::HIGHLIGHTS_QUERY,
+            ),
+            "Python" => (
+                tree_sitter_python::LANGUAGE.into(),
+                tree_sitter_python::HIGHLIGHTS_QUERY,
+            ),
+            "Javascript" => (
+                tree_sitter_javascript::LANGUAGE.into(),
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+            ),
+            "C" => (
+                tree_sitter_c::LANGUAGE.into(),
+                tree_sitter_c::HIGHLIGHT_QUERY,
+            ),
+            _ => return None,
+        };
+        let mut parser = Parser::new();
+        parser.set_language(&language).ok()?;
+        let query = Query::new(&language, highlights).ok()?;
+        let kinds = query
+            .capture_names()
+            .iter()
+            .map(|name| kind(name, config))
+            .collect();
+        Some(Self {
+            parser,
+            query,
+            kinds,
+            tree: None,
+        })
+    }
+    pub fn parse(&mut self, buffer: &Buffer, edits: Option<&[InputEdit]>) -> Option<usize> {
+        // Bring the parse up to date, returning the first line that may now highlight differently
+        let old = match (self.tree.take(), edits) {
+            (Some(tree), Some([])) => {
+                self.tree = Some(tree);
+                return None;
+            }
+            (Some(mut tree), Some(edits)) => {
+                // Tell the old parse what changed so that the parser can reuse what it can
+                for edit in edits {
+                    tree.edit(edit);
+                }
+                Some(tree)
+            }
+            _ => None,
+        };
+        let new = self.parser.parse_with(
+            &mut |byte, _| buffer.chunk_at(byte).as_bytes(),
+            old.as_ref(),
+        )?;
+        let first = match old {
+            Some(old) => old
+                .changed_ranges(&new)
+                .map(|range| range.start_point.row)
+                .chain(edits.into_iter().flatten().map(|e| e.start_position.row))
+                .min(),
+            None => Some(0),
+        };
+        self.tree = Some(new);
+        first
+    }
+    pub fn captures(&self, buffer: &Buffer, start: usize, end: usize) -> Vec<(usize, usize, &str)> {
+        // Find what to highlight between two bytes, with the highlight to use for each
+        let mut result = vec![];
+        if let Some(tree) = &self.tree {
+            let mut cursor = QueryCursor::new();
+            cursor.set_byte_range(start..end);
+            let text = |node: Node| {
+                buffer
+                    .chunks(node.start_byte(), node.end_byte())
+                    .map(str::as_bytes)
+            };
+            let mut captures = cursor.captures(&self.query, tree.root_node(), text);
+            while let Some((found, index)) = captures.next() {
+                let capture = found.captures[*index];
+                if let Some(kind) = &self.kinds[capture.index as usize] {
+                    result.push((
+                        capture.node.start_byte(),
+                        capture.node.end_byte(),
+                        kind.as_str(),
+                    ));
+                }
+            }
+        }
+        result
+    }
+}
+
+fn kind(name: &str, config: &Reader) -> Option<String> {
+    // Find the highlight for a capture, trying less specific names until one is known
+    let mut name = name;
+    loop {
+        if let Some((_, kind)) = KINDS.iter().find(|(capture, _)| *capture == name) {
+            return config.highlights.get(*kind).map(|_| (*kind).to_string());
+        }
+        name = &name[..name.rfind('.')?];
+    }
+}
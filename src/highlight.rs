@@ -1,14 +1,25 @@
 // Highlight.rs - For syntax highlighting
-use crate::config::{Reader, TokenType};
+use crate::config::{Style, TokenType};
+use crate::grammar::Grammar;
+use crate::textmate::TextMate;
+use crate::util::column_after;
+use crate::{Buffer, Row};
+use regex::Regex;
+use std::cmp;
 use std::collections::HashMap;
-use unicode_width::UnicodeWidthStr;
+use syntect::parsing::{ParseState, ScopeStack};
+
+// How many lines past the line a search starts on that a multi-line match may reach
+const REACH: usize = 500;
+// How many lines a search that found nothing is trusted for before searching again further down
+const STEP: usize = REACH / 2;
 
 // Tokens for storing syntax highlighting info
 #[derive(Debug, Clone)]
 pub struct Token {
     pub span: (usize, usize),
     pub data: String,
-    pub kind: String,
+    pub kind: Style,
     pub priority: bool,
 }
 
@@ -22,134 +33,434 @@ pub fn cine(token: &Token, hashmap: &mut HashMap<usize, Token>) {
     hashmap.insert(token.span.0, token.clone());
 }
 
-fn bounds(reg: &regex::Match, line: &str) -> (usize, usize) {
-    // Work out the width of the capture
-    let unicode_width = UnicodeWidthStr::width(reg.as_str());
-    let pre_length = UnicodeWidthStr::width(&line[..reg.start()]);
-    // Calculate the correct boundaries for syntax highlighting
-    (pre_length, pre_length + unicode_width)
+fn bounds(reg: &regex::Match, line: &str, tab_width: usize) -> (usize, usize) {
+    // Work out the columns that the capture starts and ends at
+    let start = column_after(&line[..reg.start()], 0, tab_width);
+    (start, column_after(reg.as_str(), start, tab_width))
 }
 
-fn multi_to_single(doc: &str, m: &regex::Match) -> ((usize, usize), (usize, usize)) {
-    let b = bounds(&m, &doc);
-    let start_y = doc[..m.start()].matches('\n').count();
-    let end_y = doc[..m.end()].matches('\n').count();
-    let start_x = b.0
-        - UnicodeWidthStr::width(&doc.split('\n').take(start_y).collect::<Vec<_>>().join("\n")[..]);
-    let end_x = b.1
-        - UnicodeWidthStr::width(&doc.split('\n').take(end_y).collect::<Vec<_>>().join("\n")[..]);
-    ((start_x, start_y), (end_x, end_y))
+// Highlighting worked out for a line, kept until it or a line above it changes
+#[derive(Debug)]
+struct Line {
+    tokens: HashMap<usize, Token>, // The tokens on the line, with nested ones removed
+    carry: Carry,                  // What the line passes on to the line after it
 }
 
-pub fn highlight(
-    row: &str,
-    doc: &str,
-    index: usize,
-    regex: &[TokenType],
-    highlights: &HashMap<String, (u8, u8, u8)>,
-) -> HashMap<usize, Token> {
-    // Generate syntax highlighting information
-    let mut syntax: HashMap<usize, Token> = HashMap::new();
-    if regex.is_empty() {
-        // Language not found, return empty hashmap
-        return syntax;
-    }
-    for exps in regex {
-        match exps {
-            TokenType::SingleLine(name, regex) => {
-                if name == "keywords" {
-                    for kw in regex {
-                        // Locate keywords
-                        for cap in kw.captures_iter(row) {
-                            let cap = cap.get(cap.len().saturating_sub(1)).unwrap();
-                            let boundaries = bounds(&cap, &row);
-                            cine(
-                                &Token {
-                                    span: boundaries,
-                                    data: cap.as_str().to_string(),
-                                    kind: Reader::rgb_fg(highlights["keywords"]).to_string(),
-                                    priority: false,
-                                },
-                                &mut syntax,
-                            );
-                        }
+// What the end of a line passes on to the next line
+#[derive(Debug)]
+enum Carry {
+    Nothing,                                 // The whole document was parsed at once
+    Regex(Vec<State>),                       // How far each multi-line expression got
+    TextMate(Box<(ParseState, ScopeStack)>), // Where the grammar's parser got to
+}
+
+// For remembering the highlighting of each line between frames
+#[derive(Debug, Default)]
+pub struct Cache {
+    lines: Vec<Line>, // The lines from the top of the document that are up to date
+}
+
+// A match of a multi-line expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Found {
+    start: usize,         // Byte where the whole match starts
+    end: usize,           // Byte where the whole match ends
+    span: (usize, usize), // Bytes of the capture that gets highlighted
+}
+
+// Where a multi-line expression was searching from, and the last match it found
+#[derive(Debug, Default, Clone, Copy)]
+struct State {
+    search: usize,        // Byte the search for the match started from
+    found: Option<Found>, // The match, which may still be going at the line end
+    next: usize,          // Byte the search for the match after it starts from
+}
+
+// The lines that multi-line expressions are searched in, so the whole document isn't joined
+struct Window<'a> {
+    buffer: &'a Buffer, // The document being searched
+    first: usize,       // The first line held
+    last: usize,        // The last line held
+    base: usize,        // Byte where the first line starts
+    text: String,       // The lines held, joined by newlines
+}
+
+impl Cache {
+    pub fn invalidate(&mut self, y: usize) {
+        // Forget the highlighting of a line and every line after it
+        self.lines.truncate(y);
+    }
+    pub fn tokens(&self, y: usize) -> Option<&HashMap<usize, Token>> {
+        // Get the highlighting of a line, if it is up to date
+        self.lines.get(y).map(|line| &line.tokens)
+    }
+    pub fn update(
+        &mut self,
+        buffer: &Buffer,
+        regex: &[TokenType],
+        grammar: Option<&Grammar>,
+        textmate: Option<&TextMate>,
+        highlights: &HashMap<String, Style>,
+        until: usize,
+    ) {
+        // Highlight every line up to a line, carrying on from the last line that is up to date
+        let until = cmp::min(until, buffer.len_lines().saturating_sub(1));
+        if let Some(grammar) = grammar {
+            self.update_grammar(buffer, grammar, highlights, until);
+        } else if let Some(textmate) = textmate {
+            self.update_textmate(buffer, textmate, highlights, until);
+        } else {
+            self.update_regex(buffer, regex, highlights, until);
+        }
+    }
+    fn update_grammar(
+        &mut self,
+        buffer: &Buffer,
+        grammar: &Grammar,
+        highlights: &HashMap<String, Style>,
+        until: usize,
+    ) {
+        // Highlight lines using what the parser made of the document
+        let start = self.lines.len();
+        if start > until {
+            return;
+        }
+        let end = buffer
+            .get(until + 1)
+            .map_or(buffer.len_bytes(), |_| buffer.line_to_byte(until + 1));
+        let mut syntax = vec![HashMap::new(); until + 1 - start];
+        for (from, to, name) in grammar.captures(buffer, buffer.line_to_byte(start), end) {
+            if from == to {
+                continue;
+            }
+            // Split up captures that go over several lines
+            let first = cmp::max(buffer.line_of_byte(from), start);
+            let last = cmp::min(buffer.line_of_byte(to - 1), until);
+            for index in first..=last {
+                let row = buffer.line(index);
+                multi_line(
+                    buffer,
+                    (from, to),
+                    index,
+                    &row.string,
+                    name,
+                    highlights,
+                    &mut syntax[index - start],
+                );
+            }
+        }
+        for (index, syntax) in (start..).zip(syntax) {
+            self.lines.push(Line {
+                tokens: remove_nested_tokens(&syntax, &buffer.line(index)),
+                carry: Carry::Nothing,
+            });
+        }
+    }
+    fn update_textmate(
+        &mut self,
+        buffer: &Buffer,
+        textmate: &TextMate,
+        highlights: &HashMap<String, Style>,
+        until: usize,
+    ) {
+        // Highlight lines using a TextMate grammar, starting from where the last line left it
+        let start = self.lines.len();
+        if start > until {
+            return;
+        }
+        let mut parse = match self.lines.last().map(|line| &line.carry) {
+            Some(Carry::TextMate(parse)) => (**parse).clone(),
+            _ => textmate.start(),
+        };
+        for index in start..=until {
+            let row = buffer.line(index);
+            let syntax = textmate.highlight(&row.string, row.tab_width, &mut parse, highlights);
+            self.lines.push(Line {
+                tokens: remove_nested_tokens(&syntax, &row),
+                carry: Carry::TextMate(Box::new(parse.clone())),
+            });
+        }
+    }
+    fn update_regex(
+        &mut self,
+        buffer: &Buffer,
+        regex: &[TokenType],
+        highlights: &HashMap<String, Style>,
+        until: usize,
+    ) {
+        // Highlight lines using the regular expressions from the config
+        if self.lines.len() > until {
+            return;
+        }
+        let mut window = Window::new(buffer);
+        let multi: Vec<&Regex> = regex
+            .iter()
+            .filter_map(|exps| match exps {
+                TokenType::MultiLine(_, regex) => Some(regex),
+                TokenType::SingleLine(..) => None,
+            })
+            .flatten()
+            .collect();
+        // An edit can change a match that starts above it, so go back to where that match starts
+        while let Some(line) = self.lines.last() {
+            let start = self.lines.len();
+            let stale = multi
+                .iter()
+                .zip(line.carry.regex())
+                .filter_map(|(exp, state)| stale(exp, &mut window, *state, start))
+                .min();
+            match stale {
+                Some(y) => self.lines.truncate(y),
+                None => break,
+            }
+        }
+        // Pick up each multi-line expression from where it got to at the end of the last line
+        let start = self.lines.len();
+        let mut state: Vec<State> = match self.lines.last() {
+            Some(line) => line.carry.regex().to_vec(),
+            None => vec![State::default(); multi.len()],
+        };
+        let mut next: Vec<(usize, Option<Found>)> = multi
+            .iter()
+            .zip(&state)
+            .map(|(exp, state)| {
+                let from = match state.found {
+                    Some(found) if buffer.line_of_byte(found.end) >= start => state.search,
+                    _ => state.next,
+                };
+                (from, window.find(exp, from))
+            })
+            .collect();
+        for index in start..=until {
+            let line = buffer.line(index);
+            let row: &str = &line.string;
+            let mut syntax = HashMap::new();
+            let mut k = 0;
+            for exps in regex {
+                match exps {
+                    TokenType::SingleLine(name, regex) => {
+                        single_line(&line, name, regex, highlights, &mut syntax);
                     }
-                } else {
-                    for exp in regex {
-                        // Locate expressions
-                        for cap in exp.captures_iter(row) {
-                            let cap = cap.get(cap.len().saturating_sub(1)).unwrap();
-                            let boundaries = bounds(&cap, &row);
-                            cine(
-                                &Token {
-                                    span: boundaries,
-                                    data: cap.as_str().to_string(),
-                                    kind: Reader::rgb_fg(highlights[name]).to_string(),
-                                    priority: false,
-                                },
-                                &mut syntax,
-                            );
+                    TokenType::MultiLine(name, regex) => {
+                        for exp in regex {
+                            // A search that found nothing only looked so far, so look again here
+                            while let (from, None) = next[k] {
+                                if from > buffer.len_bytes()
+                                    || buffer.line_of_byte(from) + STEP > index
+                                {
+                                    break;
+                                }
+                                let from = buffer.line_to_byte(buffer.line_of_byte(from) + STEP);
+                                next[k] = (from, window.find(exp, from));
+                                state[k].next = from;
+                            }
+                            // Go through the matches of this expression that reach this line
+                            while let (from, Some(found)) = next[k] {
+                                if buffer.line_of_byte(found.start) > index {
+                                    break;
+                                }
+                                multi_line(
+                                    buffer,
+                                    found.span,
+                                    index,
+                                    row,
+                                    name,
+                                    highlights,
+                                    &mut syntax,
+                                );
+                                state[k] = State {
+                                    search: from,
+                                    found: Some(found),
+                                    next: after(buffer, found),
+                                };
+                                if buffer.line_of_byte(found.end) > index {
+                                    // The match carries on onto the next line
+                                    break;
+                                }
+                                let from = state[k].next;
+                                next[k] = (from, window.find(exp, from));
+                            }
+                            k += 1;
                         }
                     }
                 }
             }
-            TokenType::MultiLine(name, regex) => {
-                // Multiline token
-                for exp in regex {
-                    for cap in exp.captures_iter(doc) {
-                        let cap = cap.get(cap.len().saturating_sub(1)).unwrap();
-                        let ((start_x, start_y), (end_x, end_y)) = multi_to_single(&doc, &cap);
-                        if start_y == index {
-                            cine(
-                                &Token {
-                                    span: (
-                                        start_x,
-                                        if start_y == end_y {
-                                            end_x
-                                        } else {
-                                            UnicodeWidthStr::width(row)
-                                        },
-                                    ),
-                                    data: row.to_string(),
-                                    kind: Reader::rgb_fg(highlights[name]).to_string(),
-                                    priority: true,
-                                },
-                                &mut syntax,
-                            )
-                        } else if end_y == index {
-                            cine(
-                                &Token {
-                                    span: (0, end_x),
-                                    data: row.to_string(),
-                                    kind: Reader::rgb_fg(highlights[name]).to_string(),
-                                    priority: true,
-                                },
-                                &mut syntax,
-                            )
-                        } else if (start_y..=end_y).contains(&index) {
-                            cine(
-                                &Token {
-                                    span: (0, UnicodeWidthStr::width(row)),
-                                    data: row.to_string(),
-                                    kind: Reader::rgb_fg(highlights[name]).to_string(),
-                                    priority: true,
-                                },
-                                &mut syntax,
-                            )
-                        }
-                    }
-                }
+            self.lines.push(Line {
+                tokens: remove_nested_tokens(&syntax, &line),
+                carry: Carry::Regex(state.clone()),
+            });
+        }
+    }
+}
+
+impl Carry {
+    fn regex(&self) -> &[State] {
+        // Get how far each multi-line expression got
+        match self {
+            Self::Regex(state) => state,
+            _ => &[],
+        }
+    }
+}
+
+impl<'a> Window<'a> {
+    fn new(buffer: &'a Buffer) -> Self {
+        // Start off holding no lines
+        Self {
+            buffer,
+            first: 0,
+            last: 0,
+            base: 0,
+            text: String::new(),
+        }
+    }
+    fn find(&mut self, exp: &Regex, from: usize) -> Option<Found> {
+        // Find the next match of a multi-line expression that ends within reach of where it starts
+        if from > self.buffer.len_bytes() {
+            return None;
+        }
+        let y = self.buffer.line_of_byte(from);
+        let reach = cmp::min(y + REACH, self.buffer.len_lines() - 1);
+        if self.text.is_empty() || y < self.first || reach > self.last {
+            self.first = y;
+            self.last = cmp::min(y + REACH * 2, self.buffer.len_lines() - 1);
+            self.base = self.buffer.line_to_byte(y);
+            let end = self.buffer.line_end(self.last);
+            self.text = self.buffer.slice_bytes(self.base, end).into_owned();
+        }
+        let text = &self.text[..self.buffer.line_end(reach) - self.base];
+        let mut locations = exp.capture_locations();
+        let whole = exp.captures_read_at(&mut locations, text, from - self.base)?;
+        let span = locations.get(locations.len().saturating_sub(1))?;
+        Some(Found {
+            start: whole.start() + self.base,
+            end: whole.end() + self.base,
+            span: (span.0 + self.base, span.1 + self.base),
+        })
+    }
+}
+
+fn stale(exp: &Regex, window: &mut Window, state: State, start: usize) -> Option<usize> {
+    // Find the first line that needs highlighting again if the text from a line onwards changed
+    let buffer = window.buffer;
+    let line = |byte: usize| buffer.line_of_byte(cmp::min(byte, buffer.len_bytes()));
+    match (state.found, window.find(exp, state.search)) {
+        (Some(old), Some(new)) if old.start == new.start => {
+            if line(old.end) >= start && line(new.end) >= start {
+                // The match still carries on past the lines that are up to date
+                None
+            } else if old.end == new.end {
+                // The match is the same, but the next one may now start above the edit
+                let state = State {
+                    search: state.next,
+                    found: None,
+                    next: state.next,
+                };
+                stale(exp, window, state, start)
+            } else {
+                Some(line(old.start))
             }
         }
+        (Some(old), new) => Some(new.map_or(line(old.start), |new| {
+            cmp::min(line(old.start), line(new.start))
+        })),
+        (None, _) => [state.search, state.next]
+            .iter()
+            .filter_map(|&from| window.find(exp, from))
+            .map(|new| line(new.start))
+            .filter(|&y| y < start)
+            .min(),
     }
-    syntax
 }
 
-pub fn remove_nested_tokens(tokens: &HashMap<usize, Token>, line: &str) -> HashMap<usize, Token> {
+fn after(buffer: &Buffer, found: Found) -> usize {
+    // Work out where to search for the match after a match, stepping over empty ones
+    if found.end == found.start {
+        found.end + buffer.char_at_byte(found.end).map_or(1, char::len_utf8)
+    } else {
+        found.end
+    }
+}
+
+fn single_line(
+    row: &Row,
+    name: &str,
+    regex: &[Regex],
+    highlights: &HashMap<String, Style>,
+    syntax: &mut HashMap<usize, Token>,
+) {
+    // Highlight the matches of expressions that stay on one line
+    let Some(&kind) = highlights.get(name) else {
+        return;
+    };
+    for exp in regex {
+        for cap in exp.captures_iter(&row.string) {
+            let cap = cap.get(cap.len().saturating_sub(1)).unwrap();
+            let boundaries = bounds(&cap, &row.string, row.tab_width);
+            cine(
+                &Token {
+                    span: boundaries,
+                    data: cap.as_str().to_string(),
+                    kind,
+                    priority: false,
+                },
+                syntax,
+            );
+        }
+    }
+}
+
+fn multi_line(
+    buffer: &Buffer,
+    span: (usize, usize),
+    index: usize,
+    row: &str,
+    name: &str,
+    highlights: &HashMap<String, Style>,
+    syntax: &mut HashMap<usize, Token>,
+) {
+    // Highlight the part of a multi-line match that lies on a line
+    let Some(&kind) = highlights.get(name) else {
+        return;
+    };
+    let start_y = buffer.line_of_byte(span.0);
+    let end_y = buffer.line_of_byte(span.1);
+    let line = buffer.line_to_byte(index);
+    let column = |byte: usize| column_after(&row[..byte - line], 0, buffer.tab_width);
+    let width = column_after(row, 0, buffer.tab_width);
+    let span = if start_y == index {
+        (
+            column(span.0),
+            if start_y == end_y {
+                column(span.1)
+            } else {
+                width
+            },
+        )
+    } else if end_y == index {
+        (0, column(span.1))
+    } else if (start_y..=end_y).contains(&index) {
+        (0, width)
+    } else {
+        return;
+    };
+    cine(
+        &Token {
+            span,
+            data: row.to_string(),
+            kind,
+            priority: true,
+        },
+        syntax,
+    );
+}
+
+pub fn remove_nested_tokens(tokens: &HashMap<usize, Token>, line: &Row) -> HashMap<usize, Token> {
     // Remove tokens within tokens
     let mut result = HashMap::new();
     let mut c = 0;
-    while c < line.len() {
+    let length = line.length();
+    while c < length {
         if let Some(t) = tokens.get(&c) {
             result.insert(t.span.0, t.clone());
             c += t.span.1 - t.span.0;
@@ -159,3 +470,145 @@ pub fn remove_nested_tokens(tokens: &HashMap<usize, Token>, line: &str) -> HashM
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Reader;
+
+    fn config() -> Reader {
+        // Get the default config, with the bundled grammars turned on
+        let (mut config, _) = Reader::read(&[]);
+        config.general.tree_sitter = true;
+        config
+    }
+
+    fn code(lines: usize) -> String {
+        // Make some rust with a comment that covers several lines
+        (0..lines)
+            .map(|i| match i {
+                20 => "/* a comment".to_string(),
+                22 => "*/".to_string(),
+                _ => format!("fn f{}() {{ true }} // {}", i, i),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn refresh(
+        cache: &mut Cache,
+        buffer: &mut Buffer,
+        grammar: Option<&mut Grammar>,
+        until: usize,
+    ) {
+        // Bring the highlighting up to date after edits, in the same way documents do
+        let config = config();
+        if let Some(y) = buffer.take_edited() {
+            cache.invalidate(y);
+        }
+        let edits = buffer.take_edits();
+        let grammar = grammar.map(|grammar| {
+            if let Some(y) = grammar.parse(buffer, edits.as_deref()) {
+                cache.invalidate(y);
+            }
+            &*grammar
+        });
+        let regex = Reader::get_syntax_regex(&config, "rs");
+        cache.update(buffer, &regex, grammar, None, &config.highlights, until);
+    }
+
+    fn spans(cache: &Cache) -> Vec<Vec<(usize, usize, String)>> {
+        // Get the tokens of each line in a form that can be compared
+        let mut result = vec![];
+        for line in &cache.lines {
+            let mut tokens: Vec<_> = line
+                .tokens
+                .values()
+                .map(|t| (t.span.0, t.span.1, t.data.clone()))
+                .collect();
+            tokens.sort();
+            result.push(tokens);
+        }
+        result
+    }
+
+    fn fresh(buffer: &Buffer, grammar: bool) -> Vec<Vec<(usize, usize, String)>> {
+        // Highlight a document from scratch
+        let mut buffer = Buffer::new(&buffer.text(), buffer.tab_width);
+        let mut grammar = Grammar::new(&config(), "rs").filter(|_| grammar);
+        let mut cache = Cache::default();
+        refresh(&mut cache, &mut buffer, grammar.as_mut(), usize::MAX);
+        spans(&cache)
+    }
+
+    fn untouched(grammar: bool) {
+        // Lines above an edit keep their highlighting, and lines below it are redone
+        let mut buffer = Buffer::new(&code(100), 4);
+        let mut grammar = Grammar::new(&config(), "rs").filter(|_| grammar);
+        let mut cache = Cache::default();
+        refresh(&mut cache, &mut buffer, grammar.as_mut(), 99);
+        // Mark a line above the edit so that redoing it would show
+        cache.lines[10].tokens.clear();
+        buffer.insert_str("let x = 1; ", 0, 50);
+        refresh(&mut cache, &mut buffer, grammar.as_mut(), 49);
+        assert_eq!(cache.lines.len(), 50);
+        refresh(&mut cache, &mut buffer, grammar.as_mut(), 99);
+        let mut expected = fresh(&buffer, grammar.is_some());
+        expected[10].clear();
+        assert_eq!(spans(&cache), expected);
+    }
+
+    #[test]
+    fn untouched_regex() {
+        untouched(false);
+    }
+
+    #[test]
+    fn untouched_grammar() {
+        untouched(true);
+    }
+
+    #[test]
+    fn edit_line() {
+        // The parser is told where the edit was rather than working it out from the whole text
+        let mut buffer = Buffer::new(&code(100), 4);
+        let mut grammar = Grammar::new(&config(), "rs").unwrap();
+        let edits = buffer.take_edits();
+        assert_eq!(grammar.parse(&buffer, edits.as_deref()), Some(0));
+        buffer.insert_str("let x = 1; ", 0, 50);
+        let edits = buffer.take_edits();
+        assert_eq!(grammar.parse(&buffer, edits.as_deref()), Some(50));
+        let edits = buffer.take_edits();
+        assert_eq!(grammar.parse(&buffer, edits.as_deref()), None);
+    }
+
+    #[test]
+    fn comment_above() {
+        // Closing a comment changes the lines between where it opens and the edit
+        for grammar in [false, true] {
+            let mut buffer = Buffer::new(&code(100), 4);
+            let mut grammar = Grammar::new(&config(), "rs").filter(|_| grammar);
+            let mut cache = Cache::default();
+            buffer.set_line(22, "");
+            refresh(&mut cache, &mut buffer, grammar.as_mut(), 99);
+            buffer.set_line(30, "*/");
+            refresh(&mut cache, &mut buffer, grammar.as_mut(), 99);
+            assert_eq!(spans(&cache), fresh(&buffer, grammar.is_some()));
+            buffer.remove_line(30);
+            refresh(&mut cache, &mut buffer, grammar.as_mut(), 99);
+            assert_eq!(spans(&cache), fresh(&buffer, grammar.is_some()));
+        }
+    }
+
+    #[test]
+    fn far_down() {
+        // A comment well past where the first search gave up is still found
+        let mut text = "x\n".repeat(REACH * 2);
+        text.push_str("/* a\ncomment */");
+        let mut buffer = Buffer::new(&text, 4);
+        let mut cache = Cache::default();
+        refresh(&mut cache, &mut buffer, None, usize::MAX);
+        let tokens = cache.tokens(REACH * 2 + 1).unwrap();
+        assert_eq!(tokens.get(&0).map(|t| t.span), Some((0, 10)));
+    }
+}
@@ -0,0 +1,94 @@
+// Journal.rs - For keeping unsaved edits on disk so that they survive a crash
+use crate::disk;
+use crate::util::absolute;
+use directories::BaseDirs;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io::{self, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+fn folder() -> Option<PathBuf> {
+    // Find the folder to keep journals in, within the XDG state folder
+    let state = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| Some(BaseDirs::new()?.home_dir().join(".local/state")))?;
+    Some(state.join("ox/journals"))
+}
+
+fn location(path: &str) -> Option<PathBuf> {
+    // Find the journal for a file, named after its full path with slashes swapped for %
+    if path.is_empty() {
+        return None;
+    }
+    let name = absolute(Path::new(path))
+        .to_string_lossy()
+        .replace('/', "%");
+    Some(folder()?.join(format!("{name}.journal")))
+}
+
+pub fn write(path: &str, text: &str) -> io::Result<()> {
+    // Write the edits to a file into its journal, marked with the session that wrote them
+    let location = location(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    if let Some(folder) = location.parent() {
+        // Journals hold the contents of files, so only let their owner read them
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(folder)?;
+    }
+    disk::write(&location.to_string_lossy(), false, |file| {
+        writeln!(file, "{}", process::id())?;
+        file.write_all(text.as_bytes())
+    })
+}
+
+pub fn remove(path: &str) {
+    // Delete the journal for a file, as its edits were saved or thrown away
+    if let Some(location) = location(path) {
+        let _ = fs::remove_file(location);
+    }
+}
+
+pub fn orphan(path: &str) -> Option<String> {
+    // Find the edits left in the journal for a file by a session that has ended
+    let contents = fs::read_to_string(location(path)?).ok()?;
+    let (pid, text) = contents.split_once('\n')?;
+    let pid: u32 = pid.parse().ok()?;
+    if pid == process::id() || running(pid) {
+        return None;
+    }
+    Some(text.to_string())
+}
+
+fn running(pid: u32) -> bool {
+    // Check if the session that wrote a journal is still going, by asking to signal it
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+    // Signal 0 checks that the process exists without sending it anything
+    let result = unsafe { libc::kill(pid, 0) };
+    // Processes that belong to someone else can't be signalled, but are still running
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn sessions() {
+        // This session is running, and one that has finished isn't
+        assert!(running(process::id()));
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!running(pid));
+        assert!(!running(0));
+    }
+}
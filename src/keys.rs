@@ -0,0 +1,188 @@
+// Keys.rs - For binding keys to commands and reading keyboard macros
+use crate::command::Command;
+use crate::terminal::Input;
+use std::collections::HashMap;
+use termion::event::Key;
+
+// The keybindings to use unless the config overrides them
+pub const DEFAULT_KEYS: &[(&str, &str)] = &[
+    ("ctrl+q", "quit"),
+    ("ctrl+s", "save"),
+    ("ctrl+w", "save_as"),
+    ("ctrl+p", "save_all"),
+    ("ctrl+n", "new"),
+    ("ctrl+o", "open"),
+    ("ctrl+f", "search"),
+    ("ctrl+u", "undo"),
+    ("ctrl+y", "redo"),
+    ("ctrl+r", "replace"),
+    ("ctrl+a", "replace_all"),
+    ("ctrl+c", "copy"),
+    ("ctrl+x", "cut"),
+    ("ctrl+v", "paste"),
+    ("alt+v", "cycle_clipboard"),
+    ("ctrl+d", "tab prev"),
+    ("ctrl+h", "tab next"),
+    ("ctrl+k", "command"),
+    ("alt+\\", "split vertical"),
+    ("alt+-", "split horizontal"),
+    ("alt+o", "pane next"),
+    ("alt+i", "pane prev"),
+    ("alt+.", "resize grow"),
+    ("alt+,", "resize shrink"),
+    ("alt+q", "close_pane"),
+    ("alt+r", "record"),
+    ("alt+p", "play"),
+    ("up", "move up"),
+    ("down", "move down"),
+    ("left", "move left"),
+    ("right", "move right"),
+    ("home", "move home"),
+    ("end", "move end"),
+    ("pageup", "move page_up"),
+    ("pagedown", "move page_down"),
+];
+
+pub fn parse_key(chord: &str) -> Result<Key, String> {
+    // Turn a description of a key, such as ctrl+z, alt+x or f5, into a key
+    if let Some((modifier, rest)) = chord.split_once('+').filter(|(_, r)| !r.is_empty()) {
+        let c = single(rest).ok_or(format!("Unknown key: {chord}"))?;
+        return match modifier.to_lowercase().as_str() {
+            "ctrl" => Ok(Key::Ctrl(c.to_ascii_lowercase())),
+            "alt" => Ok(Key::Alt(c)),
+            _ => Err(format!("Unknown modifier: {modifier}")),
+        };
+    }
+    Ok(match chord.to_lowercase().as_str() {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" => Key::Esc,
+        "tab" => Key::Char('\t'),
+        "backtab" => Key::BackTab,
+        "enter" => Key::Char('\n'),
+        name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) if (1..=12).contains(&n) => Key::F(n),
+            _ => Key::Char(single(chord).ok_or(format!("Unknown key: {chord}"))?),
+        },
+    })
+}
+
+pub fn keymap(bindings: &HashMap<String, String>) -> (HashMap<Key, Command>, Option<String>) {
+    // Lay the config's keybindings over the defaults, keeping the first problem found
+    let mut result = HashMap::new();
+    let mut error = None;
+    let mut overrides: Vec<(&str, &str)> = bindings
+        .iter()
+        .map(|(chord, action)| (chord.as_str(), action.as_str()))
+        .collect();
+    overrides.sort_unstable();
+    for (chord, action) in DEFAULT_KEYS.iter().copied().chain(overrides) {
+        let key = match parse_key(chord) {
+            Ok(key) => key,
+            Err(message) => {
+                error.get_or_insert(message);
+                continue;
+            }
+        };
+        if action == "none" {
+            // Allow default keybindings to be removed
+            result.remove(&key);
+            continue;
+        }
+        match Command::parse(action) {
+            Ok(command) => {
+                result.insert(key, command);
+            }
+            Err(message) => {
+                error.get_or_insert(format!("Invalid action for {chord}: {message}"));
+            }
+        }
+    }
+    (result, error)
+}
+
+pub fn key_name(key: Key) -> Option<String> {
+    // Describe a key in the same way that keybindings are written
+    Some(match key {
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        Key::PageUp => "pageup".to_string(),
+        Key::PageDown => "pagedown".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Delete => "delete".to_string(),
+        Key::Insert => "insert".to_string(),
+        Key::Esc => "esc".to_string(),
+        Key::BackTab => "backtab".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char('\n') => "enter".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl+{c}"),
+        Key::Alt(c) => format!("alt+{c}"),
+        Key::F(n) => format!("f{n}"),
+        _ => return None,
+    })
+}
+
+pub fn parse_input(entry: &str) -> Result<Input, String> {
+    // Turn an entry of a macro into input, such as ctrl+s, shift+left or text:hello
+    if let Some(text) = entry.strip_prefix("text:") {
+        Ok(Input::Paste(text.to_string()))
+    } else if let Some(key) = entry.strip_prefix("shift+") {
+        parse_key(key).map(Input::Shift)
+    } else {
+        parse_key(entry).map(Input::Key)
+    }
+}
+
+pub fn input_name(input: &Input) -> Option<String> {
+    // Describe input in the same way that macros are written
+    match input {
+        Input::Key(key) => key_name(*key),
+        Input::Shift(key) => key_name(*key).map(|name| format!("shift+{name}")),
+        Input::Paste(text) => Some(format!("text:{text}")),
+        Input::Mouse(_) => None,
+    }
+}
+
+pub fn macros(
+    entries: &HashMap<String, Vec<String>>,
+) -> (HashMap<String, Vec<Input>>, Option<String>) {
+    // Read the macros from the config, keeping the first problem found
+    let mut result = HashMap::new();
+    let mut error = None;
+    let mut names: Vec<&String> = entries.keys().collect();
+    names.sort_unstable();
+    for name in names {
+        match entries[name].iter().map(|e| parse_input(e)).collect() {
+            Ok(inputs) => {
+                result.insert(name.clone(), inputs);
+            }
+            Err(message) => {
+                error.get_or_insert(format!("Invalid key in macro {name}: {message}"));
+            }
+        }
+    }
+    (result, error)
+}
+
+fn single(text: &str) -> Option<char> {
+    // Get the character out of a string that is only one character long
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
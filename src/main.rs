@@ -16,15 +16,30 @@
 */
 
 // Bring in the external modules
+mod buffer;
+mod clipboard;
+mod command;
 mod config;
+mod diff;
+mod disk;
 mod document;
 mod editor;
+mod editorconfig;
+mod format;
+mod grammar;
 mod highlight;
+mod journal;
+mod keys;
+mod modal;
+mod pane;
 mod row;
 mod terminal;
+mod textmate;
 mod undo;
 mod util;
+mod watch;
 
+use buffer::Buffer;
 use clap::{App, Arg};
 use directories::BaseDirs;
 use document::Document;
@@ -0,0 +1,593 @@
+// Modal.rs - For vi-like modal editing, turned on in the config
+use crate::terminal::Input;
+use crate::util::raw_to_grapheme;
+use crate::Buffer;
+use termion::event::Key;
+
+// The modes that the editor can be in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Normal, // Keys move around and act on the text
+    Insert, // Keys insert text
+    Visual, // Keys move around and extend the selection
+}
+
+// Ways of moving the cursor around the document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart,     // w
+    WordBack,      // b
+    WordEnd,       // e
+    LineStart,     // 0
+    FirstNonBlank, // ^
+    LineEnd,       // $
+    FileStart,     // gg
+    FileEnd,       // G
+    Lines,         // dd, cc and yy
+    Selection,     // The selection in visual mode
+}
+
+// Things that can be done to the text a motion moves over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+// Places to start inserting text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    Before,    // i
+    After,     // a
+    LineStart, // I
+    LineEnd,   // A
+    Below,     // o
+    Above,     // O
+}
+
+// Actions that a sequence of keys in normal or visual mode asks for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Move(Motion, Option<usize>),
+    Operate(Operator, Motion, Option<usize>),
+    Insert(Place),
+    Paste(Place, Option<usize>),
+    Undo,
+    Redo,
+    Repeat(Option<usize>),
+    Visual,
+    Command,
+    Escape,
+}
+
+// The text that an operator acts on, in grapheme positions
+pub struct Range {
+    pub start: (usize, usize),         // Where the text starts
+    pub end: (usize, usize),           // Where the text ends
+    pub lines: Option<(usize, usize)>, // The first and last line, for whole lines
+}
+
+// The result of feeding a key into the parser
+pub enum Feed {
+    Action(Action), // A full action was typed
+    Pending,        // More keys are needed
+    Invalid,        // The keys didn't make sense and were thrown away
+    Unknown,        // The key isn't used by modal editing
+}
+
+// For holding the state of modal editing
+#[derive(Debug)]
+pub struct Modal {
+    pub mode: Mode,                                // The current mode
+    count: Option<usize>,                          // The count typed so far
+    operator: Option<(Operator, Option<usize>)>,   // An operator waiting for a motion
+    g: bool,                                       // True after g was typed
+    pub change: Option<Action>,                    // The change being typed in insert mode
+    pub inserted: Vec<Input>,                      // The input typed in insert mode
+    pub history: usize,                            // Undo history length before the change
+    pub last_change: Option<(Action, Vec<Input>)>, // The change to repeat with .
+}
+
+impl Modal {
+    pub fn new() -> Self {
+        // Start out in normal mode
+        Self {
+            mode: Mode::Normal,
+            count: None,
+            operator: None,
+            g: false,
+            change: None,
+            inserted: vec![],
+            history: 0,
+            last_change: None,
+        }
+    }
+    pub fn feed(&mut self, key: Key) -> Feed {
+        // Work out what a key means, given the keys typed before it
+        if let Key::Char(c @ '0'..='9') = key {
+            if c != '0' || self.count.is_some() {
+                let digit = c.to_digit(10).unwrap_or_default() as usize;
+                self.count = Some(self.count.unwrap_or(0).saturating_mul(10) + digit);
+                return Feed::Pending;
+            }
+        }
+        if std::mem::take(&mut self.g) {
+            return match key {
+                Key::Char('g') => self.motion(Motion::FileStart),
+                _ => self.invalid(),
+            };
+        }
+        let motion = match key {
+            Key::Char('h') | Key::Left | Key::Backspace => Motion::Left,
+            Key::Char('l' | ' ') | Key::Right => Motion::Right,
+            Key::Char('j') | Key::Down => Motion::Down,
+            Key::Char('k') | Key::Up => Motion::Up,
+            Key::Char('w') => Motion::WordStart,
+            Key::Char('b') => Motion::WordBack,
+            Key::Char('e') => Motion::WordEnd,
+            Key::Char('0') | Key::Home => Motion::LineStart,
+            Key::Char('^') => Motion::FirstNonBlank,
+            Key::Char('$') | Key::End => Motion::LineEnd,
+            Key::Char('G') => Motion::FileEnd,
+            Key::Char('g') => {
+                self.g = true;
+                return Feed::Pending;
+            }
+            _ => return self.other(key),
+        };
+        self.motion(motion)
+    }
+    fn motion(&mut self, motion: Motion) -> Feed {
+        // Finish a motion, applying any operator waiting for it
+        let count = self.count.take();
+        match self.operator.take() {
+            Some((operator, first)) => {
+                Feed::Action(Action::Operate(operator, motion, combine(first, count)))
+            }
+            None => Feed::Action(Action::Move(motion, count)),
+        }
+    }
+    fn other(&mut self, key: Key) -> Feed {
+        // Work out keys that aren't motions
+        let count = self.count.take();
+        let operator = match key {
+            Key::Char('d') => Some(Operator::Delete),
+            Key::Char('c') => Some(Operator::Change),
+            Key::Char('y') => Some(Operator::Yank),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            if self.mode == Mode::Visual {
+                return Feed::Action(Action::Operate(operator, Motion::Selection, None));
+            }
+            return match self.operator.take() {
+                // Doubled operators act on whole lines
+                Some((first, before)) if first == operator => Feed::Action(Action::Operate(
+                    operator,
+                    Motion::Lines,
+                    combine(before, count),
+                )),
+                Some(_) => self.invalid(),
+                None => {
+                    self.operator = Some((operator, count));
+                    Feed::Pending
+                }
+            };
+        }
+        if self.operator.take().is_some() {
+            return self.invalid();
+        }
+        let visual = self.mode == Mode::Visual;
+        Feed::Action(match key {
+            Key::Char('x') | Key::Delete if visual => {
+                Action::Operate(Operator::Delete, Motion::Selection, None)
+            }
+            Key::Char('x') | Key::Delete => Action::Operate(Operator::Delete, Motion::Right, count),
+            Key::Char('X') => Action::Operate(Operator::Delete, Motion::Left, count),
+            Key::Char('D') => Action::Operate(Operator::Delete, Motion::LineEnd, count),
+            Key::Char('C') => Action::Operate(Operator::Change, Motion::LineEnd, count),
+            Key::Char('Y') => Action::Operate(Operator::Yank, Motion::Lines, count),
+            Key::Char('i') => Action::Insert(Place::Before),
+            Key::Char('a') => Action::Insert(Place::After),
+            Key::Char('I') => Action::Insert(Place::LineStart),
+            Key::Char('A') => Action::Insert(Place::LineEnd),
+            Key::Char('o') => Action::Insert(Place::Below),
+            Key::Char('O') => Action::Insert(Place::Above),
+            Key::Char('p') => Action::Paste(Place::After, count),
+            Key::Char('P') => Action::Paste(Place::Before, count),
+            Key::Char('u') => Action::Undo,
+            Key::Ctrl('r') => Action::Redo,
+            Key::Char('.') => Action::Repeat(count),
+            Key::Char('v') => Action::Visual,
+            Key::Char(':') => Action::Command,
+            Key::Esc => Action::Escape,
+            Key::Char(_) => return Feed::Invalid,
+            _ => return Feed::Unknown,
+        })
+    }
+    fn invalid(&mut self) -> Feed {
+        // Throw away the keys typed so far
+        self.count = None;
+        self.operator = None;
+        self.g = false;
+        Feed::Invalid
+    }
+}
+
+impl Motion {
+    pub fn linewise(self) -> bool {
+        // Check if the motion acts on whole lines
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::FileStart | Self::FileEnd | Self::Lines
+        )
+    }
+    pub fn inclusive(self) -> bool {
+        // Check if the motion includes the character it lands on
+        matches!(self, Self::WordEnd | Self::Selection)
+    }
+}
+
+pub fn target(
+    buffer: &Buffer,
+    from: (usize, usize),
+    motion: Motion,
+    count: Option<usize>,
+) -> (usize, usize) {
+    // Find where a motion moves to, using grapheme positions
+    let (x, y) = from;
+    let repeat = count.unwrap_or(1);
+    let last = buffer.len_lines().saturating_sub(1);
+    match motion {
+        Motion::Left => (x.saturating_sub(repeat), y),
+        Motion::Right => (std::cmp::min(x + repeat, len(buffer, y)), y),
+        Motion::Up => column(buffer, from, y.saturating_sub(repeat)),
+        Motion::Down => column(buffer, from, std::cmp::min(y + repeat, last)),
+        Motion::Lines => (0, std::cmp::min(y + repeat - 1, last)),
+        Motion::LineStart => (0, y),
+        Motion::FirstNonBlank => (first_non_blank(buffer, y), y),
+        Motion::LineEnd => {
+            let y = std::cmp::min(y + repeat - 1, last);
+            (len(buffer, y), y)
+        }
+        Motion::FileStart => (0, count.map_or(0, |n| n.saturating_sub(1)).min(last)),
+        Motion::FileEnd => (0, count.map_or(last, |n| n.saturating_sub(1)).min(last)),
+        Motion::WordStart => (0..repeat).fold(from, |pos, _| word_start(buffer, pos)),
+        Motion::WordBack => (0..repeat).fold(from, |pos, _| word_back(buffer, pos)),
+        Motion::WordEnd => (0..repeat).fold(from, |pos, _| word_end(buffer, pos)),
+        Motion::Selection => from,
+    }
+}
+
+pub fn range(
+    buffer: &Buffer,
+    from: (usize, usize),
+    operator: Operator,
+    motion: Motion,
+    count: Option<usize>,
+) -> Range {
+    // Find the text between the cursor and where a motion moves to
+    let motion = if operator == Operator::Change
+        && motion == Motion::WordStart
+        && class_at(buffer, from) != 0
+    {
+        // Changing a word leaves the whitespace after it, like vim
+        Motion::WordEnd
+    } else {
+        motion
+    };
+    let to = target(buffer, from, motion, count);
+    let (start, mut end) = if (to.1, to.0) < (from.1, from.0) {
+        (to, from)
+    } else {
+        (from, to)
+    };
+    if motion.linewise() {
+        // Take whole lines, along with a newline so they are removed entirely
+        let (top, bottom) = (start.1, end.1);
+        let (start, end) = if operator == Operator::Change {
+            ((0, top), (len(buffer, bottom), bottom))
+        } else if bottom + 1 < buffer.len_lines() {
+            ((0, top), (0, bottom + 1))
+        } else if top > 0 {
+            (
+                (len(buffer, top - 1), top - 1),
+                (len(buffer, bottom), bottom),
+            )
+        } else {
+            ((0, top), (len(buffer, bottom), bottom))
+        };
+        return Range {
+            start,
+            end,
+            lines: Some((top, bottom)),
+        };
+    }
+    if motion.inclusive() {
+        end.0 = std::cmp::min(end.0 + 1, len(buffer, end.1));
+    } else if motion == Motion::WordStart && end.1 > start.1 {
+        // Moving over words stops at the end of the line
+        end = (len(buffer, start.1), start.1);
+    }
+    Range {
+        start,
+        end,
+        lines: None,
+    }
+}
+
+pub fn width(buffer: &Buffer, (x, y): (usize, usize)) -> usize {
+    // Find the display column of a grapheme position
+    let line = buffer.line(y);
+    line.get_jumps().iter().take(x).sum()
+}
+
+pub fn len(buffer: &Buffer, y: usize) -> usize {
+    // Find the amount of graphemes on a line
+    buffer.line(y).chars().len()
+}
+
+fn combine(first: Option<usize>, second: Option<usize>) -> Option<usize> {
+    // Multiply the counts typed before an operator and before its motion
+    match (first, second) {
+        (None, None) => None,
+        _ => Some(first.unwrap_or(1) * second.unwrap_or(1)),
+    }
+}
+
+fn column(buffer: &Buffer, from: (usize, usize), y: usize) -> (usize, usize) {
+    // Move to another line, staying in the same display column where possible
+    let x = width(buffer, from);
+    let line = buffer.line(y);
+    (raw_to_grapheme(std::cmp::min(x, line.length()), &line), y)
+}
+
+pub fn first_non_blank(buffer: &Buffer, y: usize) -> usize {
+    // Find the first grapheme on a line that isn't whitespace
+    let line = buffer.line(y);
+    let chars = line.chars();
+    chars
+        .iter()
+        .position(|c| class(c) != 0)
+        .unwrap_or(chars.len())
+}
+
+fn class(grapheme: &str) -> u8 {
+    // Sort characters into whitespace, word characters and punctuation
+    match grapheme.chars().next() {
+        Some(c) if c.is_whitespace() => 0,
+        Some(c) if c.is_alphanumeric() || c == '_' => 1,
+        Some(_) => 2,
+        None => 0,
+    }
+}
+
+fn class_at(buffer: &Buffer, (x, y): (usize, usize)) -> u8 {
+    // Find the class of the grapheme at a position, where the end of a line is whitespace
+    buffer.line(y).chars().get(x).map_or(0, |c| class(c))
+}
+
+fn forward(buffer: &Buffer, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    // Step forward one grapheme, moving onto the next line after the end of a line
+    if x < len(buffer, y) {
+        Some((x + 1, y))
+    } else if y + 1 < buffer.len_lines() {
+        Some((0, y + 1))
+    } else {
+        None
+    }
+}
+
+fn backward(buffer: &Buffer, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    // Step back one grapheme, moving onto the end of the previous line
+    if x > 0 {
+        Some((x - 1, y))
+    } else if y > 0 {
+        Some((len(buffer, y - 1), y - 1))
+    } else {
+        None
+    }
+}
+
+fn word_start(buffer: &Buffer, from: (usize, usize)) -> (usize, usize) {
+    // Move to the start of the next word, stopping at empty lines
+    let mut pos = from;
+    let start = class_at(buffer, pos);
+    while start != 0 && class_at(buffer, pos) == start {
+        match forward(buffer, pos) {
+            Some(next) => pos = next,
+            None => return pos,
+        }
+    }
+    while class_at(buffer, pos) == 0 {
+        if pos != from && pos.0 == 0 && len(buffer, pos.1) == 0 {
+            break;
+        }
+        match forward(buffer, pos) {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+    pos
+}
+
+fn word_end(buffer: &Buffer, from: (usize, usize)) -> (usize, usize) {
+    // Move to the end of the current or next word
+    let Some(mut pos) = forward(buffer, from) else {
+        return from;
+    };
+    while class_at(buffer, pos) == 0 {
+        match forward(buffer, pos) {
+            Some(next) => pos = next,
+            None => return pos,
+        }
+    }
+    let word = class_at(buffer, pos);
+    while let Some(next) = forward(buffer, pos) {
+        if next.1 != pos.1 || class_at(buffer, next) != word {
+            break;
+        }
+        pos = next;
+    }
+    pos
+}
+
+fn word_back(buffer: &Buffer, from: (usize, usize)) -> (usize, usize) {
+    // Move to the start of the current or previous word, stopping at empty lines
+    let Some(mut pos) = backward(buffer, from) else {
+        return from;
+    };
+    while class_at(buffer, pos) == 0 && len(buffer, pos.1) != 0 {
+        match backward(buffer, pos) {
+            Some(prev) => pos = prev,
+            None => return pos,
+        }
+    }
+    let word = class_at(buffer, pos);
+    while let Some(prev) = backward(buffer, pos) {
+        if prev.1 != pos.1 || class_at(buffer, prev) != word || word == 0 {
+            break;
+        }
+        pos = prev;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(modal: &mut Modal, keys: &str) -> Option<Action> {
+        // Type some keys, getting the action the last of them finished
+        let mut result = None;
+        for c in keys.chars() {
+            result = match modal.feed(Key::Char(c)) {
+                Feed::Action(action) => Some(action),
+                _ => None,
+            };
+        }
+        result
+    }
+
+    fn span(
+        buffer: &Buffer,
+        from: (usize, usize),
+        operator: Operator,
+        motion: Motion,
+    ) -> Vec<usize> {
+        // Get where an operator's text starts and ends, along with its lines if it has them
+        let range = range(buffer, from, operator, motion, None);
+        let (top, bottom) = range.lines.unwrap_or((0, 0));
+        vec![
+            range.start.0,
+            range.start.1,
+            range.end.0,
+            range.end.1,
+            top,
+            bottom,
+        ]
+    }
+
+    #[test]
+    fn feed() {
+        let mut modal = Modal::new();
+        let delete = |motion, count| Some(Action::Operate(Operator::Delete, motion, count));
+        assert_eq!(keys(&mut modal, "2d3w"), delete(Motion::WordStart, Some(6)));
+        assert_eq!(keys(&mut modal, "dd"), delete(Motion::Lines, None));
+        assert_eq!(keys(&mut modal, "d2d"), delete(Motion::Lines, Some(2)));
+        assert_eq!(
+            keys(&mut modal, "10j"),
+            Some(Action::Move(Motion::Down, Some(10)))
+        );
+        assert_eq!(
+            keys(&mut modal, "0"),
+            Some(Action::Move(Motion::LineStart, None))
+        );
+        assert_eq!(
+            keys(&mut modal, "gg"),
+            Some(Action::Move(Motion::FileStart, None))
+        );
+        assert!(matches!(modal.feed(Key::Char('d')), Feed::Pending));
+        assert!(matches!(modal.feed(Key::Char('y')), Feed::Invalid));
+        // Nothing is left over from the keys that were thrown away
+        assert_eq!(keys(&mut modal, "x"), delete(Motion::Right, None));
+        modal.mode = Mode::Visual;
+        assert_eq!(keys(&mut modal, "d"), delete(Motion::Selection, None));
+    }
+
+    #[test]
+    fn words() {
+        let buffer = Buffer::new("one two.three\n\n  four", 4);
+        let to = |from, motion| target(&buffer, from, motion, None);
+        assert_eq!(to((0, 0), Motion::WordStart), (4, 0));
+        assert_eq!(to((4, 0), Motion::WordStart), (7, 0));
+        assert_eq!(to((8, 0), Motion::WordStart), (0, 1));
+        assert_eq!(to((0, 1), Motion::WordStart), (2, 2));
+        assert_eq!(to((0, 0), Motion::WordEnd), (2, 0));
+        assert_eq!(to((2, 0), Motion::WordEnd), (6, 0));
+        assert_eq!(to((2, 2), Motion::WordBack), (0, 1));
+        assert_eq!(to((0, 1), Motion::WordBack), (8, 0));
+        assert_eq!(target(&buffer, (0, 0), Motion::WordStart, Some(3)), (8, 0));
+        assert_eq!(target(&buffer, (0, 0), Motion::Down, Some(5)), (0, 2));
+    }
+
+    #[test]
+    fn counts() {
+        // 2d3w deletes six words
+        let buffer = Buffer::new("a b c d e f g h", 4);
+        let range = range(
+            &buffer,
+            (0, 0),
+            Operator::Delete,
+            Motion::WordStart,
+            Some(6),
+        );
+        assert_eq!((range.start, range.end), ((0, 0), (12, 0)));
+    }
+
+    #[test]
+    fn change_word() {
+        // cw leaves the whitespace after the word, just like ce
+        let buffer = Buffer::new("one two", 4);
+        let cw = span(&buffer, (0, 0), Operator::Change, Motion::WordStart);
+        assert_eq!(cw, span(&buffer, (0, 0), Operator::Change, Motion::WordEnd));
+        assert_eq!(cw, vec![0, 0, 3, 0, 0, 0]);
+        // dw takes the whitespace too
+        let dw = span(&buffer, (0, 0), Operator::Delete, Motion::WordStart);
+        assert_eq!(dw, vec![0, 0, 4, 0, 0, 0]);
+        // cw on whitespace only changes the whitespace
+        let buffer = Buffer::new("one  two", 4);
+        let cw = span(&buffer, (3, 0), Operator::Change, Motion::WordStart);
+        assert_eq!(cw, vec![3, 0, 5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn delete_lines() {
+        let buffer = Buffer::new("one\ntwo\nthree", 4);
+        let dd = |y| span(&buffer, (1, y), Operator::Delete, Motion::Lines);
+        // A line takes the newline after it
+        assert_eq!(dd(1), vec![0, 1, 0, 2, 1, 1]);
+        // The last line takes the newline before it instead
+        assert_eq!(dd(2), vec![3, 1, 5, 2, 2, 2]);
+        // cc keeps the line itself
+        let cc = span(&buffer, (1, 2), Operator::Change, Motion::Lines);
+        assert_eq!(cc, vec![0, 2, 5, 2, 2, 2]);
+        // The only line is emptied
+        let buffer = Buffer::new("one", 4);
+        let dd = span(&buffer, (1, 0), Operator::Delete, Motion::Lines);
+        assert_eq!(dd, vec![0, 0, 3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn end_of_line() {
+        // dw on the last word of a line stops at the end of the line
+        let buffer = Buffer::new("one\ntwo", 4);
+        let dw = span(&buffer, (0, 0), Operator::Delete, Motion::WordStart);
+        assert_eq!(dw, vec![0, 0, 3, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,201 @@
+// Pane.rs - For splitting the screen into panes that each show a document
+use crate::Position;
+use std::{cmp, mem};
+
+// How far resizing moves the border between two panes, in percent
+const RESIZE_STEP: usize = 5;
+// The smallest and largest share of a split that a pane can take up
+const MIN_SHARE: usize = 10;
+const MAX_SHARE: usize = 90;
+
+// The part of a document that a pane is looking at
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    pub cursor: Position,       // Where the cursor is on the pane
+    pub offset: Position,       // How far the pane is scrolled
+    pub graphemes: usize,       // The grapheme cursor
+    pub mark: Option<Position>, // The anchor of the selection
+}
+
+// A pane, showing one of the open documents
+pub struct Pane {
+    pub doc: usize, // The document being shown
+    pub view: View, // Where the pane was looking when it last lost focus
+}
+
+// Ways of dividing the screen between two panes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Split {
+    Horizontal, // One above the other
+    Vertical,   // Side by side
+}
+
+// An area of the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// How the panes are arranged on the screen
+#[derive(Debug)]
+pub enum Layout {
+    Pane(usize),                                   // A single pane
+    Split(Split, usize, Box<Layout>, Box<Layout>), // Two layouts, with the share the first gets
+}
+
+// Where each pane and each divider between them goes on the screen
+type Arrangement = (Vec<(usize, Rect)>, Vec<(Split, Rect)>);
+
+impl Rect {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        // Check if a point on the screen is within this area
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+    fn divide(self, split: Split, share: usize) -> (Self, Self, Self) {
+        // Cut the area in two, leaving a line between them for a divider
+        match split {
+            Split::Vertical => {
+                let left = self.width.saturating_sub(1) * share / 100;
+                let right = self.width.saturating_sub(left + 1);
+                (
+                    Self {
+                        width: left,
+                        ..self
+                    },
+                    Self {
+                        x: self.x + left,
+                        width: cmp::min(1, self.width),
+                        ..self
+                    },
+                    Self {
+                        x: self.x + left + 1,
+                        width: right,
+                        ..self
+                    },
+                )
+            }
+            Split::Horizontal => {
+                let top = self.height.saturating_sub(1) * share / 100;
+                let bottom = self.height.saturating_sub(top + 1);
+                (
+                    Self {
+                        height: top,
+                        ..self
+                    },
+                    Self {
+                        y: self.y + top,
+                        height: cmp::min(1, self.height),
+                        ..self
+                    },
+                    Self {
+                        y: self.y + top + 1,
+                        height: bottom,
+                        ..self
+                    },
+                )
+            }
+        }
+    }
+}
+
+impl Layout {
+    pub fn arrange(&self, area: Rect) -> Arrangement {
+        // Work out where each pane and divider goes, with panes in reading order
+        let mut panes = vec![];
+        let mut dividers = vec![];
+        self.place(area, &mut panes, &mut dividers);
+        (panes, dividers)
+    }
+    fn place(&self, area: Rect, panes: &mut Vec<(usize, Rect)>, dividers: &mut Vec<(Split, Rect)>) {
+        // Place this layout within an area of the screen
+        match self {
+            Self::Pane(index) => panes.push((*index, area)),
+            Self::Split(split, share, first, second) => {
+                let (a, divider, b) = area.divide(*split, *share);
+                first.place(a, panes, dividers);
+                dividers.push((*split, divider));
+                second.place(b, panes, dividers);
+            }
+        }
+    }
+    pub fn contains(&self, target: usize) -> bool {
+        // Check if a pane is somewhere within this layout
+        match self {
+            Self::Pane(index) => *index == target,
+            Self::Split(_, _, first, second) => first.contains(target) || second.contains(target),
+        }
+    }
+    pub fn split(&mut self, target: usize, new: usize, split: Split) {
+        // Divide a pane in two, putting a new pane after it
+        match self {
+            Self::Pane(index) if *index == target => {
+                *self = Self::Split(
+                    split,
+                    50,
+                    Box::new(Self::Pane(target)),
+                    Box::new(Self::Pane(new)),
+                );
+            }
+            Self::Pane(_) => (),
+            Self::Split(_, _, first, second) => {
+                first.split(target, new, split);
+                second.split(target, new, split);
+            }
+        }
+    }
+    pub fn remove(&mut self, target: usize) {
+        // Take a pane away, giving its space to whatever was beside it
+        self.take_out(target);
+        self.renumber(target);
+    }
+    fn take_out(&mut self, target: usize) {
+        // Replace the split holding a pane with the other side of the split
+        if let Self::Split(_, _, first, second) = self {
+            let keep = if matches!(**first, Self::Pane(index) if index == target) {
+                mem::replace(&mut **second, Self::Pane(0))
+            } else if matches!(**second, Self::Pane(index) if index == target) {
+                mem::replace(&mut **first, Self::Pane(0))
+            } else {
+                first.take_out(target);
+                second.take_out(target);
+                return;
+            };
+            *self = keep;
+        }
+    }
+    fn renumber(&mut self, removed: usize) {
+        // Close the gap in the pane numbers left by a removed pane
+        match self {
+            Self::Pane(index) if *index > removed => *index -= 1,
+            Self::Pane(_) => (),
+            Self::Split(_, _, first, second) => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+    pub fn resize(&mut self, target: usize, grow: bool) -> bool {
+        // Move the nearest border of a pane, returning false when it has no border
+        match self {
+            Self::Pane(_) => false,
+            Self::Split(_, share, first, second) => {
+                if first.resize(target, grow) || second.resize(target, grow) {
+                    return true;
+                }
+                if !first.contains(target) && !second.contains(target) {
+                    return false;
+                }
+                // Growing the first layout and shrinking the second both move the border forward
+                *share = if grow == first.contains(target) {
+                    cmp::min(*share + RESIZE_STEP, MAX_SHARE)
+                } else {
+                    cmp::max(share.saturating_sub(RESIZE_STEP), MIN_SHARE)
+                };
+                true
+            }
+        }
+    }
+}
@@ -1,79 +1,92 @@
 // Row.rs - Handling the rows of a document and their appearance
-use crate::config::{Reader, TokenType};
+use crate::config::{Reader, Theme};
 use crate::editor::RESET_FG;
-use crate::highlight::{highlight, remove_nested_tokens, Token};
-use crate::util::Exp;
+use crate::highlight::Token;
+use crate::util::{column_after, grapheme_width};
+use ropey::RopeSlice;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use termion::color;
+use termion::style;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
-// Ensure we can use the Clone trait to copy row structs for manipulation
+// A view of a single line, borrowed from a buffer where possible
 #[derive(Debug, Clone)]
-pub struct Row {
-    pub string: String,                // For holding the contents of the row
+pub struct Row<'a> {
+    pub string: Cow<'a, str>,          // For holding the contents of the row
     pub syntax: HashMap<usize, Token>, // Hashmap for syntax
-    regex: Exp,                        // For holding the regex expression
+    pub tab_width: usize,              // For holding the distance between tab stops
 }
 
-// Implement a trait (similar method to inheritance) into the row
-impl From<&str> for Row {
-    fn from(s: &str) -> Self {
-        // Initialise a row from a string
+impl<'a> Row<'a> {
+    pub fn new(s: RopeSlice<'a>, tab_width: usize) -> Self {
+        // Initialise a row from a line of a rope, only copying if it is fragmented
         Self {
-            string: s.to_string(),
+            string: s.into(),
             syntax: HashMap::new(),
-            regex: Exp::new(),
+            tab_width,
         }
     }
 }
 
+// How the line numbers at the start of each row are laid out
+#[derive(Debug, Clone, Copy)]
+pub struct Gutter {
+    pub width: usize, // The room taken up by the line numbers and their padding
+    pub left: usize,  // The padding on the left of the line numbers
+    pub right: usize, // The padding on the right of the line numbers
+}
+
 // Add methods to the Row struct / class
-impl Row {
+impl Row<'_> {
     pub fn render(
         &self,
         mut start: usize,
         width: usize,
         index: usize,
-        offset: usize,
-        config: &Reader,
+        gutter: Gutter,
+        theme: &Theme,
+        selection: Option<(usize, usize)>,
     ) -> String {
         // Render the row by trimming it to the correct size
         let index = index.saturating_add(1);
         // Padding to align line numbers to the right
-        let post_padding = offset.saturating_sub(
-            index.to_string().len() +         // Length of the number
-            config.general.line_number_padding_right + // Length of the right padding
-            config.general.line_number_padding_left, // Length of the left padding
+        let post_padding = gutter.width.saturating_sub(
+            index.to_string().len() + // Length of the number
+            gutter.right +            // Length of the right padding
+            gutter.left, // Length of the left padding
         );
         // Assemble the line number data
         let line_number = format!(
             "{}{}{}{}{}{}",
-            Reader::rgb_fg(config.theme.line_number_fg),
-            " ".repeat(config.general.line_number_padding_left),
+            Reader::rgb_fg(theme.line_number_fg),
+            " ".repeat(gutter.left),
             " ".repeat(post_padding),
             index,
-            " ".repeat(config.general.line_number_padding_right),
+            " ".repeat(gutter.right),
             RESET_FG,
         );
-        // Strip ANSI values from the line
-        let line_number_len = self.regex.ansi_len(&line_number);
+        // Work out the width of the line number without ANSI values
+        let line_number_len = gutter.left + post_padding + index.to_string().len() + gutter.right;
         let width = width.saturating_sub(line_number_len);
-        let mut initial = start;
+        let initial = start;
         let mut result = String::new();
+        let in_selection = |x: usize| selection.is_some_and(|(s, e)| x >= s && x < e);
+        let mut selected = false;
         // Ensure that the render isn't impossible
-        if width != 0 && start < UnicodeWidthStr::width(&self.string[..]) {
+        if width != 0 && start < self.length() {
             // Calculate the character positions
             let end = width + start;
             let mut dna = HashMap::new();
             let mut cumulative = 0;
             // Collect the DNA from the unicode characters
             for ch in self.string.graphemes(true) {
-                dna.insert(cumulative, ch);
-                cumulative += UnicodeWidthStr::width(ch);
+                let columns = grapheme_width(ch, cumulative, self.tab_width);
+                dna.insert(cumulative, (ch, columns));
+                cumulative += columns;
             }
-            // Repair dodgy start
-            if !dna.contains_key(&start) {
+            // Repair dodgy start, such as half of a wide character or part of a tab
+            while start < end && !dna.contains_key(&start) {
                 result.push(' ');
                 start += 1;
             }
@@ -81,98 +94,114 @@ impl Row {
             'a: while start < end {
                 if let Some(t) = self.syntax.get(&start) {
                     // There is a token here
-                    result.push_str(&t.kind);
+                    result.push_str(&t.kind.start());
                     while start < end && start < t.span.1 {
-                        if let Some(ch) = dna.get(&start) {
+                        if let Some(&(ch, columns)) = dna.get(&start) {
                             // The character overlaps with the edge
-                            if start + UnicodeWidthStr::width(*ch) > end {
+                            if start + columns > end {
                                 result.push(' ');
                                 break 'a;
                             }
-                            result.push_str(ch);
-                            start += UnicodeWidthStr::width(*ch);
+                            mark_selection(&mut result, &mut selected, in_selection(start));
+                            push_grapheme(&mut result, ch, columns);
+                            start += columns;
                         } else {
                             break 'a;
                         }
                     }
-                    result.push_str(&color::Fg(color::Reset).to_string());
-                } else if let Some(ch) = dna.get(&start) {
+                    result.push_str(&t.kind.end(theme));
+                } else if let Some(&(ch, columns)) = dna.get(&start) {
                     // There is a character here
-                    if start + UnicodeWidthStr::width(*ch) > end {
+                    if start + columns > end {
                         result.push(' ');
                         break 'a;
                     }
-                    result.push_str(ch);
-                    start += UnicodeWidthStr::width(*ch);
+                    mark_selection(&mut result, &mut selected, in_selection(start));
+                    push_grapheme(&mut result, ch, columns);
+                    start += columns;
                 } else {
                     // The quota has been used up
                     break 'a;
                 }
             }
+            // Show the newline as selected when the selection carries onto the next line
+            if start < end && start >= self.length() && in_selection(self.length()) {
+                mark_selection(&mut result, &mut selected, true);
+                result.push(' ');
+            }
+            mark_selection(&mut result, &mut selected, false);
             // Correct colourization of tokens that are half off the screen and half on the screen
-            let initial_initial = initial; // Terrible variable naming, I know
-            if initial > 0 {
-                // Calculate the last token start boundary
-                while self.syntax.get(&initial).is_none() && initial > 0 {
-                    initial -= 1;
-                }
-                // Verify that the token actually exists
-                if let Some(t) = self.syntax.get(&initial) {
-                    // Verify that the token isn't up against the far left side
-                    if t.span.0 != initial_initial && t.span.1 >= initial_initial {
-                        // Insert the correct colours
-                        let mut real = 0;
-                        let mut ch = 0;
-                        for i in result.graphemes(true) {
-                            if ch == t.span.1 - initial_initial {
-                                break;
-                            }
-                            real += i.len();
+            self.colour_partial_token(&mut result, initial, theme);
+        } else if width != 0 && start == self.length() && in_selection(start) {
+            // Show the newline of an empty or scrolled past line as selected
+            result = format!("{} {}", style::Invert, style::NoInvert);
+        }
+        // Return the full line string to be rendered
+        line_number + &result
+    }
+    fn colour_partial_token(&self, result: &mut String, mut initial: usize, theme: &Theme) {
+        // Colour a token that starts off the left of the screen
+        let initial_initial = initial; // Terrible variable naming, I know
+        if initial > 0 {
+            // Calculate the last token start boundary
+            while !self.syntax.contains_key(&initial) && initial > 0 {
+                initial -= 1;
+            }
+            // Verify that the token actually exists
+            if let Some(t) = self.syntax.get(&initial) {
+                // Verify that the token isn't up against the far left side
+                if t.span.0 != initial_initial && t.span.1 >= initial_initial {
+                    // Insert the correct colours
+                    let mut real = 0;
+                    let mut ch = 0;
+                    let mut escape = false;
+                    for i in result.graphemes(true) {
+                        if ch == t.span.1 - initial_initial {
+                            break;
+                        }
+                        real += i.len();
+                        // Skip over the selection highlighting codes
+                        if i == "\u{1b}" {
+                            escape = true;
+                        } else if escape {
+                            escape = i != "m";
+                        } else {
                             ch += UnicodeWidthStr::width(i);
                         }
-                        result.insert_str(real, &RESET_FG.to_string());
-                        result.insert_str(0, &t.kind);
                     }
+                    result.insert_str(real, &t.kind.end(theme));
+                    result.insert_str(0, &t.kind.start());
                 }
             }
         }
-        // Return the full line string to be rendered
-        line_number + &result
-    }
-    pub fn update_syntax(
-        &mut self,
-        config: &Reader,
-        syntax: &[TokenType],
-        doc: &str,
-        index: usize,
-    ) {
-        // Update the syntax highlighting indices for this row
-        self.syntax = remove_nested_tokens(
-            &highlight(&self.string, &doc, index, &syntax, &config.highlights),
-            &self.string,
-        );
     }
     pub fn length(&self) -> usize {
         // Get the current length of the row
-        UnicodeWidthStr::width(&self.string[..])
+        column_after(&self.string, 0, self.tab_width)
     }
     pub fn chars(&self) -> Vec<&str> {
         // Get the characters of the line
         self.string.graphemes(true).collect()
     }
-    pub fn ext_chars(&self) -> Vec<&str> {
-        // Produce a special list of characters depending on the widths of characters
-        let mut result = Vec::new();
-        for i in self.chars() {
-            result.resize(result.len() + UnicodeWidthStr::width(i), i);
+    pub fn width_at(&self, x: usize) -> Option<usize> {
+        // Get the width of the character that covers a column
+        let mut column = 0;
+        for width in self.get_jumps() {
+            if x < column + width {
+                return Some(width);
+            }
+            column += width;
         }
-        result
+        None
     }
     pub fn get_jumps(&self) -> Vec<usize> {
         // Get the intervals of the unicode widths
         let mut result = Vec::new();
+        let mut column = 0;
         for i in self.chars() {
-            result.push(UnicodeWidthStr::width(i));
+            let width = grapheme_width(i, column, self.tab_width);
+            result.push(width);
+            column += width;
         }
         result
     }
@@ -186,29 +215,25 @@ impl Row {
         }
         result
     }
-    pub fn insert(&mut self, ch: char, pos: usize) {
-        // Insert a character
-        let mut before: String = self.string.graphemes(true).take(pos as usize).collect();
-        let after: String = self.string.graphemes(true).skip(pos as usize).collect();
-        before.push(ch);
-        before.push_str(&after);
-        self.string = before;
+}
+
+fn push_grapheme(result: &mut String, grapheme: &str, columns: usize) {
+    // Add a grapheme to a rendered line, writing tabs out as spaces up to the next tab stop
+    if grapheme == "\t" {
+        result.push_str(&" ".repeat(columns));
+    } else {
+        result.push_str(grapheme);
     }
-    pub fn delete(&mut self, pos: usize) -> Option<char> {
-        // Remove a character
-        let before: String = self.string.graphemes(true).take(pos as usize).collect();
-        let after: String = self.string.graphemes(true).skip(1 + pos as usize).collect();
-        let result: Option<char>;
-        if let Some(c) = self.chars().get(pos) {
-            if let Ok(c) = c.parse() {
-                result = Some(c);
-            } else {
-                result = None;
-            }
+}
+
+fn mark_selection(result: &mut String, selected: &mut bool, inside: bool) {
+    // Toggle the selection highlighting when moving in or out of the selection
+    if inside != *selected {
+        if inside {
+            result.push_str(style::Invert.as_ref());
         } else {
-            result = None;
+            result.push_str(style::NoInvert.as_ref());
         }
-        self.string = before + &after;
-        result
+        *selected = inside;
     }
 }
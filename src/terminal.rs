@@ -1,32 +1,60 @@
 // Terminal.rs - Handling low level terminal operations
+use crate::config::Colours;
 use crate::util::Exp;
 use crate::Position;
+use std::cell::Cell;
+use std::env;
 use std::io::{stdout, Error, Stdout, Write};
+use termion::event::{Event, Key, MouseEvent};
+use termion::input::MouseTerminal;
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::screen::AlternateScreen;
 use unicode_width::UnicodeWidthStr;
 
 // Struct to hold size
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Size {
     pub width: usize,
     pub height: usize,
 }
 
+// Markers that the terminal wraps pasted text in
+pub const PASTE_START: &[u8] = b"\x1b[200~";
+pub const PASTE_END: &[u8] = b"\x1b[201~";
+
+thread_local! {
+    // How many colours the terminal can show, for writing colours anywhere in the editor
+    static COLOURS: Cell<Colours> = const { Cell::new(Colours::TrueColour) };
+}
+
+// Enum for the kinds of input the editor acts on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Key(Key),          // A normal key press
+    Shift(Key),        // A movement key pressed while holding shift
+    Paste(String),     // A block of text pasted into the terminal
+    Mouse(MouseEvent), // A click, drag or scroll of the mouse
+}
+
 // The terminal struct
 pub struct Terminal {
-    screen: AlternateScreen<std::io::Stdout>, // Holds the screen
-    _stdout: RawTerminal<Stdout>,             // Ensures we're in raw mode for total control
-    pub size: Size,                           // For holding the size of the terminal
-    regex: Exp,                               // For holding the regex
+    screen: MouseTerminal<AlternateScreen<Stdout>>, // Holds the screen
+    _stdout: RawTerminal<Stdout>,                   // Ensures we're in raw mode for total control
+    pub size: Size,                                 // For holding the size of the terminal
+    regex: Exp,                                     // For holding the regex
 }
 
 // Implement methods into the terminal struct / class
 impl Terminal {
-    pub fn new() -> Result<Self, Error> {
+    pub fn new(colours: Colours) -> Result<Self, Error> {
         // Create a new terminal and switch into raw mode
+        Self::set_colours(colours);
         let size = termion::terminal_size()?;
+        let mut screen = MouseTerminal::from(AlternateScreen::from(stdout()));
+        // Ask for pasted text to be wrapped in markers
+        write!(screen, "\x1b[?2004h")?;
         Ok(Self {
-            screen: AlternateScreen::from(stdout()),
+            screen,
             _stdout: stdout().into_raw_mode()?,
             size: Size {
                 width: size.0 as usize,
@@ -35,6 +63,18 @@ impl Terminal {
             regex: Exp::new(),
         })
     }
+    pub fn set_colours(colours: Colours) {
+        // Use the colours from the config, working them out if it leaves it to us
+        let colours = match colours {
+            Colours::Auto => detect(),
+            colours => colours,
+        };
+        COLOURS.with(|cell| cell.set(colours));
+    }
+    pub fn colours() -> Colours {
+        // Get how many colours the terminal can show
+        COLOURS.with(Cell::get)
+    }
     pub fn goto(&mut self, p: &Position) {
         // Move the cursor to a position
         write!(
@@ -54,6 +94,11 @@ impl Terminal {
     pub fn show_cursor(&mut self) {
         write!(self.screen, "{}", termion::cursor::Show).unwrap();
     }
+    pub fn copy(&mut self, text: &str) {
+        // Place text on the host clipboard with an OSC 52 escape sequence
+        write!(self.screen, "\x1b]52;c;{}\x07", base64::encode(text)).unwrap();
+        self.flush();
+    }
     pub fn align_break(&self, l: &str, r: &str) -> String {
         // Align two items to the left and right
         let left_length = UnicodeWidthStr::width(l);
@@ -67,6 +112,36 @@ impl Terminal {
         let padding = (self.size.width as usize).saturating_sub(length);
         " ".repeat(padding as usize)
     }
+    pub fn decode(event: Event) -> Option<Input> {
+        // Turn a raw terminal event into input the editor understands
+        match event {
+            Event::Key(key) => Some(Input::Key(key)),
+            Event::Unsupported(bytes) => Self::decode_modified(&bytes),
+            Event::Mouse(event) => Some(Input::Mouse(event)),
+        }
+    }
+    fn decode_modified(bytes: &[u8]) -> Option<Input> {
+        // Decode xterm style modified keys, such as ESC [ 1 ; 2 A for shift + up
+        let sequence = std::str::from_utf8(bytes.strip_prefix(b"\x1b[")?).ok()?;
+        let (last, params) = (sequence.chars().last()?, &sequence[..sequence.len() - 1]);
+        let mut params = params.split(';');
+        let (code, modifier) = (params.next()?, params.next()?);
+        let key = match (code, last) {
+            ("1", 'A') => Key::Up,
+            ("1", 'B') => Key::Down,
+            ("1", 'C') => Key::Right,
+            ("1", 'D') => Key::Left,
+            ("1", 'H') => Key::Home,
+            ("1", 'F') => Key::End,
+            ("5", '~') => Key::PageUp,
+            ("6", '~') => Key::PageDown,
+            _ => return None,
+        };
+        match modifier {
+            "2" => Some(Input::Shift(key)),
+            _ => None,
+        }
+    }
     pub fn check_resize(&mut self) -> bool {
         // Check for and handle resize events
         let size = termion::terminal_size().unwrap();
@@ -81,3 +156,24 @@ impl Terminal {
         }
     }
 }
+
+// Restore the terminal's paste behaviour on exit
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = write!(self.screen, "\x1b[?2004l");
+        self.flush();
+    }
+}
+
+fn detect() -> Colours {
+    // Work out how many colours the terminal can show from what it tells programs
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let term = env::var("TERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+        Colours::TrueColour
+    } else if term.contains("256") {
+        Colours::Ansi256
+    } else {
+        Colours::Ansi16
+    }
+}
@@ -0,0 +1,315 @@
+// TextMate.rs - For highlighting with TextMate and Sublime Text grammars
+use crate::config::{Reader, Style};
+use crate::highlight::Token;
+use crate::util::column_after;
+use plist::Value;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use syntect::easy::ScopeRangeIterator;
+use syntect::parsing::{
+    ParseState, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet, SyntaxSetBuilder,
+};
+
+// How the scopes that grammars give to text map onto the highlights in the config
+const SCOPES: &[(&str, &str)] = &[
+    ("comment", "comments"),
+    ("string", "strings"),
+    ("string.quoted.other.lt-gt.include", "headers"),
+    ("constant.character", "characters"),
+    ("constant.numeric", "digits"),
+    ("constant.language", "booleans"),
+    ("constant.other.symbol", "symbols"),
+    ("keyword", "keywords"),
+    ("storage", "keywords"),
+    ("variable.language", "keywords"),
+    ("entity.name.function", "functions"),
+    ("support.function", "functions"),
+    ("variable.function", "functions"),
+    ("entity.name.type", "structs"),
+    ("entity.name.class", "structs"),
+    ("entity.name.struct", "structs"),
+    ("entity.name.enum", "structs"),
+    ("entity.name.trait", "structs"),
+    ("support.type", "structs"),
+    ("support.class", "structs"),
+    ("entity.name.macro", "macros"),
+    ("support.function.macro", "macros"),
+    ("entity.other.attribute-name", "attributes"),
+    ("meta.attribute", "attributes"),
+    ("markup.heading", "headers"),
+    ("variable.other.readwrite.global", "global"),
+];
+
+// A grammar for a document, along with every other grammar that it could refer to
+pub struct TextMate {
+    set: Rc<SyntaxSet>,      // All the grammars that were loaded
+    syntax: SyntaxReference, // The grammar for the document
+}
+
+impl TextMate {
+    pub fn new(config: &Reader, extension: &str) -> Option<Self> {
+        // Find the grammar for a file extension, if one was loaded
+        let set = config.syntaxes.as_ref()?;
+        let syntax = set.find_syntax_by_extension(extension)?.clone();
+        Some(Self {
+            set: Rc::clone(set),
+            syntax,
+        })
+    }
+    pub fn start(&self) -> (ParseState, ScopeStack) {
+        // Get the parser ready for the first line of a document
+        (ParseState::new(&self.syntax), ScopeStack::new())
+    }
+    pub fn highlight(
+        &self,
+        row: &str,
+        tab_width: usize,
+        parse: &mut (ParseState, ScopeStack),
+        highlights: &HashMap<String, Style>,
+    ) -> HashMap<usize, Token> {
+        // Highlight a line, leaving the parser ready for the next line
+        let (state, stack) = parse;
+        let line = format!("{row}\n");
+        let ops = state.parse_line(&line, &self.set).unwrap_or_default();
+        let mut syntax = HashMap::new();
+        for (range, op) in ScopeRangeIterator::new(&ops, &line) {
+            if stack.apply(op).is_err() {
+                break;
+            }
+            let end = range.end.min(row.len());
+            if range.start >= end {
+                continue;
+            }
+            if let Some(kind) = kind(stack, highlights) {
+                let start = column_after(&row[..range.start], 0, tab_width);
+                let data = &row[range.start..end];
+                syntax.insert(
+                    start,
+                    Token {
+                        span: (start, column_after(data, start, tab_width)),
+                        data: data.to_string(),
+                        kind: highlights[kind],
+                        priority: false,
+                    },
+                );
+            }
+        }
+        syntax
+    }
+}
+
+pub fn load(folder: &str) -> (Option<SyntaxSet>, Option<String>) {
+    // Load the grammars in a folder, if there is one, skipping and reporting any that are broken
+    let folder = Path::new(folder);
+    if !folder.is_dir() {
+        return (None, None);
+    }
+    let mut builder = SyntaxSetBuilder::new();
+    let mut error = None;
+    for path in files(folder) {
+        let grammar = match path.extension().and_then(OsStr::to_str) {
+            // Sublime Text grammars are loaded as they are, TextMate ones are converted first
+            Some("sublime-syntax") => fs::read_to_string(&path).ok(),
+            Some("tmLanguage") => Value::from_file(&path)
+                .ok()
+                .and_then(|grammar| sublime(&grammar)),
+            _ => continue,
+        };
+        let name = path.file_stem().and_then(OsStr::to_str);
+        match grammar.map(|grammar| SyntaxDefinition::load_from_str(&grammar, true, name)) {
+            Some(Ok(syntax)) => builder.add(syntax),
+            Some(Err(e)) => {
+                error = error
+                    .or_else(|| Some(format!("Failed to load grammar {}: {e}", path.display())));
+            }
+            None => {
+                error =
+                    error.or_else(|| Some(format!("Failed to load grammar: {}", path.display())));
+            }
+        }
+    }
+    if builder.syntaxes().is_empty() {
+        return (None, error);
+    }
+    (Some(builder.build()), error)
+}
+
+fn files(folder: &Path) -> Vec<PathBuf> {
+    // Find every file in a folder and the folders within it
+    let mut result = vec![];
+    if let Ok(entries) = fs::read_dir(folder) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                result.append(&mut files(&path));
+            } else {
+                result.push(path);
+            }
+        }
+    }
+    result.sort();
+    result
+}
+
+fn kind(stack: &ScopeStack, highlights: &HashMap<String, Style>) -> Option<&'static str> {
+    // Find the highlight for the innermost scope that has one
+    stack.as_slice().iter().rev().find_map(|scope| {
+        let scope = scope.build_string();
+        SCOPES
+            .iter()
+            .filter(|(prefix, kind)| {
+                let within = scope == *prefix || scope.starts_with(&format!("{prefix}."));
+                within && highlights.contains_key(*kind)
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, kind)| *kind)
+    })
+}
+
+fn sublime(grammar: &Value) -> Option<String> {
+    // Write a TextMate grammar as a Sublime Text one, using YAML's JSON-like flow style
+    let grammar = grammar.as_dictionary()?;
+    let scope = grammar.get("scopeName")?.as_string()?;
+    let name = grammar
+        .get("name")
+        .and_then(Value::as_string)
+        .unwrap_or(scope);
+    let extensions: Vec<String> =
+        grammar
+            .get("fileTypes")
+            .and_then(Value::as_array)
+            .map_or(vec![], |types| {
+                types
+                    .iter()
+                    .filter_map(Value::as_string)
+                    .map(quote)
+                    .collect()
+            });
+    let mut contexts = vec![format!(
+        "\"main\": [{}]",
+        rules(grammar.get("patterns")).join(", ")
+    )];
+    if let Some(repository) = grammar.get("repository").and_then(Value::as_dictionary) {
+        for (key, entry) in repository {
+            contexts.push(format!(
+                "{}: [{}]",
+                quote(&context(key)),
+                rule(entry).join(", ")
+            ));
+        }
+    }
+    Some(format!(
+        "{{\"name\": {}, \"scope\": {}, \"file_extensions\": [{}], \"contexts\": {{{}}}}}",
+        quote(name),
+        quote(scope),
+        extensions.join(", "),
+        contexts.join(", ")
+    ))
+}
+
+fn rules(patterns: Option<&Value>) -> Vec<String> {
+    // Convert a list of TextMate rules
+    patterns
+        .and_then(Value::as_array)
+        .map_or(vec![], |patterns| patterns.iter().flat_map(rule).collect())
+}
+
+fn rule(rule: &Value) -> Vec<String> {
+    // Convert a TextMate rule into the Sublime Text rules that do the same thing
+    let Some(rule) = rule.as_dictionary() else {
+        return vec![];
+    };
+    let text = |key| rule.get(key).and_then(Value::as_string);
+    let mut fields = vec![];
+    if let Some(include) = text("include") {
+        let target = match include {
+            "$self" | "$base" => "main".to_string(),
+            _ => include
+                .strip_prefix('#')
+                .map_or_else(|| format!("scope:{include}"), context),
+        };
+        fields.push(format!("\"include\": {}", quote(&target)));
+    } else if let Some(pattern) = text("match") {
+        fields.push(format!("\"match\": {}", quote(pattern)));
+        if let Some(name) = text("name") {
+            fields.push(format!("\"scope\": {}", quote(name)));
+        }
+        fields.extend(captures(rule.get("captures")));
+    } else if let Some(begin) = text("begin") {
+        // Push a context holding what can appear between the start and the end
+        fields.push(format!("\"match\": {}", quote(begin)));
+        fields.extend(captures(rule.get("beginCaptures").or(rule.get("captures"))));
+        let end = text("end").map_or_else(
+            || format!("^(?!{})", text("while").unwrap_or("")),
+            str::to_string,
+        );
+        let mut pop = vec![format!("\"match\": {}", quote(&end))];
+        pop.extend(captures(rule.get("endCaptures").or(rule.get("captures"))));
+        pop.push("\"pop\": true".to_string());
+        let pop = format!("{{{}}}", pop.join(", "));
+        let mut inside = vec![];
+        if let Some(name) = text("name") {
+            inside.push(format!("{{\"meta_scope\": {}}}", quote(name)));
+        }
+        if let Some(name) = text("contentName") {
+            inside.push(format!("{{\"meta_content_scope\": {}}}", quote(name)));
+        }
+        let last = rule.get("applyEndPatternLast").is_some_and(|last| {
+            last.as_boolean() == Some(true) || last.as_signed_integer() == Some(1)
+        });
+        if !last {
+            inside.push(pop.clone());
+        }
+        inside.extend(rules(rule.get("patterns")));
+        if last {
+            inside.push(pop);
+        }
+        fields.push(format!("\"push\": [{}]", inside.join(", ")));
+    } else {
+        // Rules that only group other rules together
+        return rules(rule.get("patterns"));
+    }
+    vec![format!("{{{}}}", fields.join(", "))]
+}
+
+fn captures(captures: Option<&Value>) -> Option<String> {
+    // Convert the scopes given to the groups of a match
+    let captures = captures?.as_dictionary()?;
+    let scopes: Vec<String> = captures
+        .iter()
+        .filter_map(|(group, capture)| {
+            let group: usize = group.parse().ok()?;
+            let name = capture.as_dictionary()?.get("name")?.as_string()?;
+            Some(format!("{}: {}", group, quote(name)))
+        })
+        .collect();
+    Some(format!("\"captures\": {{{}}}", scopes.join(", ")))
+}
+
+fn context(key: &str) -> String {
+    // Name the context for an entry in a grammar's repository, keeping clear of special names
+    match key {
+        "main" | "prototype" => format!("{key}_"),
+        _ => key.to_string(),
+    }
+}
+
+fn quote(text: &str) -> String {
+    // Write text as a double quoted string
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(result, "\\u{:04x}", c as u32);
+            }
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
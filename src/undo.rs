@@ -1,17 +1,19 @@
 // Undo.rs - Utilities for undoing, redoing and storing events
-use crate::{Position, Row};
+use crate::Position;
 
 // Event enum to store the types of events that occur
 #[derive(Debug, Clone)]
 pub enum Event {
-    InsertTab(Position),                   // Insert Tab
-    InsertMid(Position, char),             // Insert character
-    BackspaceStart(Position),              // Delete from start
-    BackspaceMid(Position, char),          // Delete from middle
-    ReturnStart(Position),                 // Return key in the middle of line
-    ReturnMid(Position, usize),            // Return from middle of the line
-    ReturnEnd(Position),                   // Return on the end of line
-    UpdateLine(usize, Box<Row>, Box<Row>), // For holding entire line updates
+    InsertTab(Position),               // Insert Tab
+    InsertMid(Position, char),         // Insert character
+    BackspaceStart(Position),          // Delete from start
+    BackspaceMid(Position, char),      // Delete from middle
+    ReturnStart(Position),             // Return key in the middle of line
+    ReturnMid(Position, usize),        // Return from middle of the line
+    ReturnEnd(Position),               // Return on the end of line
+    UpdateLine(usize, String, String), // For holding entire line updates
+    InsertText(Position, String),      // Insert a block of text
+    DeleteText(Position, String),      // Delete a block of text
 }
 
 // A struct for holding all the events taken by the user
@@ -45,6 +47,17 @@ impl EventStack {
         // Empty the stack
         self.history.clear();
     }
+    pub fn len(&self) -> usize {
+        // Get the amount of patches in the history
+        self.history.len()
+    }
+    pub fn squash(&mut self, from: usize) {
+        // Merge the patches committed since a point in the history into one
+        if from + 1 < self.history.len() {
+            let patch = self.history.drain(from..).rev().flatten().collect();
+            self.history.push(patch);
+        }
+    }
     pub fn commit(&mut self) {
         // Commit patch to history
         if !self.current_patch.is_empty() {
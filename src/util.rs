@@ -1,6 +1,8 @@
 // Util.rs - Utilities for the rest of the program
 use crate::{Position, Row};
 use regex::Regex;
+use std::env;
+use std::path::{Path, PathBuf};
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
@@ -73,11 +75,10 @@ pub fn is_ahead(current: &Position, position: &Position) -> bool {
     }
 }
 
-pub fn raw_to_grapheme(x: usize, string: &str) -> usize {
+pub fn raw_to_grapheme(x: usize, row: &Row) -> usize {
     // Convert raw cursor position to grapheme cursor position
     let mut graphemes = 0;
-    let current = Row::from(string);
-    let jumps = current.get_jumps();
+    let jumps = row.get_jumps();
     let mut counter = 0;
     for (mut counter2, i) in jumps.into_iter().enumerate() {
         if counter == x {
@@ -89,3 +90,48 @@ pub fn raw_to_grapheme(x: usize, string: &str) -> usize {
     }
     graphemes
 }
+
+pub fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    // Find how many columns a grapheme takes up, with tabs reaching to the next tab stop
+    if grapheme == "\t" {
+        let tab_width = tab_width.max(1);
+        tab_width - column % tab_width
+    } else {
+        UnicodeWidthStr::width(grapheme)
+    }
+}
+
+pub fn column_after(text: &str, column: usize, tab_width: usize) -> usize {
+    // Find the column that some text reaches when it starts at a column
+    text.graphemes(true).fold(column, |column, grapheme| {
+        column + grapheme_width(grapheme, column, tab_width)
+    })
+}
+
+pub fn text_end(start: Position, text: &str, tab_width: usize) -> Position {
+    // Find the position at the end of some text inserted at a position
+    let mut lines = text.split('\n');
+    let first = lines.next().unwrap_or_default();
+    match lines.next_back() {
+        Some(last) => Position {
+            x: column_after(last, 0, tab_width),
+            y: start.y + text.matches('\n').count(),
+        },
+        None => Position {
+            x: column_after(first, start.x, tab_width),
+            y: start.y,
+        },
+    }
+}
+
+pub fn absolute(path: &Path) -> PathBuf {
+    // Find the full path of a file, which may not exist yet
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let path = env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path));
+    match (path.parent().map(Path::canonicalize), path.file_name()) {
+        (Some(Ok(dir)), Some(name)) => dir.join(name),
+        _ => path,
+    }
+}
@@ -0,0 +1,149 @@
+// Watch.rs - For noticing when other programs write to the files that are open
+use crate::util::absolute;
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "linux")]
+pub use inotify::Watcher;
+#[cfg(not(target_os = "linux"))]
+pub use poll::Watcher;
+
+fn locate(path: &str) -> Option<PathBuf> {
+    // Find the full path of a document, if it has one
+    if path.is_empty() {
+        None
+    } else {
+        Some(absolute(Path::new(path)))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use std::ffi::{CString, OsStr};
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    // The events that mean a file was written, either in place or by renaming another over it
+    const EVENTS: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+
+    // Watches the folders holding the open files, as saving by renaming replaces the file itself
+    pub struct Watcher {
+        fd: i32,                        // The inotify instance, or -1 when it couldn't be made
+        folders: HashMap<i32, PathBuf>, // The folder that each watch is on
+    }
+
+    impl Watcher {
+        pub fn new() -> Self {
+            // Start an inotify instance that can be read without waiting
+            Self {
+                fd: unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) },
+                folders: HashMap::new(),
+            }
+        }
+        pub fn watch(&mut self, path: &str) {
+            // Start watching the folder that a file is in
+            let Some(folder) = super::locate(path).and_then(|p| p.parent().map(PathBuf::from))
+            else {
+                return;
+            };
+            if self.fd < 0 || self.folders.values().any(|f| *f == folder) {
+                return;
+            }
+            if let Ok(name) = CString::new(folder.as_os_str().as_bytes()) {
+                let wd = unsafe { libc::inotify_add_watch(self.fd, name.as_ptr(), EVENTS) };
+                if wd >= 0 {
+                    self.folders.insert(wd, folder);
+                }
+            }
+        }
+        pub fn changed(&mut self) -> Vec<PathBuf> {
+            // Find the files that were written to since this was last asked
+            let mut result = vec![];
+            let mut buffer = [0_u8; 4096];
+            loop {
+                let read = unsafe { libc::read(self.fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+                let Ok(read) = usize::try_from(read) else {
+                    break;
+                };
+                if read == 0 {
+                    break;
+                }
+                let mut i = 0;
+                while i + mem::size_of::<libc::inotify_event>() <= read {
+                    let event: libc::inotify_event = unsafe {
+                        buffer
+                            .as_ptr()
+                            .add(i)
+                            .cast::<libc::inotify_event>()
+                            .read_unaligned()
+                    };
+                    let start = i + mem::size_of::<libc::inotify_event>();
+                    let end = (start + event.len as usize).min(read);
+                    let name = &buffer[start..end];
+                    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                    if let Some(folder) = self.folders.get(&event.wd) {
+                        result.push(folder.join(OsStr::from_bytes(name)));
+                    }
+                    i = end;
+                }
+            }
+            result
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            // Stop watching the folders
+            if self.fd >= 0 {
+                unsafe { libc::close(self.fd) };
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod poll {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    // Checks when each open file was last written to
+    pub struct Watcher {
+        files: Vec<(PathBuf, Option<SystemTime>)>, // Each file, with when it was last written to
+    }
+
+    impl Watcher {
+        pub fn new() -> Self {
+            // Start with nothing to watch
+            Self { files: vec![] }
+        }
+        pub fn watch(&mut self, path: &str) {
+            // Start checking a file for changes
+            if let Some(path) = super::locate(path) {
+                if !self.files.iter().any(|(p, _)| *p == path) {
+                    let modified = modified(&path);
+                    self.files.push((path, modified));
+                }
+            }
+        }
+        pub fn changed(&mut self) -> Vec<PathBuf> {
+            // Find the files that were written to since this was last asked
+            let mut result = vec![];
+            for (path, last) in &mut self.files {
+                let now = modified(path);
+                if now != *last {
+                    *last = now;
+                    result.push(path.clone());
+                }
+            }
+            result
+        }
+    }
+
+    fn modified(path: &PathBuf) -> Option<SystemTime> {
+        // Find when a file was last written to
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+}